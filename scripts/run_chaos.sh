@@ -20,7 +20,7 @@ function run_supervisor() {
     ulimit -c unlimited
     ulimit -n 102400
     setsid ${BASE_DIR}/target/debug/engula-supervisor \
-        --config ${BASE_DIR}/chaos-config \
+        --config ${BASE_DIR}/chaos-config run \
         >${BASE_DIR}/log 2>&1 &
 }
 
@@ -0,0 +1,255 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use serde_json::json;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    base::{ErrorStats, ExecCtx, Reader, Writers},
+    control::Command,
+    log::LogFilter,
+    metrics,
+};
+
+/// A minimal HTTP server to inspect and control a running supervisor:
+///
+/// - `GET /status`, the step and errors of each writer, and the lag and errors of each reader.
+/// - `GET /metrics`, the metrics of the writers and readers in the format of Prometheus.
+/// - `POST /pause`, `POST /resume` and `POST /stop`, the same as the commands from stdin.
+/// - `GET /log` and `PUT /log?filter=<filter>`, the filter of the log, see [`LogFilter::set`].
+pub struct AdminServer {
+    listener: TcpListener,
+    log_filter: LogFilter,
+    writers: Writers,
+    readers: Vec<Arc<dyn Reader>>,
+    paused: Arc<AtomicBool>,
+    sender: mpsc::UnboundedSender<Command>,
+}
+
+impl AdminServer {
+    pub async fn bind(
+        addr: &str,
+        writers: Writers,
+        readers: Vec<Arc<dyn Reader>>,
+        paused: Arc<AtomicBool>,
+        sender: mpsc::UnboundedSender<Command>,
+        log_filter: LogFilter,
+    ) -> Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        info!("admin server listens on {}", listener.local_addr()?);
+        Ok(AdminServer {
+            listener,
+            log_filter,
+            writers,
+            readers,
+            paused,
+            sender,
+        })
+    }
+
+    async fn serve(&self, mut stream: TcpStream) -> Result<()> {
+        // The requests have no body, so the head is read only.
+        let mut buf = vec![0; 4096];
+        let mut len = 0;
+        while !buf[..len].windows(4).any(|w| w == b"\r\n\r\n") {
+            if len == buf.len() {
+                anyhow::bail!("the request head is too large");
+            }
+            let n = stream.read(&mut buf[len..]).await?;
+            if n == 0 {
+                anyhow::bail!("the connection is closed before the request is received");
+            }
+            len += n;
+        }
+        let head = String::from_utf8_lossy(&buf[..len]);
+        let mut parts = head.lines().next().unwrap_or_default().split_whitespace();
+        let method = parts.next().unwrap_or_default();
+        let target = parts.next().unwrap_or_default();
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let (status, body) = match (method, path) {
+            ("GET", "/status") => ("200 OK", self.status().await),
+            ("GET", "/metrics") => {
+                let writers = self.writers.lock().unwrap().clone();
+                let metrics = metrics::collect(&writers, &self.readers);
+                let body = metrics::render_prometheus(&metrics);
+                return respond(&mut stream, "200 OK", "text/plain; version=0.0.4", &body).await;
+            }
+            ("POST", "/pause") => self.send(Command::Pause),
+            ("POST", "/resume") => self.send(Command::Resume),
+            ("POST", "/stop") => self.send(Command::Stop),
+            ("GET", "/log") => ("200 OK", json!({ "filter": self.log_filter.get() })),
+            ("PUT", "/log") => self.set_log_filter(query),
+            _ => ("404 Not Found", json!({ "error": "not found" })),
+        };
+        respond(&mut stream, status, "application/json", &body.to_string()).await
+    }
+
+    fn send(&self, cmd: Command) -> (&'static str, serde_json::Value) {
+        info!("admin server receive command {:?}", cmd);
+        match self.sender.send(cmd) {
+            Ok(()) => ("200 OK", json!({ "ok": true })),
+            Err(_) => (
+                "503 Service Unavailable",
+                json!({ "error": "the run is stopping" }),
+            ),
+        }
+    }
+
+    fn set_log_filter(&self, query: &str) -> (&'static str, serde_json::Value) {
+        let filter = query
+            .split('&')
+            .find_map(|kv| kv.strip_prefix("filter="))
+            .map(percent_decode)
+            .unwrap_or_default();
+        match self.log_filter.set(&filter) {
+            Ok(()) => {
+                info!("admin server set log filter {}", filter);
+                ("200 OK", json!({ "filter": filter }))
+            }
+            Err(e) => (
+                "400 Bad Request",
+                json!({ "error": format!("invalid log filter {:?}: {}", filter, e) }),
+            ),
+        }
+    }
+
+    async fn status(&self) -> serde_json::Value {
+        let writers = self.writers.lock().unwrap().clone();
+        let writer_status = writers
+            .iter()
+            .map(|w| {
+                let stats = w.error_stats();
+                json!({
+                    "index": w.index(),
+                    "step": w.current_step(),
+                    "verify_lag": w.verify_progress().lag(w.current_step()),
+                    "succeeds": stats.succeeds(),
+                    "timeouts": stats.timeouts(),
+                    "errors": stats.errors(),
+                    "classes": classes(stats),
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut reader_status = vec![];
+        for reader in &self.readers {
+            // The lag behind each traced writer.
+            let mut lags = serde_json::Map::new();
+            for index in reader.traced_writers().await {
+                if let Some(w) = writers.iter().find(|w| w.index() == index) {
+                    let verified_step = w
+                        .verify_progress()
+                        .verified_step(reader.index())
+                        .unwrap_or_default();
+                    let lag = w.current_step().saturating_sub(verified_step);
+                    lags.insert(index.to_string(), json!(lag));
+                }
+            }
+            let stats = reader.error_stats();
+            reader_status.push(json!({
+                "index": reader.index(),
+                "lags": lags,
+                "succeeds": stats.succeeds(),
+                "timeouts": stats.timeouts(),
+                "errors": stats.errors(),
+                "classes": classes(stats),
+            }));
+        }
+        json!({
+            "paused": self.paused.load(Ordering::Acquire),
+            "writers": writer_status,
+            "readers": reader_status,
+        })
+    }
+}
+
+/// Decode the `%XX` escapes of a query value, the invalid ones are kept as they are.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b'+', _) => {
+                out.push(b' ');
+                i += 1;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+async fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// The number of failures of each class, by the name of class.
+fn classes(stats: &ErrorStats) -> serde_json::Value {
+    stats
+        .breakdown()
+        .into_iter()
+        .map(|(class, num)| (class.as_str().to_owned(), json!(num)))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+#[super::async_trait]
+impl super::base::Task for AdminServer {
+    async fn run(&self, mut ctx: ExecCtx) {
+        loop {
+            tokio::select! {
+                accepted = self.listener.accept() => match accepted {
+                    Ok((stream, peer)) => {
+                        // The requests are served one by one, they are rare and cheap.
+                        match tokio::time::timeout(Duration::from_secs(5), self.serve(stream)).await {
+                            Ok(Ok(())) => {}
+                            Ok(Err(e)) => warn!("admin server serve {}: {}", peer, e),
+                            Err(_) => warn!("admin server serve {}: timeout", peer),
+                        }
+                    }
+                    Err(e) => error!("admin server accept: {}", e),
+                },
+                result = ctx.wait_until_timeout_or_shutdown(Duration::from_secs(1)) => {
+                    if result.is_none() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+
+use anyhow::Result;
+use engula_client::Collection;
+use tracing::{error, info};
+
+use crate::{
+    base::{with_timeout, Config, Failure, FailureKind, Seeds},
+    gen::{Generator, NextOp},
+    value::Value,
+};
+
+/// The value of a key expected by the audit, `None` means the key is deleted.
+type ExpectedValue = Option<(usize, Vec<u8>)>;
+
+/// Verify the contents of a collection written by a previous run, by replaying the generators of
+/// its writers without writing anything.
+pub struct Auditor {
+    seeds: Seeds,
+    generator: Config,
+    collection: Collection,
+    op_timeout: Duration,
+}
+
+impl Auditor {
+    pub fn new(
+        seeds: Seeds,
+        generator: Config,
+        collection: Collection,
+        op_timeout: Duration,
+    ) -> Self {
+        Auditor {
+            seeds,
+            generator,
+            collection,
+            op_timeout,
+        }
+    }
+
+    /// Verify the final state of writers, each is given as the index of the writer and the last
+    /// step executed by it.
+    pub async fn audit(&self, writers: &[(usize, usize)]) -> Result<()> {
+        let mut mismatches = 0;
+        for (idx, step) in writers {
+            mismatches += self.audit_writer(*idx, *step).await?;
+        }
+        if mismatches > 0 {
+            anyhow::bail!(Failure::new(
+                FailureKind::Verification,
+                format!("audit found {} mismatched keys", mismatches)
+            ));
+        }
+        info!("audit {} writers success", writers.len());
+        Ok(())
+    }
+
+    async fn audit_writer(&self, idx: usize, step: usize) -> Result<usize> {
+        let seed = self.seeds.writer(idx);
+        let mut gen = Generator::new(seed, idx as u64, self.generator.clone());
+        let mut expected: BTreeMap<Vec<u8>, ExpectedValue> = BTreeMap::default();
+        // The op of the last step might not be acknowledged, so the value before it is accepted
+        // too.
+        let mut last_op: Option<(Vec<u8>, ExpectedValue)> = None;
+        for s in 1..=step {
+            let (key, value) = match gen.next_op() {
+                NextOp::Put { key, value } => (key, Some((s, value))),
+                NextOp::Delete { key } => (key, None),
+            };
+            if s == step {
+                last_op = Some((key.clone(), expected.get(&key).cloned().flatten()));
+            }
+            expected.insert(key, value);
+        }
+
+        let mut mismatches = 0;
+        for (key, expect) in &expected {
+            let got = self.get(key).await?;
+            if matches(idx, &got, expect) {
+                continue;
+            }
+            if let Some((last_key, prev)) = &last_op {
+                if last_key == key && matches(idx, &got, prev) {
+                    continue;
+                }
+            }
+            mismatches += 1;
+            error!(
+                "audit key {} of writer {}, expect {}, but got {}",
+                String::from_utf8_lossy(key),
+                idx,
+                describe(expect),
+                got.map(|v| format!("value of writer {} step {}", v.writer(), v.index()))
+                    .unwrap_or_else(|| "nothing".to_owned()),
+            );
+        }
+        info!(
+            "audit writer {} at step {}, {} keys, {} mismatches",
+            idx,
+            step,
+            expected.len(),
+            mismatches
+        );
+        Ok(mismatches)
+    }
+
+    /// Delete every key written by the writers until their last steps, so the namespace of the
+    /// run is left empty.
+    pub async fn clean(&self, writers: &[(usize, usize)]) -> Result<()> {
+        for (idx, step) in writers {
+            let seed = self.seeds.writer(*idx);
+            let mut gen = Generator::new(seed, *idx as u64, self.generator.clone());
+            let keys = (0..*step)
+                .map(|_| gen.next_op().key().to_owned())
+                .collect::<BTreeSet<_>>();
+            for key in &keys {
+                self.delete(key).await?;
+            }
+            info!(
+                "clean {} keys of writer {} at step {}",
+                keys.len(),
+                idx,
+                step
+            );
+        }
+        Ok(())
+    }
+
+    async fn delete(&self, key: &[u8]) -> Result<()> {
+        let mut last_err = None;
+        for _ in 0..120 {
+            match with_timeout(self.op_timeout, self.collection.delete(key.to_owned())).await {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    error!("clean delete key {}: {}", String::from_utf8_lossy(key), e);
+                    last_err = Some(e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+        Err(last_err.unwrap())
+    }
+
+    async fn get(&self, key: &[u8]) -> Result<Option<Value>> {
+        let mut last_err = None;
+        for _ in 0..120 {
+            match with_timeout(self.op_timeout, self.collection.get(key.to_owned())).await {
+                Ok(value) => {
+                    return value.map(|v| Value::decode(&v)).transpose().map_err(|e| {
+                        Failure::new(
+                            FailureKind::Verification,
+                            format!("audit key {}: {}", String::from_utf8_lossy(key), e),
+                        )
+                        .into()
+                    })
+                }
+                Err(e) => {
+                    error!("audit get key {}: {}", String::from_utf8_lossy(key), e);
+                    last_err = Some(e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+        Err(last_err.unwrap())
+    }
+}
+
+fn matches(writer: usize, got: &Option<Value>, expect: &ExpectedValue) -> bool {
+    match (got, expect) {
+        (None, None) => true,
+        (Some(v), Some((step, value))) => {
+            v.writer() == writer && v.index() == *step && v.value() == *value
+        }
+        _ => false,
+    }
+}
+
+fn describe(expect: &ExpectedValue) -> String {
+    match expect {
+        Some((step, _)) => format!("value of step {}", step),
+        None => "deleted".to_owned(),
+    }
+}
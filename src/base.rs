@@ -1,29 +1,333 @@
-use std::time::Duration;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 
+use engula_client::{AppError, Collection, Partition};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
-use tokio::sync::broadcast;
+use tokio::{sync::broadcast, time::Instant};
+
+use crate::{
+    chaos::FaultLog,
+    gen::Snapshot,
+    histogram::{HistogramSnapshot, OpLatencies},
+    history::History,
+    reader::TrackerSnapshot,
+};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Config {
+    /// Delete all alive keys after every `cleanup_interval` random ops.
+    pub cleanup_interval: Option<usize>,
+    /// The probability that an op is sent again after it is acknowledged.
+    pub duplicate_ratio: f64,
+    /// Only generate the keys belonging to this hash slot, to direct all load at one shard.
+    pub affinity_slot: Option<u32>,
+    /// The number of hash slots of the collection, it is filled from the partition of the app
+    /// config, and it is zero for the range partition.
+    #[serde(skip)]
+    pub hash_slots: u32,
+    /// The run id of the app config, the keys are prefixed by `<run_id>/` so the runs sharing a
+    /// collection never collide, they are not prefixed if it is empty.
+    #[serde(skip)]
+    pub namespace: String,
+    // The ranges are serialized as tables, so they must be placed after the plain values.
+    #[serde(deserialize_with = "deserialize_size_range")]
     pub key_range: std::ops::Range<usize>,
+    #[serde(deserialize_with = "deserialize_size_range")]
     pub value_range: std::ops::Range<usize>,
 }
 
+/// The seeds of the generators of writers.
+#[derive(Clone, Debug)]
+pub struct Seeds {
+    base: u64,
+    /// The seeds pinned for the writers by their indexes.
+    pinned: Vec<u64>,
+}
+
+impl Seeds {
+    pub fn new(base: u64, pinned: Vec<u64>) -> Self {
+        Seeds { base, pinned }
+    }
+
+    /// Return the seed of the writer, it is `base + idx` unless it is pinned.
+    pub fn writer(&self, idx: usize) -> u64 {
+        self.pinned
+            .get(idx)
+            .copied()
+            .unwrap_or_else(|| self.base.wrapping_add(idx as u64))
+    }
+}
+
+/// The partition of the collections.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PartitionConfig {
+    /// The keys are distributed to `slots` shards by `crc32(key) % slots`.
+    Hash { slots: u32 },
+    /// The keys are distributed by ranges, the generated keys are alphanumeric so they are
+    /// spread over the ranges evenly.
+    Range,
+}
+
+impl PartitionConfig {
+    pub fn hash_slots(&self) -> Option<u32> {
+        match self {
+            PartitionConfig::Hash { slots } => Some(*slots),
+            PartitionConfig::Range => None,
+        }
+    }
+
+    pub fn to_partition(self) -> Partition {
+        match self {
+            PartitionConfig::Hash { slots } => Partition::Hash { slots },
+            PartitionConfig::Range => Partition::Range,
+        }
+    }
+}
+
+/// The options of writer, which are not a part of the generator.
+#[derive(Clone, Debug)]
+pub struct WriterOptions {
+    pub op_timeout: Duration,
+    /// The attempts of ops slower than it are logged, with their keys.
+    pub slow_op_threshold: Option<Duration>,
+    /// The latencies are not recorded before the warm-up deadline.
+    pub warmup_deadline: Instant,
+    /// The think time and the ratio of read-your-writes, see [`TunableOptions`].
+    pub tunables: Arc<Tunables>,
+    /// Each attempt to execute an op is published to the history.
+    pub history: History,
+    /// The writer is throttled while the slowest reader lags more steps behind it.
+    pub max_verify_lag_steps: Option<usize>,
+    /// The writer stops executing ops once it reaches the step.
+    pub max_steps: Option<usize>,
+    pub relaxation: Arc<Relaxation>,
+    /// The writers do not execute the next op while it is set.
+    pub paused: Arc<AtomicBool>,
+}
+
+impl WriterOptions {
+    #[inline]
+    pub fn effective_op_timeout(&self) -> Duration {
+        self.relaxation.op_timeout(self.op_timeout)
+    }
+}
+
+/// The options which could be changed at runtime by reloading the config, they affect neither
+/// the generated ops nor the verified results.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TunableOptions {
+    /// The fraction of puts that will be read back immediately after acknowledged.
+    pub read_your_writes_ratio: f64,
+    /// The duration to wait after each acknowledged op, the writer runs in a tight loop if it is
+    /// not specified.
+    pub think_time: Option<ThinkTime>,
+    /// The interval between two wakeups of the verification of a writer.
+    pub verify_interval: Duration,
+    /// The number of ops verified in each wakeup.
+    pub ops_per_wakeup: usize,
+    /// The interval of wakeups once the verification catches up with the writer, it is used
+    /// only if it is longer than `verify_interval`.
+    pub idle_interval: Duration,
+}
+
+/// The tunable options shared by all writers and readers.
+#[derive(Debug)]
+pub struct Tunables {
+    options: Mutex<TunableOptions>,
+}
+
+impl Tunables {
+    pub fn new(options: TunableOptions) -> Self {
+        Tunables {
+            options: Mutex::new(options),
+        }
+    }
+
+    pub fn get(&self) -> TunableOptions {
+        self.options.lock().unwrap().clone()
+    }
+
+    pub fn set(&self, options: TunableOptions) {
+        *self.options.lock().unwrap() = options;
+    }
+}
+
+/// The distribution of the think time between two ops of a writer.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ThinkTime {
+    Fixed { ms: u64 },
+    Uniform { min_ms: u64, max_ms: u64 },
+    Exponential { mean_ms: u64 },
+}
+
+impl ThinkTime {
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Duration {
+        match self {
+            ThinkTime::Fixed { ms } => Duration::from_millis(*ms),
+            ThinkTime::Uniform { min_ms, max_ms } => {
+                Duration::from_millis(rng.gen_range(*min_ms..=(*max_ms).max(*min_ms)))
+            }
+            ThinkTime::Exponential { mean_ms } => {
+                // Inverse transform sampling, `1 - u` is in (0, 1] so the logarithm is finite.
+                let u: f64 = rng.gen();
+                let ms = -(*mean_ms as f64) * (1.0 - u).ln();
+                Duration::from_secs_f64(ms / 1000.0)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ReaderOptions {
+    pub op_timeout: Duration,
+    /// The reads slower than it are logged, with their keys.
+    pub slow_op_threshold: Option<Duration>,
+    /// The staled reads are retried instead of failing the run before the warm-up deadline.
+    pub warmup_deadline: Instant,
+    /// The number of steps a read value could lag behind the verified step.
+    pub max_lag_steps: usize,
+    /// The duration a staled read is retried before failing the run, the run fails at the first
+    /// staled read if it is zero.
+    pub max_lag: Duration,
+    /// The op is retried until this deadline before failing the run.
+    pub verify_deadline: Duration,
+    /// Verify one of every `sample_interval` ops, the model still advances for all ops.
+    pub sample_interval: usize,
+    /// The bound of the keys expected to be put or deleted by the later ops of a writer, the
+    /// reads are skipped until they are resolved once it is reached.
+    pub max_expected_keys: Option<usize>,
+    /// The duration a key deleted by the cleanup phase could be still visible, it is checked
+    /// again periodically before failing the run.
+    pub delete_grace: Duration,
+    /// The cadence of the verification, see [`TunableOptions`].
+    pub tunables: Arc<Tunables>,
+    /// Drop and respawn the verification tasks from their snapshots periodically, to test that
+    /// the verification could be resumed after the supervisor restarts.
+    pub crash_interval: Option<Duration>,
+    /// The interval between two lookups of the keys never written by a writer, which must not
+    /// exist.
+    pub negative_lookup_interval: Option<Duration>,
+    /// Each read of the data collection is published to the history.
+    pub history: History,
+    pub relaxation: Arc<Relaxation>,
+    /// The windows of the injected faults, the reads failed during a window and `verify_deadline`
+    /// after it are retried instead of failing the run.
+    pub fault_log: Arc<FaultLog>,
+}
+
+impl ReaderOptions {
+    #[inline]
+    pub fn effective_op_timeout(&self) -> Duration {
+        self.relaxation.op_timeout(self.op_timeout)
+    }
+
+    /// Return whether the value written at `written_step` is too old for `accessed_step`.
+    #[inline]
+    pub fn is_stale(&self, written_step: usize, accessed_step: usize) -> bool {
+        written_step + self.max_lag_steps < accessed_step
+    }
+
+    /// Return whether a staled read could be retried, `stale_since` records the first time the
+    /// staled read is observed.
+    pub fn tolerate_stale(&self, stale_since: &mut Option<Instant>) -> bool {
+        let now = Instant::now();
+        let stale_since = *stale_since.get_or_insert(now);
+        now < self.warmup_deadline
+            || now.duration_since(stale_since) < self.relaxation.max_lag(self.max_lag)
+    }
+}
+
+/// The thresholds of verification during a fault, so the injected latency does not fail the
+/// run. They only widen the configured thresholds.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RelaxConfig {
+    #[serde(default, deserialize_with = "deserialize_opt_millis")]
+    pub op_timeout_ms: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_opt_millis")]
+    pub max_lag_ms: Option<u64>,
+    /// Retry the failed ops of writers without limit, eg the writes are expected to fail while
+    /// the disk is full.
+    pub tolerate_write_failures: Option<bool>,
+}
+
+/// The thresholds relaxed by the active faults, each is identified by its fault window.
+#[derive(Default, Debug)]
+pub struct Relaxation {
+    active: Mutex<Vec<(usize, RelaxConfig)>>,
+}
+
+impl Relaxation {
+    pub fn relax(&self, id: usize, cfg: RelaxConfig) {
+        self.active.lock().unwrap().push((id, cfg));
+    }
+
+    pub fn restore(&self, id: usize) {
+        self.active.lock().unwrap().retain(|(i, _)| *i != id);
+    }
+
+    pub fn op_timeout(&self, configured: Duration) -> Duration {
+        self.widen(configured, |cfg| cfg.op_timeout_ms)
+    }
+
+    pub fn tolerate_write_failures(&self) -> bool {
+        self.active
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(_, cfg)| cfg.tolerate_write_failures.unwrap_or_default())
+    }
+
+    pub fn max_lag(&self, configured: Duration) -> Duration {
+        self.widen(configured, |cfg| cfg.max_lag_ms)
+    }
+
+    fn widen<F: Fn(&RelaxConfig) -> Option<u64>>(&self, configured: Duration, f: F) -> Duration {
+        self.active
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(_, cfg)| f(cfg))
+            .map(Duration::from_millis)
+            .fold(configured, Duration::max)
+    }
+}
+
 pub struct ExecCtx {
     shutdown: (broadcast::Sender<()>, broadcast::Receiver<()>),
+    /// Only the context created by [`ExecCtx::new`] broadcasts shutdown when it is dropped, so
+    /// an exited (or aborted) task never stops the others.
+    owner: bool,
+    is_shutdown: bool,
 }
 
 impl ExecCtx {
     pub fn new() -> Self {
         ExecCtx {
             shutdown: broadcast::channel(1),
+            owner: true,
+            is_shutdown: false,
         }
     }
 
     /// Wait until timeout or shutdown.
     pub async fn wait_until_timeout_or_shutdown(&mut self, duration: Duration) -> Option<()> {
+        if self.is_shutdown {
+            return None;
+        }
         tokio::select! {
             _ = self.shutdown.1.recv() => {
+                self.is_shutdown = true;
                 None
             }
             _ = tokio::time::sleep(duration) => {
@@ -31,11 +335,24 @@ impl ExecCtx {
             }
         }
     }
+
+    /// Return whether the shutdown has been broadcasted, without waiting.
+    pub fn is_shutdown(&mut self) -> bool {
+        if !self.is_shutdown {
+            self.is_shutdown = !matches!(
+                self.shutdown.1.try_recv(),
+                Err(broadcast::error::TryRecvError::Empty)
+            );
+        }
+        self.is_shutdown
+    }
 }
 
 impl Drop for ExecCtx {
     fn drop(&mut self) {
-        self.shutdown.0.send(()).unwrap_or_default();
+        if self.owner {
+            self.shutdown.0.send(()).unwrap_or_default();
+        }
     }
 }
 
@@ -43,7 +360,459 @@ impl Clone for ExecCtx {
     fn clone(&self) -> Self {
         let tx = self.shutdown.0.clone();
         let rx = tx.subscribe();
-        ExecCtx { shutdown: (tx, rx) }
+        ExecCtx {
+            shutdown: (tx, rx),
+            owner: false,
+            is_shutdown: self.is_shutdown,
+        }
+    }
+}
+
+/// An error indicates that an operation is not finished before the deadline.
+#[derive(Debug)]
+pub struct Timeout(pub Duration);
+
+impl Display for Timeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "operation timeout after {:?}", self.0)
+    }
+}
+
+impl std::error::Error for Timeout {}
+
+/// An error indicates that a read observes a value which is not expected yet, eg a staled value
+/// within the tolerance, so the verification is retried.
+#[derive(Debug)]
+pub struct Unexpected(pub String);
+
+impl Display for Unexpected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for Unexpected {}
+
+/// The kind of failure of a run, it decides the exit code of the process, so the automation
+/// could tell the lost data from a malformed config.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureKind {
+    /// A verification or safety check is failed, eg a staled read or a lost key.
+    Verification = 1,
+    /// The cluster is unavailable beyond the tolerance, eg the error budget is exceeded.
+    Availability = 2,
+    /// The supervisor itself is failed, eg it could not connect to the cluster.
+    Internal = 3,
+    /// The config or the arguments are invalid.
+    Config = 4,
+}
+
+/// The kind of the next panic, the panics are verification failures unless it is marked
+/// otherwise by [`FailureKind::mark_panic`].
+static PANIC_KIND: AtomicU8 = AtomicU8::new(FailureKind::Verification as u8);
+
+impl FailureKind {
+    pub fn exit_code(self) -> i32 {
+        self as i32
+    }
+
+    /// Return the kind of an error, the errors which are not a [`Failure`] are internal.
+    pub fn of(err: &anyhow::Error) -> Self {
+        err.chain()
+            .find_map(|e| e.downcast_ref::<Failure>())
+            .map(|f| f.kind)
+            .unwrap_or(FailureKind::Internal)
+    }
+
+    /// Mark the kind of the panic which is about to be raised.
+    pub fn mark_panic(self) {
+        PANIC_KIND.store(self as u8, Ordering::Release);
+    }
+
+    pub fn of_panic() -> Self {
+        match PANIC_KIND.load(Ordering::Acquire) {
+            2 => FailureKind::Availability,
+            3 => FailureKind::Internal,
+            4 => FailureKind::Config,
+            _ => FailureKind::Verification,
+        }
+    }
+}
+
+/// An error with the kind of failure, the message is displayed as it is.
+#[derive(Debug)]
+pub struct Failure {
+    pub kind: FailureKind,
+    pub msg: String,
+}
+
+impl Failure {
+    pub fn new(kind: FailureKind, msg: impl Into<String>) -> Self {
+        Failure {
+            kind,
+            msg: msg.into(),
+        }
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// Await the future until it finishes or the duration expired, the expiration is surfaced as a
+/// [`Timeout`] error so that it could be retried like the others.
+pub async fn with_timeout<T, E, F>(duration: Duration, fut: F) -> anyhow::Result<T>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Into<anyhow::Error>,
+{
+    match tokio::time::timeout(duration, fut).await {
+        Ok(result) => result.map_err(Into::into),
+        Err(_) => Err(Timeout(duration).into()),
+    }
+}
+
+/// Parse a human readable duration, a sequence of numbers with units, eg `500ms`, `30s` or
+/// `1h30m`.
+pub fn parse_duration(s: &str) -> anyhow::Result<Duration> {
+    let mut total = Duration::ZERO;
+    let mut rest = s.trim();
+    if rest.is_empty() {
+        anyhow::bail!("empty duration");
+    }
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let unit_len = rest[digits..]
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len() - digits);
+        let value: u64 = match rest[..digits].parse() {
+            Ok(value) => value,
+            Err(_) => anyhow::bail!("invalid duration {:?}", s),
+        };
+        let unit = match &rest[digits..digits + unit_len] {
+            "ms" => Duration::from_millis(1),
+            "s" => Duration::from_secs(1),
+            "m" => Duration::from_secs(60),
+            "h" => Duration::from_secs(3600),
+            "d" => Duration::from_secs(86400),
+            unit => anyhow::bail!("invalid unit {:?} of duration {:?}", unit, s),
+        };
+        total = u32::try_from(value)
+            .ok()
+            .and_then(|value| unit.checked_mul(value))
+            .and_then(|d| total.checked_add(d))
+            .ok_or_else(|| anyhow::anyhow!("the duration {:?} overflows", s))?;
+        rest = &rest[digits + unit_len..];
+    }
+    Ok(total)
+}
+
+/// Format the duration with the largest unit which represents it exactly.
+pub fn format_duration(d: Duration) -> String {
+    let ms = d.as_millis();
+    for (unit, name) in [
+        (86_400_000, "d"),
+        (3_600_000, "h"),
+        (60_000, "m"),
+        (1000, "s"),
+    ] {
+        if ms > 0 && ms % unit == 0 {
+            return format!("{}{}", ms / unit, name);
+        }
+    }
+    format!("{}ms", ms)
+}
+
+pub fn serialize_duration<S: serde::Serializer>(
+    d: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format_duration(*d))
+}
+
+pub fn deserialize_duration<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Duration, D::Error> {
+    parse_duration(&String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+/// A config field given as a number in the unit of the field, or as a human readable string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumberOrString {
+    Number(u64),
+    String(String),
+}
+
+/// Deserialize a number of milliseconds, or a duration like `1s500ms`, see [`parse_duration`].
+pub fn deserialize_millis<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<u64, D::Error> {
+    match NumberOrString::deserialize(deserializer)? {
+        NumberOrString::Number(ms) => Ok(ms),
+        NumberOrString::String(s) => parse_duration(&s)
+            .map(|d| d.as_millis() as u64)
+            .map_err(serde::de::Error::custom),
+    }
+}
+
+/// Deserialize a number of seconds, or a duration of whole seconds like `2m30s`, see
+/// [`parse_duration`].
+pub fn deserialize_secs<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<u64, D::Error> {
+    match NumberOrString::deserialize(deserializer)? {
+        NumberOrString::Number(secs) => Ok(secs),
+        NumberOrString::String(s) => {
+            let d = parse_duration(&s).map_err(serde::de::Error::custom)?;
+            if d.subsec_nanos() != 0 {
+                return Err(serde::de::Error::custom(format!(
+                    "the duration {:?} is not whole seconds",
+                    s
+                )));
+            }
+            Ok(d.as_secs())
+        }
+    }
+}
+
+pub fn deserialize_opt_millis<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    deserialize_millis(deserializer).map(Some)
+}
+
+pub fn deserialize_opt_secs<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    deserialize_secs(deserializer).map(Some)
+}
+
+/// Parse a human readable size, a number with an optional unit, eg `512`, `4KiB` or `1MB`.
+pub fn parse_size(s: &str) -> anyhow::Result<usize> {
+    let s = s.trim();
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let value: usize = match s[..digits].parse() {
+        Ok(value) => value,
+        Err(_) => anyhow::bail!("invalid size {:?}", s),
+    };
+    let unit = match s[digits..].trim() {
+        "" | "B" => 1,
+        "KB" => 1000,
+        "KiB" => 1 << 10,
+        "MB" => 1000 * 1000,
+        "MiB" => 1 << 20,
+        "GB" => 1000 * 1000 * 1000,
+        "GiB" => 1 << 30,
+        unit => anyhow::bail!("invalid unit {:?} of size {:?}", unit, s),
+    };
+    match value.checked_mul(unit) {
+        Some(size) => Ok(size),
+        None => anyhow::bail!("the size {:?} overflows", s),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SizeRange {
+    Table { start: usize, end: usize },
+    String(String),
+}
+
+/// Deserialize a range of sizes, as a table of `start` and `end`, or a string like
+/// `4KiB..1MiB`, see [`parse_size`].
+pub fn deserialize_size_range<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<std::ops::Range<usize>, D::Error> {
+    match SizeRange::deserialize(deserializer)? {
+        SizeRange::Table { start, end } => Ok(start..end),
+        SizeRange::String(s) => {
+            let (start, end) = s.split_once("..").ok_or_else(|| {
+                serde::de::Error::custom(format!("the range {:?} should be start..end", s))
+            })?;
+            let start = parse_size(start).map_err(serde::de::Error::custom)?;
+            let end = parse_size(end).map_err(serde::de::Error::custom)?;
+            Ok(start..end)
+        }
+    }
+}
+
+/// The class of an error, which decides whether the failed op is retried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The op is not finished before the deadline, of the supervisor or the client.
+    Timeout,
+    /// The connection to the cluster is broken.
+    Transport,
+    /// The request is sent to a stale route, eg the replica is not the leader any more.
+    Routing,
+    /// The database or collection is not found, it is impossible since they are never dropped.
+    NotFound,
+    /// The request is rejected as invalid, which is a bug of the supervisor or the client.
+    ClientBug,
+    /// The other errors reported by the server.
+    Internal,
+    /// The read value is not expected yet by the verification, see [`Unexpected`].
+    Verification,
+}
+
+/// The messages of the errors which are caused by stale routes, the client surfaces them as
+/// internal errors once its retries are exhausted.
+const ROUTING_ERRORS: &[&str] = &[
+    "not leader",
+    "epoch not match",
+    "group not found",
+    "shard not found",
+];
+
+impl ErrorClass {
+    const ALL: [ErrorClass; 7] = [
+        ErrorClass::Timeout,
+        ErrorClass::Transport,
+        ErrorClass::Routing,
+        ErrorClass::NotFound,
+        ErrorClass::ClientBug,
+        ErrorClass::Internal,
+        ErrorClass::Verification,
+    ];
+
+    pub fn of(err: &anyhow::Error) -> Self {
+        if err.is::<Timeout>() {
+            return ErrorClass::Timeout;
+        }
+        if err.is::<Unexpected>() {
+            return ErrorClass::Verification;
+        }
+        match err.downcast_ref::<AppError>() {
+            Some(AppError::DeadlineExceeded(_)) => ErrorClass::Timeout,
+            Some(AppError::Network(_)) => ErrorClass::Transport,
+            Some(AppError::NotFound(_)) => ErrorClass::NotFound,
+            Some(AppError::AlreadyExists(_) | AppError::InvalidArgument(_)) => {
+                ErrorClass::ClientBug
+            }
+            Some(AppError::Internal(e)) => {
+                let msg = e.to_string().to_lowercase();
+                if ROUTING_ERRORS.iter().any(|r| msg.contains(r)) {
+                    ErrorClass::Routing
+                } else {
+                    ErrorClass::Internal
+                }
+            }
+            _ => ErrorClass::Internal,
+        }
+    }
+
+    /// Return whether an op failed with this class might succeed if it is retried.
+    #[inline]
+    pub fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            ErrorClass::Timeout
+                | ErrorClass::Transport
+                | ErrorClass::Routing
+                | ErrorClass::Internal
+                | ErrorClass::Verification
+        )
+    }
+
+    /// The stable name of the class, eg in the structured logs and the admin status.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorClass::Timeout => "timeout",
+            ErrorClass::Transport => "transport",
+            ErrorClass::Routing => "routing",
+            ErrorClass::NotFound => "not_found",
+            ErrorClass::ClientBug => "client_bug",
+            ErrorClass::Internal => "internal",
+            ErrorClass::Verification => "verification",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ErrorClass::Timeout => "timeouts",
+            ErrorClass::Transport => "transport errors",
+            ErrorClass::Routing => "routing errors",
+            ErrorClass::NotFound => "not found errors",
+            ErrorClass::ClientBug => "client bugs",
+            ErrorClass::Internal => "internal errors",
+            ErrorClass::Verification => "unexpected reads",
+        }
+    }
+}
+
+/// The counters of operations, the failures are counted by the class of errors.
+#[derive(Default)]
+pub struct ErrorStats {
+    succeeds: AtomicUsize,
+    classes: [AtomicUsize; ErrorClass::ALL.len()],
+}
+
+impl ErrorStats {
+    #[inline]
+    pub fn record_success(&self) {
+        self.succeeds.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count the error and return its class.
+    pub fn record(&self, err: &anyhow::Error) -> ErrorClass {
+        let class = ErrorClass::of(err);
+        self.classes[class as usize].fetch_add(1, Ordering::Relaxed);
+        class
+    }
+
+    #[inline]
+    pub fn succeeds(&self) -> usize {
+        self.succeeds.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn failures(&self) -> usize {
+        self.timeouts() + self.errors()
+    }
+
+    #[inline]
+    pub fn timeouts(&self) -> usize {
+        self.count(ErrorClass::Timeout)
+    }
+
+    /// Return the number of failures except timeouts.
+    #[inline]
+    pub fn errors(&self) -> usize {
+        ErrorClass::ALL
+            .iter()
+            .filter(|c| **c != ErrorClass::Timeout)
+            .map(|c| self.count(*c))
+            .sum()
+    }
+
+    #[inline]
+    pub fn count(&self, class: ErrorClass) -> usize {
+        self.classes[class as usize].load(Ordering::Relaxed)
+    }
+
+    /// Return the number of failures of each class.
+    pub fn breakdown(&self) -> Vec<(ErrorClass, usize)> {
+        ErrorClass::ALL
+            .iter()
+            .map(|c| (*c, self.count(*c)))
+            .collect()
+    }
+}
+
+impl Display for ErrorStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} succeeds", self.succeeds())?;
+        for class in ErrorClass::ALL {
+            write!(f, ", {} {}", self.count(class), class.name())?;
+        }
+        Ok(())
     }
 }
 
@@ -53,12 +822,84 @@ pub trait Task: Send + Sync {
 }
 
 #[super::async_trait]
-pub trait Reader: Task {}
+pub trait Reader: Task {
+    fn index(&self) -> usize;
+
+    /// Return the counters of the reads issued by the reader.
+    fn error_stats(&self) -> &ErrorStats;
+
+    /// Return the age of the older versions observed by the reader.
+    fn staleness(&self) -> HistogramSnapshot;
+
+    /// Return the latency of the reads issued by the reader.
+    fn get_latency(&self) -> HistogramSnapshot;
+
+    /// Return the verification progress of the traced writers.
+    async fn snapshot(&self) -> Vec<TrackerSnapshot>;
+
+    /// Start tracing a writer, the verification starts from the first step of the writer.
+    async fn add_writer(&self, writer: Arc<dyn Writer>);
+
+    /// Stop tracing a writer, return `None` if the writer is not traced by this reader.
+    async fn remove_writer(&self, writer_index: usize) -> Option<Arc<dyn Writer>>;
+
+    /// Return the indexes of the traced writers.
+    async fn traced_writers(&self) -> Vec<usize>;
+}
+
+/// The steps of a writer verified by each reader tracing it.
+#[derive(Default)]
+pub struct VerifyProgress {
+    verified_steps: Mutex<HashMap<usize, usize>>,
+}
+
+impl VerifyProgress {
+    pub fn update(&self, reader: usize, step: usize) {
+        let mut verified_steps = self.verified_steps.lock().unwrap();
+        let verified_step = verified_steps.entry(reader).or_default();
+        *verified_step = (*verified_step).max(step);
+    }
+
+    pub fn remove(&self, reader: usize) {
+        self.verified_steps.lock().unwrap().remove(&reader);
+    }
+
+    /// Return the step verified by the reader, `None` if the writer is not traced by it.
+    pub fn verified_step(&self, reader: usize) -> Option<usize> {
+        self.verified_steps.lock().unwrap().get(&reader).cloned()
+    }
+
+    /// Return the number of steps the slowest reader lags behind `current_step`, `None` if the
+    /// writer is not traced.
+    pub fn lag(&self, current_step: usize) -> Option<usize> {
+        let verified_steps = self.verified_steps.lock().unwrap();
+        let min_step = verified_steps.values().min()?;
+        Some(current_step.saturating_sub(*min_step))
+    }
+}
+
+/// The writers of a run, which might be changed at runtime.
+pub type Writers = Arc<Mutex<Vec<Arc<dyn Writer>>>>;
+
+/// Return the number of steps the reader lags behind the slowest writer traced by it.
+pub fn reader_lag(reader: usize, writers: &[Arc<dyn Writer>]) -> usize {
+    writers
+        .iter()
+        .filter_map(|w| {
+            let verified_step = w.verify_progress().verified_step(reader)?;
+            Some(w.current_step().saturating_sub(verified_step))
+        })
+        .max()
+        .unwrap_or_default()
+}
 
 #[super::async_trait]
 pub trait Writer: Task {
     fn index(&self) -> usize;
 
+    /// Return the counters of the ops executed by the writer.
+    fn error_stats(&self) -> &ErrorStats;
+
     /// Return the current step of writer.
     fn current_step(&self) -> usize;
 
@@ -67,4 +908,104 @@ pub trait Writer: Task {
 
     /// Return the config of the writer.
     fn config(&self) -> Config;
+
+    /// Return the snapshot of the generator of the writer. The op of the current step might not
+    /// be acknowledged yet.
+    fn snapshot(&self) -> Snapshot;
+
+    /// Return the latencies of the executed ops, grouped by the type of op.
+    fn latencies(&self) -> OpLatencies;
+
+    /// Return the steps verified by the readers tracing the writer.
+    fn verify_progress(&self) -> &VerifyProgress;
+
+    /// Return the collection written by the writer, and its index collection.
+    fn collections(&self) -> (Collection, Option<Collection>);
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("2m").unwrap(), Duration::from_secs(120));
+        assert_eq!(
+            parse_duration(" 1h30m ").unwrap(),
+            Duration::from_secs(5400)
+        );
+        assert_eq!(parse_duration("1d").unwrap(), Duration::from_secs(86400));
+        assert_eq!(parse_duration("0s").unwrap(), Duration::ZERO);
+
+        for invalid in ["", " ", "5", "s", "1.5s", "-1s", "5 s", "5sec", "1h30"] {
+            assert!(parse_duration(invalid).is_err(), "{:?}", invalid);
+        }
+        // The value overflows u32, u64 and the duration.
+        assert!(parse_duration("4294967296ms").is_err());
+        assert!(parse_duration("18446744073709551616s").is_err());
+        let max = format!("{}d", u32::MAX);
+        assert!(parse_duration(&max.repeat(1 << 10)).is_ok());
+        assert!(parse_duration(&max.repeat(1 << 20)).is_err());
+    }
+
+    #[test]
+    fn format_durations() {
+        for d in ["0ms", "500ms", "1500ms", "30s", "2m", "90m", "3h", "2d"] {
+            assert_eq!(format_duration(parse_duration(d).unwrap()), d);
+        }
+    }
+
+    #[test]
+    fn parse_sizes() {
+        assert_eq!(parse_size("512").unwrap(), 512);
+        assert_eq!(parse_size("512B").unwrap(), 512);
+        assert_eq!(parse_size("4KB").unwrap(), 4000);
+        assert_eq!(parse_size("4KiB").unwrap(), 4096);
+        assert_eq!(parse_size(" 1 MiB ").unwrap(), 1 << 20);
+        assert_eq!(parse_size("2MB").unwrap(), 2_000_000);
+        assert_eq!(parse_size("1GB").unwrap(), 1_000_000_000);
+        assert_eq!(parse_size("1GiB").unwrap(), 1 << 30);
+
+        for invalid in ["", "KiB", "1.5KiB", "-1", "4kib", "4TB"] {
+            assert!(parse_size(invalid).is_err(), "{:?}", invalid);
+        }
+        // The value overflows usize, before and after the unit is applied.
+        assert!(parse_size(&format!("{}0", usize::MAX)).is_err());
+        assert!(parse_size(&format!("{}GiB", usize::MAX >> 29)).is_err());
+        assert!(parse_size(&format!("{}GiB", usize::MAX >> 30)).is_ok());
+    }
+
+    #[test]
+    fn deserialize_size_ranges() {
+        assert_eq!(
+            deserialize_size_range(json!({"start": 512, "end": 2048})).unwrap(),
+            512..2048
+        );
+        assert_eq!(
+            deserialize_size_range(json!("4KiB..1MiB")).unwrap(),
+            4096..(1 << 20)
+        );
+        assert_eq!(
+            deserialize_size_range(json!(" 512 .. 2KB ")).unwrap(),
+            512..2000
+        );
+
+        for invalid in [
+            json!("4KiB"),
+            json!("4KiB..1TB"),
+            json!(16),
+            json!({ "start": 1 }),
+        ] {
+            assert!(
+                deserialize_size_range(invalid.clone()).is_err(),
+                "{}",
+                invalid
+            );
+        }
+        assert!(deserialize_size_range(json!(format!("1..{}0", usize::MAX))).is_err());
+    }
 }
@@ -0,0 +1,109 @@
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use tracing::error;
+
+use crate::base::{deserialize_secs, Failure, FailureKind, Reader, Writers};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ErrorBudgetConfig {
+    /// Abort the run if the fraction of failed ops in the window exceeds it.
+    pub max_error_ratio: f64,
+    #[serde(deserialize_with = "deserialize_secs")]
+    pub window_secs: u64,
+    /// The window is not evaluated until it contains at least this number of ops, so a few
+    /// failures during a quiet period do not abort the run.
+    pub min_ops: usize,
+}
+
+/// Evaluate the failed ops of all writers and readers over a sliding window.
+pub struct ErrorBudget {
+    cfg: ErrorBudgetConfig,
+    writers: Writers,
+    readers: Vec<Arc<dyn Reader>>,
+}
+
+impl ErrorBudget {
+    pub fn new(cfg: ErrorBudgetConfig, writers: Writers, readers: Vec<Arc<dyn Reader>>) -> Self {
+        ErrorBudget {
+            cfg,
+            writers,
+            readers,
+        }
+    }
+
+    /// Sample the counters every second, return an error once the budget is exceeded.
+    pub async fn watch(self) -> Result<()> {
+        let window = Duration::from_secs(self.cfg.window_secs);
+        let mut samples: VecDeque<(Instant, usize, usize)> = VecDeque::new();
+        loop {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            let now = Instant::now();
+            let (succeeds, failures) = self.totals();
+            samples.push_back((now, succeeds, failures));
+            while let Some((at, _, _)) = samples.front() {
+                if now.duration_since(*at) <= window {
+                    break;
+                }
+                samples.pop_front();
+            }
+
+            let (_, first_succeeds, first_failures) = *samples.front().unwrap();
+            let failed = failures.saturating_sub(first_failures);
+            let total = succeeds.saturating_sub(first_succeeds) + failed;
+            if total == 0 || total < self.cfg.min_ops {
+                continue;
+            }
+            let ratio = failed as f64 / total as f64;
+            if ratio > self.cfg.max_error_ratio {
+                self.report(ratio, failed, total);
+                anyhow::bail!(Failure::new(
+                    FailureKind::Availability,
+                    format!(
+                        "error budget exceeded: {} of {} ops failed ({:.2}%) in the last {:?}, the limit is {:.2}%",
+                        failed,
+                        total,
+                        ratio * 100.0,
+                        window,
+                        self.cfg.max_error_ratio * 100.0,
+                    )
+                ));
+            }
+        }
+    }
+
+    fn totals(&self) -> (usize, usize) {
+        let writers = self.writers.lock().unwrap();
+        let writer_stats = writers.iter().map(|w| w.error_stats());
+        let reader_stats = self.readers.iter().map(|r| r.error_stats());
+        writer_stats
+            .chain(reader_stats)
+            .fold((0, 0), |(succeeds, failures), stats| {
+                (succeeds + stats.succeeds(), failures + stats.failures())
+            })
+    }
+
+    fn report(&self, ratio: f64, failed: usize, total: usize) {
+        error!(
+            "error budget exceeded, {} of {} ops failed ({:.2}%) in the window",
+            failed,
+            total,
+            ratio * 100.0
+        );
+        for writer in self.writers.lock().unwrap().iter() {
+            let stats = writer.error_stats();
+            error!(
+                "writer {} at step {}: {}",
+                writer.index(),
+                writer.current_step(),
+                stats,
+            );
+        }
+        for reader in &self.readers {
+            let stats = reader.error_stats();
+            error!("reader {}: {}", reader.index(), stats);
+        }
+    }
+}
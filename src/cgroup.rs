@@ -0,0 +1,69 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::Result;
+use tracing::{error, info};
+
+use crate::{chaos::Fault, cluster::Cluster};
+
+/// The cgroup v2 group of the supervisor, each node is moved into its own child group once a
+/// limit is applied to it.
+const CGROUP_ROOT: &str = "/sys/fs/cgroup/engula_chaos";
+
+/// Apply a limit of cgroup v2 to the processes of the targets, eg `io.max`, and reset it once the
+/// fault is recovered. The nodes must be spawned by the supervisor.
+pub struct CgroupFault {
+    pub name: &'static str,
+    pub cluster: Arc<Cluster>,
+    /// The controller of the limit, it is enabled for the child groups.
+    pub controller: &'static str,
+    pub file: &'static str,
+    pub limit: String,
+    pub reset: String,
+}
+
+impl CgroupFault {
+    async fn write(&self, target: &str, value: &str) -> Result<()> {
+        let root = PathBuf::from(CGROUP_ROOT);
+        let group = root.join(target);
+        tokio::fs::create_dir_all(&group).await?;
+        for parent in [root.parent().unwrap(), root.as_path()] {
+            tokio::fs::write(
+                parent.join("cgroup.subtree_control"),
+                format!("+{}", self.controller),
+            )
+            .await?;
+        }
+        // The process is moved again after the node is restarted.
+        if let Some(pid) = self.cluster.node_pid(target).await {
+            tokio::fs::write(group.join("cgroup.procs"), pid.to_string()).await?;
+        }
+        tokio::fs::write(group.join(self.file), value).await?;
+        Ok(())
+    }
+}
+
+#[super::async_trait]
+impl Fault for CgroupFault {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    async fn inject(&self, targets: &[String]) -> Result<()> {
+        for target in targets {
+            self.write(target, &self.limit).await?;
+            info!("set {} of node {} to {}", self.file, target, self.limit);
+        }
+        Ok(())
+    }
+
+    async fn recover(&self, targets: &[String]) -> Result<()> {
+        let mut result = Ok(());
+        for target in targets {
+            if let Err(e) = self.write(target, &self.reset).await {
+                error!("reset {} of node {}: {}", self.file, target, e);
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
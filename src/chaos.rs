@@ -0,0 +1,872 @@
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use rand::{prelude::SmallRng, seq::SliceRandom, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tracing::{error, info, info_span, warn, Instrument};
+
+use crate::{
+    base::{
+        deserialize_duration, deserialize_millis, deserialize_opt_secs, deserialize_secs,
+        serialize_duration, ExecCtx, RelaxConfig, Relaxation,
+    },
+    cgroup::CgroupFault,
+    cluster::Cluster,
+    docker::{self, Docker},
+    history::now_us,
+    kubernetes::{self, Kubernetes},
+    net::{host_of, run_on, Firewall, NetemFault, PartitionFault},
+    toxiproxy::{ToxicFault, Toxiproxy},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NemesisConfig {
+    /// The interval between the recovery of a fault and the next injection, in seconds.
+    #[serde(deserialize_with = "deserialize_secs")]
+    pub interval_secs: u64,
+    /// The duration of a fault before it is recovered, eg the downtime of a killed node.
+    #[serde(deserialize_with = "deserialize_secs")]
+    pub duration_secs: u64,
+    /// Extend each interval and duration by a random duration up to the jitter, which is drawn
+    /// from the seed of the nemesis too.
+    #[serde(default, deserialize_with = "deserialize_opt_secs")]
+    pub interval_jitter_secs: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_opt_secs")]
+    pub duration_jitter_secs: Option<u64>,
+    /// The number of nodes selected as the targets of each injection.
+    pub targets: usize,
+    /// The thresholds of verification while the fault is active.
+    pub relax: Option<RelaxConfig>,
+    #[serde(flatten)]
+    pub fault: FaultConfig,
+}
+
+/// A timed fault of the chaos schedule, eg
+/// `{ at = "2m", action = "partition", targets = ["node-1"], duration = "30s", ... }`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ChaosStep {
+    /// The time since the run starts.
+    #[serde(
+        serialize_with = "serialize_duration",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub at: Duration,
+    #[serde(
+        serialize_with = "serialize_duration",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub duration: Duration,
+    pub targets: Vec<String>,
+    pub relax: Option<RelaxConfig>,
+    #[serde(flatten)]
+    pub fault: FaultConfig,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum FaultConfig {
+    /// Kill the nodes by SIGKILL, and restart them once the fault is recovered.
+    Kill,
+    /// Freeze the nodes by SIGSTOP, and resume them by SIGCONT once the fault is recovered, like
+    /// a long GC or VM pause.
+    Pause,
+    /// Drop the packets from the other nodes to the targets, and from the targets to the others
+    /// too if it is symmetric.
+    Partition {
+        symmetric: bool,
+        firewall: Firewall,
+        /// The command prefix to run the rules on the host of a node, eg `["ssh", "{host}"]`,
+        /// they are run locally if it is not specified.
+        remote_shell: Option<Vec<String>>,
+    },
+    /// Add latency, jitter, reordering and loss on the interface of the targets by tc/netem.
+    Netem {
+        interface: String,
+        #[serde(deserialize_with = "deserialize_millis")]
+        delay_ms: u64,
+        #[serde(deserialize_with = "deserialize_millis")]
+        jitter_ms: u64,
+        loss_percent: f64,
+        reorder_percent: f64,
+        remote_shell: Option<Vec<String>>,
+    },
+    /// Add a toxic of Toxiproxy to the proxies of the targets, eg `latency`, `bandwidth` or
+    /// `reset_peer`, with the attributes defined by Toxiproxy.
+    Toxic {
+        toxic: String,
+        attributes: BTreeMap<String, i64>,
+    },
+    /// Skew the clock of the targets by an offset and a drift rate, either by restarting them
+    /// under libfaketime, or by the command hook with `{offset_ms}` and `{drift_ppm}`.
+    ClockSkew {
+        offset_ms: i64,
+        drift_ppm: i64,
+        /// The path of `libfaketime.so.1`, it requires the `cluster` config.
+        libfaketime: Option<PathBuf>,
+        hook: Option<CommandHook>,
+    },
+    /// Fill the disk of the data directory of the targets with a file of `fill_bytes`.
+    DiskFull {
+        fill_bytes: u64,
+        remote_shell: Option<Vec<String>>,
+    },
+    /// Inject IO errors by the command hook, eg to load a dm-flakey table or a faulty fuse
+    /// mount under the data directory. In kubernetes, the errors are injected into the files
+    /// under `volume_path` of the pods by chaos-mesh instead.
+    DiskError {
+        hook: Option<CommandHook>,
+        volume_path: Option<String>,
+    },
+    /// Add `targets` spare nodes to the cluster, and decommission them once the fault is
+    /// recovered, by the `decommission` command with `{name}` and `{addr}` if it is specified,
+    /// before they are killed.
+    Membership {
+        decommission: Option<Vec<String>>,
+        remote_shell: Option<Vec<String>>,
+    },
+    /// Throttle the IO of the targets on the device `major:minor` by cgroup v2.
+    IoThrottle {
+        device: String,
+        read_bps: u64,
+        write_bps: u64,
+    },
+    /// Limit the CPU of the targets to a percentage of one core by cgroup v2.
+    CpuQuota { percent: u64 },
+    /// Limit the memory of the targets by cgroup v2, the node is throttled and reclaimed above
+    /// `high_bytes`, and killed by OOM above `max_bytes`.
+    MemoryLimit {
+        high_bytes: Option<u64>,
+        max_bytes: Option<u64>,
+    },
+}
+
+/// The commands to inject and recover a fault on each target, `{name}`, `{host}` and
+/// `{data_dir}` are replaced by the values of the node, and the others by the parameters of
+/// the fault.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CommandHook {
+    pub inject: Vec<String>,
+    pub recover: Vec<String>,
+    /// The command prefix to run the commands on the host of a node, eg `["ssh", "{host}"]`.
+    pub remote_shell: Option<Vec<String>>,
+}
+
+#[super::async_trait]
+pub trait Fault: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn inject(&self, targets: &[String]) -> Result<()>;
+
+    async fn recover(&self, targets: &[String]) -> Result<()>;
+}
+
+/// The components which the faults are injected into.
+pub struct ChaosEnv {
+    pub cluster: Option<Arc<Cluster>>,
+    pub toxiproxy: Option<Arc<Toxiproxy>>,
+    /// The faults are injected into the pods instead of the local nodes if it is specified.
+    pub kubernetes: Option<Arc<Kubernetes>>,
+    /// The faults are injected into the containers instead of the local nodes if it is
+    /// specified.
+    pub docker: Option<Arc<Docker>>,
+}
+
+impl ChaosEnv {
+    /// Return the names of the nodes which could be selected as the targets.
+    pub fn node_names(&self) -> Vec<String> {
+        if let Some(k8s) = &self.kubernetes {
+            return k8s.pod_names();
+        }
+        if let Some(docker) = &self.docker {
+            return docker.node_names();
+        }
+        match (&self.cluster, &self.toxiproxy) {
+            (Some(cluster), _) => cluster.node_names(),
+            (None, Some(toxiproxy)) => toxiproxy.proxy_names(),
+            (None, None) => vec![],
+        }
+    }
+
+    fn cluster(&self) -> Result<Arc<Cluster>> {
+        match &self.cluster {
+            Some(cluster) => Ok(cluster.clone()),
+            None => anyhow::bail!("the fault requires the `cluster` config"),
+        }
+    }
+}
+
+pub fn build_fault(cfg: &FaultConfig, env: &ChaosEnv) -> Result<Arc<dyn Fault>> {
+    if let Some(k8s) = &env.kubernetes {
+        return kubernetes::build_fault(cfg, k8s.clone());
+    }
+    if let Some(docker) = &env.docker {
+        return docker::build_fault(cfg, docker.clone());
+    }
+    let fault: Arc<dyn Fault> = match cfg {
+        FaultConfig::Kill => Arc::new(KillFault {
+            cluster: env.cluster()?,
+        }),
+        FaultConfig::Pause => Arc::new(PauseFault {
+            cluster: env.cluster()?,
+        }),
+        FaultConfig::Partition {
+            symmetric,
+            firewall,
+            remote_shell,
+        } => Arc::new(PartitionFault {
+            cluster: env.cluster()?,
+            symmetric: *symmetric,
+            firewall: *firewall,
+            remote_shell: remote_shell.clone(),
+        }),
+        FaultConfig::Netem {
+            interface,
+            delay_ms,
+            jitter_ms,
+            loss_percent,
+            reorder_percent,
+            remote_shell,
+        } => Arc::new(NetemFault {
+            cluster: env.cluster()?,
+            interface: interface.clone(),
+            delay_ms: *delay_ms,
+            jitter_ms: *jitter_ms,
+            loss_percent: *loss_percent,
+            reorder_percent: *reorder_percent,
+            remote_shell: remote_shell.clone(),
+        }),
+        FaultConfig::Toxic { toxic, attributes } => match &env.toxiproxy {
+            Some(toxiproxy) => Arc::new(ToxicFault {
+                toxiproxy: toxiproxy.clone(),
+                toxic: toxic.clone(),
+                attributes: attributes.clone(),
+            }),
+            None => anyhow::bail!("the toxic fault requires the `toxiproxy` config"),
+        },
+        FaultConfig::ClockSkew {
+            offset_ms,
+            drift_ppm,
+            libfaketime,
+            hook,
+        } => match (libfaketime, hook) {
+            (Some(libfaketime), _) => Arc::new(FaketimeFault {
+                cluster: env.cluster()?,
+                libfaketime: libfaketime.clone(),
+                offset_ms: *offset_ms,
+                drift_ppm: *drift_ppm,
+            }),
+            (None, Some(hook)) => Arc::new(HookFault {
+                name: "clock_skew",
+                cluster: env.cluster.clone(),
+                hook: hook.clone(),
+                vars: vec![
+                    ("offset_ms".to_owned(), offset_ms.to_string()),
+                    ("drift_ppm".to_owned(), drift_ppm.to_string()),
+                ],
+            }),
+            (None, None) => anyhow::bail!("the clock skew requires `libfaketime` or `hook`"),
+        },
+        FaultConfig::DiskFull {
+            fill_bytes,
+            remote_shell,
+        } => Arc::new(HookFault {
+            name: "disk_full",
+            cluster: Some(env.cluster()?),
+            hook: CommandHook {
+                inject: args(&["fallocate", "-l", "{fill_bytes}", FILL_FILE]),
+                recover: args(&["rm", "-f", FILL_FILE]),
+                remote_shell: remote_shell.clone(),
+            },
+            vars: vec![("fill_bytes".to_owned(), fill_bytes.to_string())],
+        }),
+        FaultConfig::DiskError { hook, .. } => match hook {
+            Some(hook) => Arc::new(HookFault {
+                name: "disk_error",
+                cluster: env.cluster.clone(),
+                hook: hook.clone(),
+                vars: vec![],
+            }),
+            None => anyhow::bail!("the disk error requires `hook` outside kubernetes"),
+        },
+        FaultConfig::Membership {
+            decommission,
+            remote_shell,
+        } => Arc::new(MembershipFault {
+            cluster: env.cluster()?,
+            decommission: decommission.clone(),
+            remote_shell: remote_shell.clone(),
+            added: Mutex::default(),
+        }),
+        FaultConfig::IoThrottle {
+            device,
+            read_bps,
+            write_bps,
+        } => Arc::new(CgroupFault {
+            name: "io_throttle",
+            cluster: env.cluster()?,
+            controller: "io",
+            file: "io.max",
+            limit: format!("{} rbps={} wbps={}", device, read_bps, write_bps),
+            reset: format!("{} rbps=max wbps=max", device),
+        }),
+        FaultConfig::CpuQuota { percent: 0 } => {
+            anyhow::bail!("the cpu quota requires a positive `percent`")
+        }
+        FaultConfig::CpuQuota { percent } => Arc::new(CgroupFault {
+            name: "cpu_quota",
+            cluster: env.cluster()?,
+            controller: "cpu",
+            file: "cpu.max",
+            limit: format!("{} {}", CPU_PERIOD_US * percent / 100, CPU_PERIOD_US),
+            reset: format!("max {}", CPU_PERIOD_US),
+        }),
+        FaultConfig::MemoryLimit {
+            high_bytes: Some(high_bytes),
+            max_bytes: None,
+        } => Arc::new(CgroupFault {
+            name: "memory_limit",
+            cluster: env.cluster()?,
+            controller: "memory",
+            file: "memory.high",
+            limit: high_bytes.to_string(),
+            reset: "max".to_owned(),
+        }),
+        FaultConfig::MemoryLimit {
+            high_bytes: None,
+            max_bytes: Some(max_bytes),
+        } => Arc::new(CgroupFault {
+            name: "memory_limit",
+            cluster: env.cluster()?,
+            controller: "memory",
+            file: "memory.max",
+            limit: max_bytes.to_string(),
+            reset: "max".to_owned(),
+        }),
+        FaultConfig::MemoryLimit { .. } => {
+            anyhow::bail!("the memory limit requires one of `high_bytes` and `max_bytes`")
+        }
+    };
+    Ok(fault)
+}
+
+/// The time window of an injected fault, in microseconds since unix epoch like the history, so
+/// the failures could be correlated with the faults.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FaultWindow {
+    pub fault: String,
+    pub targets: Vec<String>,
+    pub start_us: u64,
+    /// `None` if the fault is not recovered yet.
+    pub end_us: Option<u64>,
+}
+
+/// The windows of all injected faults.
+#[derive(Default)]
+pub struct FaultLog {
+    windows: std::sync::Mutex<Vec<FaultWindow>>,
+    /// The faults which are injected and not recovered yet, by the ids of their windows.
+    active: std::sync::Mutex<Vec<(usize, Arc<dyn Fault>)>>,
+}
+
+impl std::fmt::Debug for FaultLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FaultLog")
+            .field("windows", &self.windows)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FaultLog {
+    pub fn begin(&self, fault: &'static str, targets: &[String]) -> usize {
+        let start_us = now_us();
+        info!("fault {} on {:?} begins at {}", fault, targets, start_us);
+        let mut windows = self.windows.lock().unwrap();
+        windows.push(FaultWindow {
+            fault: fault.to_owned(),
+            targets: targets.to_owned(),
+            start_us,
+            end_us: None,
+        });
+        windows.len() - 1
+    }
+
+    /// Return the windows of all injected faults, in the order of injection.
+    pub fn windows(&self) -> Vec<FaultWindow> {
+        self.windows.lock().unwrap().clone()
+    }
+
+    /// Return the faults active at the time in microseconds since unix epoch.
+    pub fn active_at(&self, at_us: u64) -> Vec<String> {
+        let windows = self.windows.lock().unwrap();
+        windows
+            .iter()
+            .filter(|w| w.start_us <= at_us && !matches!(w.end_us, Some(end_us) if end_us < at_us))
+            .map(|w| w.fault.clone())
+            .collect()
+    }
+
+    /// Return whether a fault is active, or was recovered less than `grace` ago. The targets
+    /// are not considered, since the nodes serving a key are unknown to the supervisor.
+    pub fn is_disturbed(&self, grace: Duration) -> bool {
+        let now = now_us();
+        let grace_us = grace.as_micros() as u64;
+        let windows = self.windows.lock().unwrap();
+        windows.iter().any(|w| match w.end_us {
+            Some(end_us) => end_us + grace_us > now,
+            None => true,
+        })
+    }
+
+    /// Record the fault of the window, so it could be recovered by [`FaultLog::recover_active`]
+    /// until the window ends.
+    fn activate(&self, id: usize, fault: Arc<dyn Fault>) {
+        self.active.lock().unwrap().push((id, fault));
+    }
+
+    /// Recover the faults which are not recovered yet, it is the best effort to leave the nodes
+    /// healthy when the run is aborted without stopping the nemeses.
+    pub async fn recover_active(&self) {
+        let active = std::mem::take(&mut *self.active.lock().unwrap());
+        for (id, fault) in active {
+            let targets = self.windows.lock().unwrap()[id].targets.clone();
+            match fault.recover(&targets).await {
+                Ok(()) => self.end(id),
+                Err(e) => error!("recover fault {} on {:?}: {}", fault.name(), targets, e),
+            }
+        }
+    }
+
+    pub fn end(&self, id: usize) {
+        self.active
+            .lock()
+            .unwrap()
+            .retain(|(active, _)| *active != id);
+        let end_us = now_us();
+        let mut windows = self.windows.lock().unwrap();
+        let window = &mut windows[id];
+        window.end_us = Some(end_us);
+        info!(
+            "fault {} on {:?} ends at {}, lasts {:?}",
+            window.fault,
+            window.targets,
+            end_us,
+            Duration::from_micros(end_us.saturating_sub(window.start_us))
+        );
+    }
+}
+
+/// Inject a fault on the nodes selected randomly from the seed periodically, and recover it
+/// after the duration.
+pub struct Nemesis {
+    /// The timing and the targets might be changed by reloading the config, the fault is not.
+    cfg: std::sync::Mutex<NemesisConfig>,
+    seed: u64,
+    fault: Arc<dyn Fault>,
+    nodes: Vec<String>,
+    rng: Mutex<SmallRng>,
+    log: Arc<FaultLog>,
+    relaxation: Arc<Relaxation>,
+}
+
+impl Nemesis {
+    pub fn new(
+        cfg: NemesisConfig,
+        seed: u64,
+        fault: Arc<dyn Fault>,
+        nodes: Vec<String>,
+        log: Arc<FaultLog>,
+        relaxation: Arc<Relaxation>,
+    ) -> Self {
+        Nemesis {
+            cfg: std::sync::Mutex::new(cfg),
+            seed,
+            fault,
+            nodes,
+            rng: Mutex::new(SmallRng::seed_from_u64(seed)),
+            log,
+            relaxation,
+        }
+    }
+
+    /// Apply the config from the next injection.
+    pub fn update(&self, cfg: NemesisConfig) {
+        *self.cfg.lock().unwrap() = cfg;
+    }
+}
+
+#[super::async_trait]
+impl super::base::Task for Nemesis {
+    async fn run(&self, mut ctx: ExecCtx) {
+        info!(
+            "nemesis {} starts with seed {}",
+            self.fault.name(),
+            self.seed
+        );
+        let mut rng = self.rng.lock().await;
+        // All decisions are drawn in the same order, so the run could be reproduced by the seed.
+        loop {
+            let cfg = self.cfg.lock().unwrap().clone();
+            let interval = jitter(&mut rng, cfg.interval_secs, cfg.interval_jitter_secs);
+            let duration = jitter(&mut rng, cfg.duration_secs, cfg.duration_jitter_secs);
+            if ctx.wait_until_timeout_or_shutdown(interval).await.is_none() {
+                break;
+            }
+            let targets = self
+                .nodes
+                .choose_multiple(&mut *rng, cfg.targets)
+                .cloned()
+                .collect::<Vec<_>>();
+            let window = FaultWindowCtx {
+                log: &self.log,
+                relaxation: &self.relaxation,
+                relax: &cfg.relax,
+            };
+            window.run(&self.fault, &targets, duration, &mut ctx).await;
+        }
+    }
+}
+
+/// Add a random jitter in `[0, jitter_secs]` to `secs`, it saturates instead of overflowing
+/// at the huge values of the config.
+fn jitter(rng: &mut SmallRng, secs: u64, jitter_secs: Option<u64>) -> Duration {
+    let jitter_secs = jitter_secs.unwrap_or_default();
+    Duration::from_secs(secs.saturating_add(rng.gen_range(0..=jitter_secs)))
+}
+
+/// Execute the faults of the chaos schedule at their time, each fault is independent of the
+/// others, so they might overlap.
+pub struct ChaosScheduler {
+    steps: Vec<(ChaosStep, Arc<dyn Fault>)>,
+    log: Arc<FaultLog>,
+    relaxation: Arc<Relaxation>,
+}
+
+impl ChaosScheduler {
+    pub fn new(
+        steps: Vec<(ChaosStep, Arc<dyn Fault>)>,
+        log: Arc<FaultLog>,
+        relaxation: Arc<Relaxation>,
+    ) -> Self {
+        ChaosScheduler {
+            steps,
+            log,
+            relaxation,
+        }
+    }
+}
+
+#[super::async_trait]
+impl super::base::Task for ChaosScheduler {
+    async fn run(&self, ctx: ExecCtx) {
+        let start = tokio::time::Instant::now();
+        let mut handles = vec![];
+        for (step, fault) in self.steps.clone() {
+            let log = self.log.clone();
+            let relaxation = self.relaxation.clone();
+            let mut ctx = ctx.clone();
+            handles.push(tokio::spawn(async move {
+                let delay =
+                    (start + step.at).saturating_duration_since(tokio::time::Instant::now());
+                if ctx.wait_until_timeout_or_shutdown(delay).await.is_none() {
+                    return;
+                }
+                let window = FaultWindowCtx {
+                    log: &log,
+                    relaxation: &relaxation,
+                    relax: &step.relax,
+                };
+                window
+                    .run(&fault, &step.targets, step.duration, &mut ctx)
+                    .await;
+            }));
+        }
+        for handle in handles {
+            handle.await.unwrap_or_default();
+        }
+    }
+}
+
+/// Inject a fault for a window, which is recorded to the log.
+struct FaultWindowCtx<'a> {
+    log: &'a FaultLog,
+    relaxation: &'a Relaxation,
+    relax: &'a Option<RelaxConfig>,
+}
+
+impl<'a> FaultWindowCtx<'a> {
+    async fn run(
+        &self,
+        fault: &Arc<dyn Fault>,
+        targets: &[String],
+        duration: Duration,
+        ctx: &mut ExecCtx,
+    ) {
+        let id = self.log.begin(fault.name(), targets);
+        self.log.activate(id, fault.clone());
+        if let Some(relax) = self.relax {
+            self.relaxation.relax(id, relax.clone());
+        }
+        let span = info_span!("inject", fault = fault.name(), targets = ?targets);
+        if let Err(e) = fault.inject(targets).instrument(span).await {
+            error!("inject fault {} on {:?}: {}", fault.name(), targets, e);
+        }
+        // The fault is always recovered, even if the run is stopped.
+        ctx.wait_until_timeout_or_shutdown(duration).await;
+        let span = info_span!("recover", fault = fault.name(), targets = ?targets);
+        if let Err(e) = fault.recover(targets).instrument(span).await {
+            error!("recover fault {} on {:?}: {}", fault.name(), targets, e);
+        }
+        self.relaxation.restore(id);
+        self.log.end(id);
+    }
+}
+
+struct KillFault {
+    cluster: Arc<Cluster>,
+}
+
+#[super::async_trait]
+impl Fault for KillFault {
+    fn name(&self) -> &'static str {
+        "kill"
+    }
+
+    async fn inject(&self, targets: &[String]) -> Result<()> {
+        for target in targets {
+            self.cluster.kill_node(target).await?;
+        }
+        Ok(())
+    }
+
+    async fn recover(&self, targets: &[String]) -> Result<()> {
+        // Start the others even if a node fails to start.
+        let mut result = Ok(());
+        for target in targets {
+            if let Err(e) = self.cluster.start_node(target).await {
+                error!("restart node {}: {}", target, e);
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
+
+struct PauseFault {
+    cluster: Arc<Cluster>,
+}
+
+#[super::async_trait]
+impl Fault for PauseFault {
+    fn name(&self) -> &'static str {
+        "pause"
+    }
+
+    async fn inject(&self, targets: &[String]) -> Result<()> {
+        for target in targets {
+            self.cluster.signal_node(target, "STOP").await?;
+        }
+        Ok(())
+    }
+
+    async fn recover(&self, targets: &[String]) -> Result<()> {
+        let mut result = Ok(());
+        for target in targets {
+            if let Err(e) = self.cluster.signal_node(target, "CONT").await {
+                error!("resume node {}: {}", target, e);
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
+
+/// The period of the CPU quota, the default of cgroup v2.
+const CPU_PERIOD_US: u64 = 100_000;
+
+/// The file to fill the disk of a node.
+const FILL_FILE: &str = "{data_dir}/engula_chaos.fill";
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|s| s.to_string()).collect()
+}
+
+/// Run the command hook on each target.
+struct HookFault {
+    name: &'static str,
+    cluster: Option<Arc<Cluster>>,
+    hook: CommandHook,
+    vars: Vec<(String, String)>,
+}
+
+impl HookFault {
+    async fn run(&self, cmd: &[String], target: &str) -> Result<()> {
+        run_node_command(
+            self.cluster.as_deref(),
+            &self.hook.remote_shell,
+            cmd,
+            target,
+            &self.vars,
+        )
+        .await
+    }
+}
+
+/// Run the command for the node on its host, the placeholders of the node and `vars` are
+/// replaced.
+async fn run_node_command(
+    cluster: Option<&Cluster>,
+    remote_shell: &Option<Vec<String>>,
+    cmd: &[String],
+    target: &str,
+    vars: &[(String, String)],
+) -> Result<()> {
+    let node = cluster.and_then(|c| c.node_config(target));
+    let addr = node.as_ref().map(|n| n.addr.clone()).unwrap_or_default();
+    let data_dir = node
+        .as_ref()
+        .map(|n| n.data_dir.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let host = host_of(&addr);
+    let cmd = cmd
+        .iter()
+        .map(|arg| {
+            let arg = arg
+                .replace("{name}", target)
+                .replace("{addr}", &addr)
+                .replace("{host}", host)
+                .replace("{data_dir}", &data_dir);
+            vars.iter()
+                .fold(arg, |arg, (k, v)| arg.replace(&format!("{{{}}}", k), v))
+        })
+        .collect::<Vec<_>>();
+    run_on(host, remote_shell, &cmd).await
+}
+
+/// Add the spare nodes, the targets only decide the number of nodes.
+struct MembershipFault {
+    cluster: Arc<Cluster>,
+    decommission: Option<Vec<String>>,
+    remote_shell: Option<Vec<String>>,
+    /// The nodes added by the last injection.
+    added: Mutex<Vec<String>>,
+}
+
+#[super::async_trait]
+impl Fault for MembershipFault {
+    fn name(&self) -> &'static str {
+        "membership"
+    }
+
+    async fn inject(&self, targets: &[String]) -> Result<()> {
+        let mut added = self.added.lock().await;
+        let spares = self.cluster.idle_spare_names().await;
+        if spares.len() < targets.len() {
+            warn!(
+                "add {} nodes, but there are only {} idle spare nodes",
+                targets.len(),
+                spares.len()
+            );
+        }
+        for name in spares.into_iter().take(targets.len()) {
+            self.cluster.start_node(&name).await?;
+            info!("node {} joins the cluster", name);
+            added.push(name);
+        }
+        Ok(())
+    }
+
+    async fn recover(&self, _targets: &[String]) -> Result<()> {
+        let mut result = Ok(());
+        for name in std::mem::take(&mut *self.added.lock().await) {
+            if let Some(cmd) = &self.decommission {
+                let cluster = Some(self.cluster.as_ref());
+                if let Err(e) = run_node_command(cluster, &self.remote_shell, cmd, &name, &[]).await
+                {
+                    error!("decommission node {}: {}", name, e);
+                    result = Err(e);
+                    // The node is kept until it is decommissioned, or the data might be lost.
+                    continue;
+                }
+            }
+            if let Err(e) = self.cluster.kill_node(&name).await {
+                error!("stop node {}: {}", name, e);
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
+
+#[super::async_trait]
+impl Fault for HookFault {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    async fn inject(&self, targets: &[String]) -> Result<()> {
+        for target in targets {
+            self.run(&self.hook.inject, target).await?;
+        }
+        Ok(())
+    }
+
+    async fn recover(&self, targets: &[String]) -> Result<()> {
+        let mut result = Ok(());
+        for target in targets {
+            if let Err(e) = self.run(&self.hook.recover, target).await {
+                error!("recover fault {} of {}: {}", self.name, target, e);
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
+
+/// Restart the targets under libfaketime, so their clocks are skewed from the start.
+struct FaketimeFault {
+    cluster: Arc<Cluster>,
+    libfaketime: PathBuf,
+    offset_ms: i64,
+    drift_ppm: i64,
+}
+
+#[super::async_trait]
+impl Fault for FaketimeFault {
+    fn name(&self) -> &'static str {
+        "clock_skew"
+    }
+
+    async fn inject(&self, targets: &[String]) -> Result<()> {
+        // eg `+1.5 x1.0001`, the offset in seconds and the rate of the clock.
+        let faketime = format!(
+            "{:+} x{}",
+            self.offset_ms as f64 / 1000.0,
+            1.0 + self.drift_ppm as f64 / 1_000_000.0
+        );
+        let envs = vec![
+            (
+                "LD_PRELOAD".to_owned(),
+                self.libfaketime.to_string_lossy().into_owned(),
+            ),
+            ("FAKETIME".to_owned(), faketime),
+        ];
+        for target in targets {
+            self.cluster
+                .restart_node_with_envs(target, envs.clone())
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn recover(&self, targets: &[String]) -> Result<()> {
+        let mut result = Ok(());
+        for target in targets {
+            if let Err(e) = self.cluster.restart_node_with_envs(target, vec![]).await {
+                error!("restart node {} without clock skew: {}", target, e);
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
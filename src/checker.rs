@@ -0,0 +1,332 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+use anyhow::Result;
+use tracing::{error, info, warn};
+
+use crate::{
+    base::{Failure, FailureKind},
+    history::{LostEvents, OpEvent, OpKind, OpOutcome},
+};
+
+/// The number of searched states of a key, before the checker gives up.
+const MAX_STATES: usize = 1 << 20;
+
+/// An op on a single key. The value written or read is identified by the step of the writer,
+/// since each key is only written by one writer. `None` means the key does not exist.
+#[derive(Debug)]
+struct Operation {
+    call: u64,
+    /// The op without a response, because it is timeout or failed, might take effect at any time
+    /// after it is invoked, or never.
+    ret: Option<u64>,
+    kind: RegisterOp,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum RegisterOp {
+    Write(Option<usize>),
+    Read(Option<usize>),
+}
+
+/// The state of the register. The history might start in the middle of a run (eg resumed from
+/// a checkpoint), so the initial value is unknown until it is observed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum RegisterState {
+    Unknown,
+    Known(Option<usize>),
+}
+
+impl RegisterState {
+    fn apply(self, op: RegisterOp) -> Option<RegisterState> {
+        match (self, op) {
+            (_, RegisterOp::Write(v)) => Some(RegisterState::Known(v)),
+            (RegisterState::Unknown, RegisterOp::Read(v)) => Some(RegisterState::Known(v)),
+            (RegisterState::Known(x), RegisterOp::Read(v)) if x == v => Some(self),
+            _ => None,
+        }
+    }
+}
+
+enum CheckResult {
+    Ok,
+    Illegal,
+    Unknown,
+}
+
+/// Check that the history recorded by `HistoryRecorder` is linearizable, under the model of one
+/// register per key. The keys are checked independently (P-compositionality), and the history
+/// of each key is searched exhaustively in the way of Wing & Gong with the cache of Lowe.
+///
+/// Unlike the staleness checks of readers, it does not depend on the lag settings, and it could
+/// prove a read is stale even if the read value is newer than the verified step.
+pub fn check_history(path: &Path) -> Result<()> {
+    let mut keys: HashMap<Vec<u8>, (usize, Vec<Operation>)> = HashMap::default();
+    let reader = BufReader::new(File::open(path)?);
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Ok(lost) = serde_json::from_str::<LostEvents>(&line) {
+            anyhow::bail!(Failure::new(
+                FailureKind::Internal,
+                format!(
+                    "the history {} is incomplete, the recorder lost {} events",
+                    path.display(),
+                    lost.lost_events
+                )
+            ));
+        }
+        let event: OpEvent = serde_json::from_str(&line)?;
+        let kind = match (event.kind, &event.outcome) {
+            (OpKind::Put, _) => RegisterOp::Write(Some(event.step)),
+            (OpKind::Delete, _) => RegisterOp::Write(None),
+            (OpKind::Get, OpOutcome::Ok) => RegisterOp::Read(event.read_step),
+            // A failed read observes nothing.
+            (OpKind::Get, _) => continue,
+        };
+        let ret = match event.outcome {
+            OpOutcome::Ok => Some(event.completed_at_us),
+            _ => None,
+        };
+        keys.entry(event.key)
+            .or_insert_with(|| (event.writer, vec![]))
+            .1
+            .push(Operation {
+                call: event.invoked_at_us,
+                ret,
+                kind,
+            });
+    }
+
+    let mut num_illegal = 0;
+    let mut num_unknown = 0;
+    for (key, (writer, ops)) in &mut keys {
+        match check_register(ops) {
+            CheckResult::Ok => {}
+            CheckResult::Illegal => {
+                num_illegal += 1;
+                error!(
+                    "the history of key {} written by writer {} is not linearizable, {} ops",
+                    String::from_utf8_lossy(key),
+                    writer,
+                    ops.len()
+                );
+            }
+            CheckResult::Unknown => {
+                num_unknown += 1;
+                warn!(
+                    "give up checking the history of key {} written by writer {}, {} ops",
+                    String::from_utf8_lossy(key),
+                    writer,
+                    ops.len()
+                );
+            }
+        }
+    }
+    info!(
+        "check history {}, {} keys, {} not linearizable, {} unknown",
+        path.display(),
+        keys.len(),
+        num_illegal,
+        num_unknown
+    );
+    if num_illegal > 0 {
+        anyhow::bail!(Failure::new(
+            FailureKind::Verification,
+            format!("{} keys are not linearizable", num_illegal)
+        ));
+    }
+    Ok(())
+}
+
+fn check_register(ops: &mut [Operation]) -> CheckResult {
+    ops.sort_by_key(|op| op.call);
+    let num_required = ops.iter().filter(|op| op.ret.is_some()).count();
+
+    let mut linearized = vec![0u64; ops.len() / 64 + 1];
+    let mut num_linearized_required = 0;
+    let mut state = RegisterState::Unknown;
+    // The linearized ops and the states before them, used to backtrack.
+    let mut stack: Vec<(usize, RegisterState)> = vec![];
+    let mut cache: HashSet<(Vec<u64>, RegisterState)> = HashSet::default();
+    let mut start = 0;
+    loop {
+        if num_linearized_required == num_required {
+            // The remaining ops are without a response, so they could never take effect.
+            return CheckResult::Ok;
+        }
+        if cache.len() > MAX_STATES {
+            return CheckResult::Unknown;
+        }
+
+        // An op could be linearized only if it is invoked before all pending ops respond.
+        let min_ret = ops
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !is_set(&linearized, *i))
+            .filter_map(|(_, op)| op.ret)
+            .min()
+            .unwrap_or(u64::MAX);
+        let mut next = None;
+        for (i, op) in ops.iter().enumerate().skip(start) {
+            if op.call > min_ret {
+                break;
+            }
+            if is_set(&linearized, i) {
+                continue;
+            }
+            if let Some(next_state) = state.apply(op.kind) {
+                set(&mut linearized, i);
+                if cache.insert((linearized.clone(), next_state)) {
+                    next = Some((i, next_state));
+                    break;
+                }
+                unset(&mut linearized, i);
+            }
+        }
+
+        match next {
+            Some((i, next_state)) => {
+                stack.push((i, state));
+                state = next_state;
+                if ops[i].ret.is_some() {
+                    num_linearized_required += 1;
+                }
+                start = 0;
+            }
+            None => {
+                let (i, prev_state) = match stack.pop() {
+                    Some(v) => v,
+                    None => return CheckResult::Illegal,
+                };
+                unset(&mut linearized, i);
+                state = prev_state;
+                if ops[i].ret.is_some() {
+                    num_linearized_required -= 1;
+                }
+                start = i + 1;
+            }
+        }
+    }
+}
+
+#[inline]
+fn is_set(bits: &[u64], i: usize) -> bool {
+    bits[i / 64] & (1 << (i % 64)) != 0
+}
+
+#[inline]
+fn set(bits: &mut [u64], i: usize) {
+    bits[i / 64] |= 1 << (i % 64);
+}
+
+#[inline]
+fn unset(bits: &mut [u64], i: usize) {
+    bits[i / 64] &= !(1 << (i % 64));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn op(call: u64, ret: Option<u64>, kind: RegisterOp) -> Operation {
+        Operation { call, ret, kind }
+    }
+
+    fn check(mut ops: Vec<Operation>) -> CheckResult {
+        check_register(&mut ops)
+    }
+
+    #[test]
+    fn linearizable() {
+        let ops = vec![
+            op(0, Some(10), RegisterOp::Write(Some(1))),
+            op(20, Some(30), RegisterOp::Read(Some(1))),
+            op(40, Some(50), RegisterOp::Write(None)),
+            op(60, Some(70), RegisterOp::Read(None)),
+            op(80, Some(90), RegisterOp::Write(Some(2))),
+            op(100, Some(110), RegisterOp::Read(Some(2))),
+        ];
+        assert!(matches!(check(ops), CheckResult::Ok));
+    }
+
+    #[test]
+    fn stale_read() {
+        let ops = vec![
+            op(0, Some(10), RegisterOp::Write(Some(1))),
+            op(20, Some(30), RegisterOp::Write(Some(2))),
+            op(40, Some(50), RegisterOp::Read(Some(1))),
+        ];
+        assert!(matches!(check(ops), CheckResult::Illegal));
+    }
+
+    #[test]
+    fn lost_ack_write() {
+        // The write without a response might take effect at any time after it is invoked.
+        let ops = vec![
+            op(0, Some(10), RegisterOp::Write(Some(1))),
+            op(20, None, RegisterOp::Write(Some(2))),
+            op(100, Some(110), RegisterOp::Read(Some(2))),
+        ];
+        assert!(matches!(check(ops), CheckResult::Ok));
+
+        // Or never.
+        let ops = vec![
+            op(0, Some(10), RegisterOp::Write(Some(1))),
+            op(20, None, RegisterOp::Write(Some(2))),
+            op(100, Some(110), RegisterOp::Read(Some(1))),
+        ];
+        assert!(matches!(check(ops), CheckResult::Ok));
+
+        // But not before it is invoked.
+        let ops = vec![
+            op(0, Some(10), RegisterOp::Write(Some(1))),
+            op(20, Some(30), RegisterOp::Read(Some(2))),
+            op(40, None, RegisterOp::Write(Some(2))),
+        ];
+        assert!(matches!(check(ops), CheckResult::Illegal));
+
+        // And it could not be undone once it is observed.
+        let ops = vec![
+            op(0, Some(10), RegisterOp::Write(Some(1))),
+            op(20, None, RegisterOp::Write(Some(2))),
+            op(100, Some(110), RegisterOp::Read(Some(2))),
+            op(120, Some(130), RegisterOp::Read(Some(1))),
+        ];
+        assert!(matches!(check(ops), CheckResult::Illegal));
+    }
+
+    #[test]
+    fn concurrent_ops() {
+        // The concurrent writes could be linearized in either order.
+        let ops = vec![
+            op(0, Some(50), RegisterOp::Write(Some(1))),
+            op(10, Some(60), RegisterOp::Write(Some(2))),
+            op(70, Some(80), RegisterOp::Read(Some(1))),
+        ];
+        assert!(matches!(check(ops), CheckResult::Ok));
+
+        // The reads overlapping a write observe either the old or the new value, but not the
+        // old one after the new one.
+        let ops = vec![
+            op(0, Some(10), RegisterOp::Write(None)),
+            op(20, Some(100), RegisterOp::Write(Some(1))),
+            op(30, Some(40), RegisterOp::Read(None)),
+            op(50, Some(60), RegisterOp::Read(Some(1))),
+        ];
+        assert!(matches!(check(ops), CheckResult::Ok));
+        let ops = vec![
+            op(0, Some(10), RegisterOp::Write(None)),
+            op(20, Some(100), RegisterOp::Write(Some(1))),
+            op(30, Some(40), RegisterOp::Read(Some(1))),
+            op(50, Some(60), RegisterOp::Read(None)),
+        ];
+        assert!(matches!(check(ops), CheckResult::Illegal));
+    }
+}
@@ -0,0 +1,108 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{
+    base::{ExecCtx, Reader, Writers},
+    gen::Snapshot,
+    reader::TrackerSnapshot,
+};
+
+/// The progress of all writers and readers, which is enough to resume a run after the
+/// supervisor restarts.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Checkpoint {
+    pub base_seed: u64,
+    /// The run id of the run, the checkpoints saved before it is introduced are of the keys not
+    /// prefixed.
+    #[serde(default)]
+    pub run_id: String,
+    pub writers: Vec<Snapshot>,
+    #[serde(default)]
+    pub trackers: Vec<TrackerSnapshot>,
+}
+
+impl Checkpoint {
+    pub fn load(path: &PathBuf) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Save the checkpoint to a temporary file then rename it, so a crash during saving never
+    /// corrupts the previous checkpoint.
+    pub fn save(&self, path: &PathBuf) -> Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+pub struct Checkpointer {
+    base_seed: u64,
+    run_id: String,
+    path: PathBuf,
+    interval: Duration,
+    writers: Writers,
+    readers: Vec<Arc<dyn Reader>>,
+}
+
+impl Checkpointer {
+    pub fn new(
+        base_seed: u64,
+        run_id: String,
+        path: PathBuf,
+        interval: Duration,
+        writers: Writers,
+        readers: Vec<Arc<dyn Reader>>,
+    ) -> Self {
+        Checkpointer {
+            base_seed,
+            run_id,
+            path,
+            interval,
+            writers,
+            readers,
+        }
+    }
+
+    async fn save(&self) {
+        // The readers are saved before the writers, so a tracker never goes ahead of its writer.
+        let mut trackers = vec![];
+        for reader in &self.readers {
+            trackers.extend(reader.snapshot().await);
+        }
+        let checkpoint = Checkpoint {
+            base_seed: self.base_seed,
+            run_id: self.run_id.clone(),
+            writers: self
+                .writers
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|w| w.snapshot())
+                .collect(),
+            trackers,
+        };
+        match checkpoint.save(&self.path) {
+            Ok(()) => info!("save checkpoint to {}", self.path.display()),
+            Err(e) => error!("save checkpoint to {}: {}", self.path.display(), e),
+        }
+    }
+}
+
+#[super::async_trait]
+impl super::base::Task for Checkpointer {
+    async fn run(&self, mut ctx: ExecCtx) {
+        while ctx
+            .wait_until_timeout_or_shutdown(self.interval)
+            .await
+            .is_some()
+        {
+            self.save().await;
+        }
+        self.save().await;
+    }
+}
@@ -0,0 +1,307 @@
+use std::{fs::OpenOptions, path::PathBuf, process::Stdio, time::Duration};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    net::TcpStream,
+    process::{Child, Command},
+    sync::Mutex,
+    time::Instant,
+};
+use tracing::{error, info, warn};
+
+use crate::base::{deserialize_secs, ExecCtx};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ClusterConfig {
+    /// The path of the `engula` binary.
+    pub binary: PathBuf,
+    /// The output of each node is appended to `<log_dir>/<name>.log`.
+    pub log_dir: PathBuf,
+    /// The duration to wait for a node to accept connections after it is started.
+    #[serde(deserialize_with = "deserialize_secs")]
+    pub ready_timeout_secs: u64,
+    /// Restart a node once it exits without being stopped by the supervisor.
+    pub restart_on_exit: bool,
+    pub nodes: Vec<NodeConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct NodeConfig {
+    pub name: String,
+    /// The binary of the node, it overrides the one of the cluster, eg to run a mixed-version
+    /// cluster.
+    pub binary: Option<PathBuf>,
+    pub addr: String,
+    pub data_dir: PathBuf,
+    /// The arguments of the binary, `{name}`, `{addr}` and `{data_dir}` are replaced by the
+    /// values of the node.
+    pub args: Vec<String>,
+    /// A spare node is not started with the cluster, it joins the cluster as a new member by
+    /// the membership fault.
+    pub spare: Option<bool>,
+}
+
+impl NodeConfig {
+    #[inline]
+    fn is_spare(&self) -> bool {
+        self.spare.unwrap_or_default()
+    }
+}
+
+struct Node {
+    cfg: NodeConfig,
+    /// The process of the node, `None` if it is stopped by the supervisor or exited.
+    child: Option<Child>,
+    /// The extra environment variables of the process, eg to run it under libfaketime.
+    envs: Vec<(String, String)>,
+}
+
+/// Spawn, monitor and restart the local `engula` server processes, so the cluster and the
+/// workload could be brought up in one shot.
+pub struct Cluster {
+    cfg: ClusterConfig,
+    nodes: Mutex<Vec<Node>>,
+}
+
+impl Cluster {
+    pub fn new(cfg: ClusterConfig) -> Self {
+        let nodes = cfg
+            .nodes
+            .iter()
+            .map(|cfg| Node {
+                cfg: cfg.clone(),
+                child: None,
+                envs: vec![],
+            })
+            .collect();
+        Cluster {
+            cfg,
+            nodes: Mutex::new(nodes),
+        }
+    }
+
+    pub fn addrs(&self) -> Vec<String> {
+        self.cfg
+            .nodes
+            .iter()
+            .filter(|n| !n.is_spare())
+            .map(|n| n.addr.clone())
+            .collect()
+    }
+
+    /// Return the names of the nodes except the spares.
+    pub fn node_names(&self) -> Vec<String> {
+        self.cfg
+            .nodes
+            .iter()
+            .filter(|n| !n.is_spare())
+            .map(|n| n.name.clone())
+            .collect()
+    }
+
+    /// Return the names of the spare nodes which are not running.
+    pub async fn idle_spare_names(&self) -> Vec<String> {
+        self.nodes
+            .lock()
+            .await
+            .iter()
+            .filter(|n| n.cfg.is_spare() && n.child.is_none())
+            .map(|n| n.cfg.name.clone())
+            .collect()
+    }
+
+    pub fn node_configs(&self) -> Vec<NodeConfig> {
+        self.cfg.nodes.clone()
+    }
+
+    pub fn node_config(&self, name: &str) -> Option<NodeConfig> {
+        self.cfg.nodes.iter().find(|n| n.name == name).cloned()
+    }
+
+    /// Start all nodes except the spares, in the order of the config, and wait until they are
+    /// ready.
+    pub async fn start(&self) -> Result<()> {
+        std::fs::create_dir_all(&self.cfg.log_dir)?;
+        let names = self.node_names();
+        for name in &names {
+            self.start_node(name).await?;
+        }
+        info!("cluster of {} nodes is ready", names.len());
+        Ok(())
+    }
+
+    /// Start the node and wait until it accepts connections.
+    pub async fn start_node(&self, name: &str) -> Result<()> {
+        let addr = {
+            let mut nodes = self.nodes.lock().await;
+            let node = find_node(&mut nodes, name)?;
+            if node.child.is_some() {
+                anyhow::bail!("node {} is already running", name);
+            }
+            node.child = Some(self.spawn(&node.cfg, &node.envs)?);
+            node.cfg.addr.clone()
+        };
+        self.wait_ready(name, &addr).await
+    }
+
+    /// Kill the node by SIGKILL, it is not restarted until `start_node` is called.
+    pub async fn kill_node(&self, name: &str) -> Result<()> {
+        let mut nodes = self.nodes.lock().await;
+        let node = find_node(&mut nodes, name)?;
+        if let Some(mut child) = node.child.take() {
+            child.kill().await?;
+            info!("node {} is killed", name);
+        }
+        Ok(())
+    }
+
+    /// Send the signal to the node, eg `STOP` and `CONT` to pause and resume it.
+    pub async fn signal_node(&self, name: &str, signal: &str) -> Result<()> {
+        let pid = match self.node_pid(name).await {
+            Some(pid) => pid,
+            None => anyhow::bail!("node {} is not running", name),
+        };
+        let status = Command::new("kill")
+            .args([&format!("-{}", signal), &pid.to_string()])
+            .status()
+            .await?;
+        if !status.success() {
+            anyhow::bail!("send SIG{} to node {}: {}", signal, name, status);
+        }
+        info!("send SIG{} to node {}", signal, name);
+        Ok(())
+    }
+
+    /// Kill all nodes.
+    pub async fn stop(&self) {
+        for node in &self.cfg.nodes {
+            if let Err(e) = self.kill_node(&node.name).await {
+                error!("stop node {}: {}", node.name, e);
+            }
+        }
+        info!("cluster is stopped");
+    }
+
+    /// Return the pid of the node, `None` if it is not running.
+    pub async fn node_pid(&self, name: &str) -> Option<u32> {
+        let mut nodes = self.nodes.lock().await;
+        let node = find_node(&mut nodes, name).ok()?;
+        node.child.as_ref().and_then(Child::id)
+    }
+
+    /// Kill the node and start it again with the extra environment variables, they are kept if
+    /// the node is restarted later.
+    pub async fn restart_node_with_envs(
+        &self,
+        name: &str,
+        envs: Vec<(String, String)>,
+    ) -> Result<()> {
+        self.kill_node(name).await?;
+        find_node(&mut self.nodes.lock().await, name)?.envs = envs;
+        self.start_node(name).await
+    }
+
+    /// Kill the node and start it again with the binary, it is kept if the node is restarted
+    /// later.
+    pub async fn upgrade_node(&self, name: &str, binary: PathBuf) -> Result<()> {
+        self.kill_node(name).await?;
+        find_node(&mut self.nodes.lock().await, name)?.cfg.binary = Some(binary);
+        self.start_node(name).await
+    }
+
+    fn spawn(&self, cfg: &NodeConfig, envs: &[(String, String)]) -> Result<Child> {
+        let path = self.cfg.log_dir.join(format!("{}.log", cfg.name));
+        let log = OpenOptions::new().create(true).append(true).open(&path)?;
+        let args = cfg
+            .args
+            .iter()
+            .map(|arg| {
+                arg.replace("{name}", &cfg.name)
+                    .replace("{addr}", &cfg.addr)
+                    .replace("{data_dir}", &cfg.data_dir.to_string_lossy())
+            })
+            .collect::<Vec<_>>();
+        let binary = cfg.binary.as_ref().unwrap_or(&self.cfg.binary);
+        let child = Command::new(binary)
+            .args(&args)
+            .envs(envs.iter().cloned())
+            .stdin(Stdio::null())
+            .stdout(Stdio::from(log.try_clone()?))
+            .stderr(Stdio::from(log))
+            .kill_on_drop(true)
+            .spawn()?;
+        info!(
+            "node {} is started, binary {}, pid {:?}, log {}",
+            cfg.name,
+            binary.display(),
+            child.id(),
+            path.display()
+        );
+        Ok(child)
+    }
+
+    async fn wait_ready(&self, name: &str, addr: &str) -> Result<()> {
+        let deadline = Instant::now() + Duration::from_secs(self.cfg.ready_timeout_secs);
+        loop {
+            match TcpStream::connect(addr).await {
+                Ok(_) => {
+                    info!("node {} is ready at {}", name, addr);
+                    return Ok(());
+                }
+                Err(e) if Instant::now() >= deadline => {
+                    anyhow::bail!("node {} is not ready at {}: {}", name, addr, e);
+                }
+                Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+            }
+        }
+    }
+
+    /// Reap the exited nodes, and restart them if `restart_on_exit` is enabled.
+    async fn reap(&self) {
+        let mut exited = vec![];
+        {
+            let mut nodes = self.nodes.lock().await;
+            for node in nodes.iter_mut() {
+                let status = match node.child.as_mut().map(Child::try_wait) {
+                    Some(Ok(Some(status))) => status,
+                    Some(Err(e)) => {
+                        error!("wait node {}: {}", node.cfg.name, e);
+                        continue;
+                    }
+                    _ => continue,
+                };
+                warn!("node {} exits unexpectedly, {}", node.cfg.name, status);
+                node.child = None;
+                if self.cfg.restart_on_exit {
+                    exited.push(node.cfg.name.clone());
+                }
+            }
+        }
+        for name in exited {
+            if let Err(e) = self.start_node(&name).await {
+                error!("restart node {}: {}", name, e);
+            }
+        }
+    }
+}
+
+fn find_node<'a>(nodes: &'a mut [Node], name: &str) -> Result<&'a mut Node> {
+    match nodes.iter_mut().find(|n| n.cfg.name == name) {
+        Some(node) => Ok(node),
+        None => anyhow::bail!("node {} is not found in the cluster", name),
+    }
+}
+
+#[super::async_trait]
+impl super::base::Task for Cluster {
+    async fn run(&self, mut ctx: ExecCtx) {
+        while ctx
+            .wait_until_timeout_or_shutdown(Duration::from_secs(1))
+            .await
+            .is_some()
+        {
+            self.reap().await;
+        }
+    }
+}
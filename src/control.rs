@@ -0,0 +1,449 @@
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+
+use anyhow::Result;
+use engula_client::Collection;
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    signal::unix::{signal, SignalKind},
+    sync::mpsc,
+    task::JoinHandle,
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    audit::Auditor,
+    base::{Config, ExecCtx, ReaderOptions, Seeds, Task, Writer as _, WriterOptions, Writers},
+    chaos::FaultLog,
+    gen::Snapshot,
+    reader::{Reader, TrackerSnapshot},
+    writer::Writer,
+};
+
+/// The commands to change the running workload.
+#[derive(Debug)]
+pub enum Command {
+    AddWriters(usize),
+    RemoveWriters(usize),
+    /// Pause the writers after their in-flight ops, the readers keep verifying.
+    Pause,
+    Resume,
+    /// Stop the run, the writers are stopped before the final verification.
+    Stop,
+}
+
+impl std::str::FromStr for Command {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let cmd = parts.next().unwrap_or_default();
+        let num = match parts.next() {
+            Some(num) => num.parse::<usize>()?,
+            None => 1,
+        };
+        match cmd {
+            "add" => Ok(Command::AddWriters(num)),
+            "remove" => Ok(Command::RemoveWriters(num)),
+            "pause" => Ok(Command::Pause),
+            "resume" => Ok(Command::Resume),
+            "stop" => Ok(Command::Stop),
+            _ => anyhow::bail!(
+                "unknown command {:?}, expect `add [num]`, `remove [num]`, `pause`, `resume` or `stop`",
+                s
+            ),
+        }
+    }
+}
+
+/// The collections written by a group of writers.
+#[derive(Clone)]
+pub struct WriterGroup {
+    pub collection: Collection,
+    pub index_collection: Option<Collection>,
+    /// The number of writers of the group, the groups take the consecutive indexes of writers in
+    /// order.
+    pub writers: usize,
+}
+
+/// Owns the writers and readers of a run, and changes them according to the received commands.
+pub struct Controller {
+    seeds: Seeds,
+    generator: Config,
+    /// The writers added by commands belong to the first group.
+    groups: Vec<WriterGroup>,
+    writer_opts: WriterOptions,
+    reader_opts: ReaderOptions,
+    /// The writers and readers are stopped separately, so the cluster could settle before the
+    /// final verification.
+    writer_ctx: Option<ExecCtx>,
+    reader_ctx: Option<ExecCtx>,
+
+    readers_per_writer: usize,
+    next_writer_index: usize,
+    writers: Writers,
+    writer_handles: Vec<(usize, JoinHandle<()>)>,
+    readers: Vec<Arc<dyn crate::base::Reader>>,
+    reader_handles: Vec<JoinHandle<()>>,
+}
+
+impl Controller {
+    pub fn new(
+        seeds: Seeds,
+        generator: Config,
+        groups: Vec<WriterGroup>,
+        writer_opts: WriterOptions,
+        reader_opts: ReaderOptions,
+    ) -> Self {
+        assert!(!groups.is_empty(), "there is no group of writers");
+        Controller {
+            seeds,
+            generator,
+            groups,
+            writer_opts,
+            reader_opts,
+            writer_ctx: Some(ExecCtx::new()),
+            reader_ctx: Some(ExecCtx::new()),
+            readers_per_writer: 1,
+            next_writer_index: 0,
+            writers: Writers::default(),
+            writer_handles: vec![],
+            readers: vec![],
+            reader_handles: vec![],
+        }
+    }
+
+    #[inline]
+    pub fn writers(&self) -> Writers {
+        self.writers.clone()
+    }
+
+    #[inline]
+    pub fn readers(&self) -> Vec<Arc<dyn crate::base::Reader>> {
+        self.readers.clone()
+    }
+
+    /// Spawn a writer with the next unused index, the index is never reused because it is a part
+    /// of the generated keys.
+    pub fn spawn_writer(&mut self, snapshot: Option<&Snapshot>) -> Arc<Writer> {
+        let idx = match snapshot {
+            Some(snapshot) => snapshot.writer as usize,
+            None => self.next_writer_index,
+        };
+        self.next_writer_index = self.next_writer_index.max(idx + 1);
+
+        let seed = self.seeds.writer(idx);
+        let group = self.groups[self.group_index_of(idx)].clone();
+        let writer = Arc::new(Writer::new(
+            idx,
+            seed,
+            self.generator.clone(),
+            group.collection,
+            group.index_collection,
+            self.writer_opts.clone(),
+        ));
+        if let Some(snapshot) = snapshot {
+            writer.restore(snapshot);
+        }
+        self.writers.lock().unwrap().push(writer.clone());
+
+        let cloned_writer = writer.clone();
+        let cloned_ctx = self
+            .writer_ctx
+            .as_ref()
+            .expect("writers are stopped")
+            .clone();
+        let handle = tokio::spawn(async move {
+            cloned_writer.run(cloned_ctx).await;
+        });
+        self.writer_handles.push((idx, handle));
+        writer
+    }
+
+    /// Spawn readers, each writer is assigned to `readers_per_writer` consecutive readers in round
+    /// robin, and each of them replays the writer independently.
+    pub fn spawn_readers(
+        &mut self,
+        num_readers: usize,
+        readers_per_writer: usize,
+        resume: Option<&[TrackerSnapshot]>,
+    ) {
+        self.readers_per_writer = readers_per_writer;
+        let writers = self.writers.lock().unwrap().clone();
+        for idx in 0..num_readers {
+            let traced_writers = writers
+                .iter()
+                .enumerate()
+                .filter(|(pos, _)| (0..readers_per_writer).any(|j| (pos + j) % num_readers == idx))
+                .map(|(_, w)| w.clone())
+                .collect();
+            let mut reader = Reader::new(idx, traced_writers, self.reader_opts.clone());
+            if let Some(snapshots) = resume {
+                reader.resume(snapshots);
+            }
+            let reader: Arc<dyn crate::base::Reader> = Arc::new(reader);
+            self.readers.push(reader.clone());
+            let cloned_ctx = self
+                .reader_ctx
+                .as_ref()
+                .expect("readers are stopped")
+                .clone();
+            let handle = tokio::spawn(async move {
+                reader.run(cloned_ctx).await;
+            });
+            self.reader_handles.push(handle);
+        }
+    }
+
+    /// Handle the commands until the channel is closed or the run is stopped.
+    pub async fn run(&mut self, mut receiver: mpsc::UnboundedReceiver<Command>) {
+        while let Some(cmd) = receiver.recv().await {
+            info!("controller receive command {:?}", cmd);
+            match cmd {
+                Command::Stop => return,
+                Command::AddWriters(num) => {
+                    for _ in 0..num {
+                        self.add_writer().await;
+                    }
+                }
+                Command::RemoveWriters(num) => {
+                    for _ in 0..num {
+                        self.remove_writer().await;
+                    }
+                }
+                Command::Pause => {
+                    self.writer_opts.paused.store(true, Ordering::Release);
+                    continue;
+                }
+                Command::Resume => {
+                    self.writer_opts.paused.store(false, Ordering::Release);
+                    continue;
+                }
+            }
+            self.rebalance().await;
+        }
+    }
+
+    /// Stop the writers and wait until they exit.
+    pub async fn stop_writers(&mut self) {
+        self.writer_ctx.take();
+        for (_, handle) in std::mem::take(&mut self.writer_handles) {
+            handle.await.unwrap_or_default();
+        }
+        for writer in self.writers.lock().unwrap().iter() {
+            let latencies = writer.latencies();
+            info!(
+                "writer {} is stopped at step {}, put latency: {}, delete latency: {}, get latency: {}, {}",
+                writer.index(),
+                writer.current_step(),
+                latencies.put,
+                latencies.delete,
+                latencies.get,
+                writer.error_stats(),
+            );
+        }
+    }
+
+    /// Stop the readers and wait until they exit.
+    pub async fn stop_readers(&mut self) {
+        self.reader_ctx.take();
+        for handle in std::mem::take(&mut self.reader_handles) {
+            handle.await.unwrap_or_default();
+        }
+        for reader in &self.readers {
+            info!(
+                "reader {} is stopped, get latency: {}, staleness of observed older versions: {}, {}",
+                reader.index(),
+                reader.get_latency(),
+                reader.staleness(),
+                reader.error_stats()
+            );
+        }
+    }
+
+    /// Verify every key of the writers strictly, without any staleness tolerance. It should be
+    /// called after the writers are stopped, and the cluster is given `settle` to converge.
+    pub async fn verify_quiescent(&self, settle: Duration) -> Result<()> {
+        info!("wait {:?} for the cluster to settle", settle);
+        tokio::time::sleep(settle).await;
+        // Each collection is audited with the writers of its group.
+        let mut writers = vec![vec![]; self.groups.len()];
+        for w in self.writers.lock().unwrap().iter() {
+            writers[self.group_index_of(w.index())].push((w.index(), w.current_step()));
+        }
+        for (group, writers) in self.groups.iter().zip(writers) {
+            if writers.is_empty() {
+                continue;
+            }
+            let auditor = Auditor::new(
+                self.seeds.clone(),
+                self.generator.clone(),
+                group.collection.clone(),
+                self.writer_opts.op_timeout,
+            );
+            auditor.audit(&writers).await?;
+        }
+        Ok(())
+    }
+
+    /// Return the index of the group of the writer, see [`WriterGroup::writers`].
+    fn group_index_of(&self, idx: usize) -> usize {
+        let mut first = 0;
+        for (i, group) in self.groups.iter().enumerate() {
+            if idx < first + group.writers {
+                return i;
+            }
+            first += group.writers;
+        }
+        0
+    }
+
+    async fn add_writer(&mut self) {
+        let writer = self.spawn_writer(None);
+        info!("controller add writer {}", writer.index());
+        let readers = self.least_loaded_readers(self.readers_per_writer).await;
+        if readers.is_empty() {
+            warn!("there is no reader to trace writer {}", writer.index());
+        }
+        for reader in readers {
+            reader.add_writer(writer.clone()).await;
+        }
+    }
+
+    /// Remove the latest added writer. The writer is untraced before it is stopped, so the
+    /// in-flight op is never verified.
+    async fn remove_writer(&mut self) {
+        let (idx, handle) = match self.writer_handles.pop() {
+            Some(v) => v,
+            None => {
+                warn!("there is no writer to remove");
+                return;
+            }
+        };
+        for reader in &self.readers {
+            reader.remove_writer(idx).await;
+        }
+        handle.abort();
+        let mut writers = self.writers.lock().unwrap();
+        if let Some(writer) = writers.iter().find(|w| w.index() == idx) {
+            let latencies = writer.latencies();
+            info!(
+                "controller remove writer {} at step {}, put latency: {}, delete latency: {}",
+                idx,
+                writer.current_step(),
+                latencies.put,
+                latencies.delete,
+            );
+        }
+        writers.retain(|w| w.index() != idx);
+    }
+
+    /// Move writers from the most loaded reader to the least loaded one, until the difference of
+    /// them is at most one.
+    async fn rebalance(&mut self) {
+        loop {
+            let mut loads = vec![];
+            for reader in &self.readers {
+                loads.push((reader.clone(), reader.traced_writers().await));
+            }
+            let (max_reader, max_writers) = match loads.iter().max_by_key(|(_, w)| w.len()) {
+                Some(v) => v.clone(),
+                None => return,
+            };
+            let (min_reader, min_writers) = loads.iter().min_by_key(|(_, w)| w.len()).unwrap();
+            if max_writers.len() <= min_writers.len() + 1 {
+                return;
+            }
+            // The writer should not be traced by the least loaded reader already.
+            let writer_index = match max_writers.iter().rev().find(|w| !min_writers.contains(w)) {
+                Some(writer_index) => *writer_index,
+                None => return,
+            };
+            if let Some(writer) = max_reader.remove_writer(writer_index).await {
+                info!(
+                    "controller move writer {} from reader {} to reader {}",
+                    writer_index,
+                    max_reader.index(),
+                    min_reader.index()
+                );
+                min_reader.add_writer(writer).await;
+            }
+        }
+    }
+
+    async fn least_loaded_readers(&self, num: usize) -> Vec<Arc<dyn crate::base::Reader>> {
+        let mut loads = vec![];
+        for reader in &self.readers {
+            loads.push((reader.traced_writers().await.len(), reader.clone()));
+        }
+        loads.sort_by_key(|(load, _)| *load);
+        loads.into_iter().take(num).map(|(_, r)| r).collect()
+    }
+}
+
+/// The exit code of the run aborted by the second signal, it is the conventional one of a
+/// process interrupted by SIGINT, and different from the ones of [`crate::base::FailureKind`].
+const ABORT_EXIT_CODE: i32 = 130;
+
+/// Send `stop` to the controller at the first SIGINT or SIGTERM, so the run is stopped with the
+/// final verification. The process exits immediately at the second one, after recovering the
+/// active faults in the best effort.
+pub async fn stop_on_signals(sender: mpsc::UnboundedSender<Command>, fault_log: Arc<FaultLog>) {
+    let (mut interrupt, mut terminate) = match (
+        signal(SignalKind::interrupt()),
+        signal(SignalKind::terminate()),
+    ) {
+        (Ok(interrupt), Ok(terminate)) => (interrupt, terminate),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("install signal handlers: {}", e);
+            return;
+        }
+    };
+    let mut stopping = false;
+    loop {
+        tokio::select! {
+            _ = interrupt.recv() => {}
+            _ = terminate.recv() => {}
+        }
+        if stopping {
+            warn!("receive signal again, recover the active faults and exit immediately");
+            // The recovery might hang on an unreachable node, so it is bounded.
+            if tokio::time::timeout(Duration::from_secs(10), fault_log.recover_active())
+                .await
+                .is_err()
+            {
+                error!("recover the active faults: timeout");
+            }
+            std::process::exit(ABORT_EXIT_CODE);
+        }
+        info!("receive signal, stop the run gracefully, send it again to exit immediately");
+        stopping = true;
+        // The run might be stopping already.
+        sender.send(Command::Stop).unwrap_or_default();
+    }
+}
+
+/// Read commands from stdin line by line, and send them to the controller.
+pub async fn read_commands_from_stdin(sender: mpsc::UnboundedSender<Command>) {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        match lines.next_line().await {
+            Ok(Some(line)) if line.trim().is_empty() => continue,
+            Ok(Some(line)) => match line.parse::<Command>() {
+                Ok(cmd) => {
+                    if sender.send(cmd).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => error!("parse command: {}", e),
+            },
+            Ok(None) => return,
+            Err(e) => {
+                error!("read command from stdin: {}", e);
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, Mutex};
+use tracing::{info, warn};
+
+use crate::{
+    base::ExecCtx,
+    chaos::FaultLog,
+    history::{History, OpEvent, OpKind, OpOutcome},
+};
+
+/// The names of all faults, see `Fault::name`.
+const FAULTS: &[&str] = &[
+    "kill",
+    "pause",
+    "partition",
+    "netem",
+    "toxic",
+    "clock_skew",
+    "disk_full",
+    "disk_error",
+    "membership",
+    "io_throttle",
+    "cpu_quota",
+    "memory_limit",
+    "upgrade",
+];
+
+/// The kinds of ops interleaved with a fault, `<op>/<outcome>`.
+const INTERLEAVINGS: &[&str] = &[
+    "put/ok",
+    "put/timeout",
+    "put/error",
+    "delete/ok",
+    "delete/timeout",
+    "delete/error",
+    "get/ok",
+    "get/timeout",
+    "get/error",
+];
+
+/// The faults exercised by one or more runs.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Coverage {
+    pub runs: u64,
+    /// The coverage of each fault, by its name.
+    pub faults: BTreeMap<String, FaultCoverage>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct FaultCoverage {
+    pub injections: u64,
+    /// The number of injections on each node.
+    pub targets: BTreeMap<String, u64>,
+    /// The number of ops invoked while the fault is active, by `<op>/<outcome>`.
+    pub ops: BTreeMap<String, u64>,
+}
+
+impl Coverage {
+    fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Coverage::default());
+        }
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    fn merge(&mut self, other: Coverage) {
+        self.runs += other.runs;
+        for (name, fault) in other.faults {
+            let entry = self.faults.entry(name).or_default();
+            entry.injections += fault.injections;
+            for (target, num) in fault.targets {
+                *entry.targets.entry(target).or_default() += num;
+            }
+            for (op, num) in fault.ops {
+                *entry.ops.entry(op).or_default() += num;
+            }
+        }
+    }
+
+    /// Log the exercised combinations, and the ones never exercised, so the next runs could be
+    /// steered towards them.
+    fn report(&self, nodes: &[String]) {
+        info!("chaos coverage of {} runs:", self.runs);
+        let mut untested = vec![];
+        for name in FAULTS {
+            let fault = match self.faults.get(*name) {
+                Some(fault) if fault.injections > 0 => fault,
+                _ => {
+                    untested.push(*name);
+                    continue;
+                }
+            };
+            let missing_nodes = nodes
+                .iter()
+                .filter(|n| !fault.targets.contains_key(*n))
+                .collect::<Vec<_>>();
+            let missing_ops = INTERLEAVINGS
+                .iter()
+                .filter(|op| !fault.ops.contains_key(**op))
+                .collect::<Vec<_>>();
+            info!(
+                "  {}: {} injections, targets {:?}, ops {:?}, never targeted {:?}, never interleaved with {:?}",
+                name, fault.injections, fault.targets, fault.ops, missing_nodes, missing_ops
+            );
+        }
+        info!("  never injected: {:?}", untested);
+    }
+}
+
+/// Track the faults injected in this run, and the ops interleaved with them. The events are
+/// received from the history.
+pub struct CoverageTracker {
+    receiver: Mutex<broadcast::Receiver<Arc<OpEvent>>>,
+    log: Arc<FaultLog>,
+    ops: std::sync::Mutex<BTreeMap<(String, String), u64>>,
+}
+
+impl CoverageTracker {
+    pub fn new(history: &History, log: Arc<FaultLog>) -> Self {
+        CoverageTracker {
+            receiver: Mutex::new(history.subscribe()),
+            log,
+            ops: std::sync::Mutex::default(),
+        }
+    }
+
+    fn handle(&self, event: &OpEvent) {
+        let faults = self.log.active_at(event.invoked_at_us);
+        if faults.is_empty() {
+            return;
+        }
+        let kind = match event.kind {
+            OpKind::Put => "put",
+            OpKind::Delete => "delete",
+            OpKind::Get => "get",
+        };
+        let outcome = match event.outcome {
+            OpOutcome::Ok => "ok",
+            OpOutcome::Timeout => "timeout",
+            OpOutcome::Error(_) => "error",
+        };
+        let op = format!("{}/{}", kind, outcome);
+        let mut ops = self.ops.lock().unwrap();
+        for fault in faults {
+            *ops.entry((fault, op.clone())).or_default() += 1;
+        }
+    }
+
+    /// Report the coverage of this run, merged with the previous runs recorded in `path`, and
+    /// save it back.
+    pub fn finish(&self, path: Option<&PathBuf>, nodes: &[String]) -> Result<()> {
+        let mut coverage = Coverage {
+            runs: 1,
+            ..Default::default()
+        };
+        for window in self.log.windows() {
+            let fault = coverage.faults.entry(window.fault).or_default();
+            fault.injections += 1;
+            for target in window.targets {
+                *fault.targets.entry(target).or_default() += 1;
+            }
+        }
+        for ((fault, op), num) in self.ops.lock().unwrap().iter() {
+            let fault = coverage.faults.entry(fault.clone()).or_default();
+            *fault.ops.entry(op.clone()).or_default() += num;
+        }
+        if let Some(path) = path {
+            let mut total = Coverage::load(path)?;
+            total.merge(coverage);
+            total.save(path)?;
+            coverage = total;
+        }
+        coverage.report(nodes);
+        Ok(())
+    }
+}
+
+#[super::async_trait]
+impl super::base::Task for CoverageTracker {
+    async fn run(&self, mut ctx: ExecCtx) {
+        let mut receiver = self.receiver.lock().await;
+        loop {
+            tokio::select! {
+                event = receiver.recv() => match event {
+                    Ok(event) => self.handle(&event),
+                    Err(broadcast::error::RecvError::Lagged(num)) => {
+                        warn!("coverage tracker lost {} events", num);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                },
+                result = ctx.wait_until_timeout_or_shutdown(Duration::from_secs(1)) => {
+                    if result.is_none() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
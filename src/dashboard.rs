@@ -0,0 +1,285 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::Stdout,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use ratatui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Direction, Layout},
+    style::{Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Paragraph, Row, Table},
+    Frame, Terminal,
+};
+use tokio::time::Instant;
+use tracing::error;
+
+use crate::{
+    base::{reader_lag, ExecCtx, Reader, Writers},
+    chaos::FaultLog,
+    history::now_us,
+};
+
+const MAX_LOG_LINES: usize = 200;
+
+/// The recent lines of the log, the log is written here instead of stdout when the dashboard is
+/// shown, so they are not interleaved.
+#[derive(Clone, Default)]
+pub struct LogLines {
+    lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl LogLines {
+    /// Return the last `num` lines.
+    fn tail(&self, num: usize) -> Vec<String> {
+        let lines = self.lines.lock().unwrap();
+        lines
+            .iter()
+            .skip(lines.len().saturating_sub(num))
+            .cloned()
+            .collect()
+    }
+}
+
+impl std::io::Write for LogLines {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut lines = self.lines.lock().unwrap();
+        for line in String::from_utf8_lossy(buf).lines() {
+            if lines.len() == MAX_LOG_LINES {
+                lines.pop_front();
+            }
+            lines.push_back(line.to_owned());
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The counters of a task at the last refresh, to compute the rates over the interval.
+#[derive(Clone, Copy, Default)]
+struct Counters {
+    succeeds: usize,
+    failures: usize,
+}
+
+/// A live view of the run in the terminal, it is redrawn every interval:
+///
+/// - the step, the throughput and the error rate of each writer.
+/// - the lag, the verified ops and the error rate of each reader.
+/// - the active faults, and the recent lines of the log.
+///
+/// Only the changed cells are written at each redraw, so the screen does not flicker.
+pub struct Dashboard {
+    writers: Writers,
+    readers: Vec<Arc<dyn Reader>>,
+    fault_log: Arc<FaultLog>,
+    paused: Arc<AtomicBool>,
+    logs: LogLines,
+    interval: Duration,
+}
+
+impl Dashboard {
+    pub fn new(
+        writers: Writers,
+        readers: Vec<Arc<dyn Reader>>,
+        fault_log: Arc<FaultLog>,
+        paused: Arc<AtomicBool>,
+        logs: LogLines,
+    ) -> Self {
+        Dashboard {
+            writers,
+            readers,
+            fault_log,
+            paused,
+            logs,
+            interval: Duration::from_secs(1),
+        }
+    }
+
+    fn draw<B: Backend>(
+        &self,
+        f: &mut Frame<B>,
+        elapsed: Duration,
+        last: &mut HashMap<(&'static str, usize), Counters>,
+    ) {
+        let secs = elapsed.as_secs_f64();
+        let mut rate = |kind: &'static str, index: usize, current: Counters| {
+            let prev = last.insert((kind, index), current).unwrap_or_default();
+            if secs == 0.0 {
+                return (0.0, 0.0);
+            }
+            (
+                (current.succeeds.saturating_sub(prev.succeeds)) as f64 / secs,
+                (current.failures.saturating_sub(prev.failures)) as f64 / secs,
+            )
+        };
+        let header = |cells: [&'static str; 5]| {
+            Row::new(cells).style(Style::default().add_modifier(Modifier::BOLD))
+        };
+        let widths = [Constraint::Length(12); 5];
+
+        let writers = self.writers.lock().unwrap().clone();
+        let writer_rows = writers
+            .iter()
+            .map(|w| {
+                let stats = w.error_stats();
+                let counters = Counters {
+                    succeeds: stats.succeeds(),
+                    failures: stats.failures(),
+                };
+                let (ops, errors) = rate("writer", w.index(), counters);
+                Row::new(vec![
+                    w.index().to_string(),
+                    w.current_step().to_string(),
+                    format!("{:.1}", ops),
+                    format!("{:.1}", errors),
+                    counters.failures.to_string(),
+                ])
+            })
+            .collect::<Vec<_>>();
+
+        let reader_rows = self
+            .readers
+            .iter()
+            .map(|reader| {
+                let lag = reader_lag(reader.index(), &writers);
+                let stats = reader.error_stats();
+                let counters = Counters {
+                    succeeds: stats.succeeds(),
+                    failures: stats.failures(),
+                };
+                let (_, errors) = rate("reader", reader.index(), counters);
+                Row::new(vec![
+                    reader.index().to_string(),
+                    lag.to_string(),
+                    counters.succeeds.to_string(),
+                    format!("{:.1}", errors),
+                    counters.failures.to_string(),
+                ])
+            })
+            .collect::<Vec<_>>();
+
+        let now = now_us();
+        let mut faults = self
+            .fault_log
+            .windows()
+            .into_iter()
+            .filter(|w| w.end_us.is_none())
+            .map(|w| {
+                Spans::from(format!(
+                    "{} on {:?}, for {}s",
+                    w.fault,
+                    w.targets,
+                    now.saturating_sub(w.start_us) / 1_000_000
+                ))
+            })
+            .collect::<Vec<_>>();
+        if faults.is_empty() {
+            faults.push(Spans::from("none"));
+        }
+
+        // The borders take 2 lines, and the headers of tables take 1 more.
+        let height = |lines: usize| Constraint::Length(lines.saturating_add(2) as u16);
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(1),
+                height(writer_rows.len() + 1),
+                height(reader_rows.len() + 1),
+                height(faults.len()),
+                Constraint::Min(3),
+            ])
+            .split(f.size());
+
+        let status = if self.paused.load(Ordering::Acquire) {
+            "paused"
+        } else {
+            "running"
+        };
+        f.render_widget(
+            Paragraph::new(Span::styled(
+                format!("engula supervisor, {}", status),
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+            chunks[0],
+        );
+        f.render_widget(
+            Table::new(writer_rows)
+                .header(header(["WRITER", "STEP", "OPS/S", "ERRORS/S", "ERRORS"]))
+                .block(Block::default().title("WRITERS").borders(Borders::ALL))
+                .widths(&widths),
+            chunks[1],
+        );
+        f.render_widget(
+            Table::new(reader_rows)
+                .header(header(["READER", "LAG", "VERIFIED", "ERRORS/S", "ERRORS"]))
+                .block(Block::default().title("READERS").borders(Borders::ALL))
+                .widths(&widths),
+            chunks[2],
+        );
+        f.render_widget(
+            Paragraph::new(faults).block(
+                Block::default()
+                    .title("ACTIVE FAULTS")
+                    .borders(Borders::ALL),
+            ),
+            chunks[3],
+        );
+        let logs = self
+            .logs
+            .tail(chunks[4].height.saturating_sub(2) as usize)
+            .into_iter()
+            .map(Spans::from)
+            .collect::<Vec<_>>();
+        f.render_widget(
+            Paragraph::new(logs).block(Block::default().title("RECENT LOGS").borders(Borders::ALL)),
+            chunks[4],
+        );
+    }
+}
+
+fn terminal() -> std::io::Result<Terminal<CrosstermBackend<Stdout>>> {
+    let mut terminal = Terminal::new(CrosstermBackend::new(std::io::stdout()))?;
+    terminal.clear()?;
+    terminal.hide_cursor()?;
+    Ok(terminal)
+}
+
+#[super::async_trait]
+impl super::base::Task for Dashboard {
+    async fn run(&self, mut ctx: ExecCtx) {
+        let mut terminal = match terminal() {
+            Ok(terminal) => terminal,
+            Err(e) => {
+                error!("open the terminal of dashboard: {}", e);
+                return;
+            }
+        };
+        let mut last = HashMap::new();
+        let mut last_refresh = Instant::now();
+        loop {
+            let now = Instant::now();
+            let elapsed = now - last_refresh;
+            last_refresh = now;
+            if let Err(e) = terminal.draw(|f| self.draw(f, elapsed, &mut last)) {
+                error!("draw dashboard: {}", e);
+                return;
+            }
+            if ctx
+                .wait_until_timeout_or_shutdown(self.interval)
+                .await
+                .is_none()
+            {
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use std::{path::PathBuf, process::Stdio, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::{net::TcpStream, process::Command, time::Instant};
+use tracing::{error, info};
+
+use crate::{
+    base::deserialize_secs,
+    chaos::{Fault, FaultConfig},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct DockerConfig {
+    /// The path of `docker`, it is looked up in `PATH` if not specified.
+    pub docker: Option<PathBuf>,
+    pub image: String,
+    /// The images of the nodes, they override `image` by the index of node, eg to run a
+    /// mixed-version cluster.
+    pub node_images: Option<Vec<String>>,
+    /// Pull the image before the containers are created.
+    pub pull: bool,
+    /// The bridge network of the containers, it is created if not exists.
+    pub network: String,
+    /// The number of nodes, the container of the i-th node is named `<name_prefix>-<i>`.
+    pub nodes: usize,
+    pub name_prefix: String,
+    /// The port of the i-th node is `base_port + i`, it is published to the same port of
+    /// `listen_host`.
+    pub listen_host: String,
+    pub base_port: u16,
+    /// The arguments of the image, `{name}`, `{port}`, `{addr}` and `{first_addr}` are replaced
+    /// by the name, the port and the published address of the node, and the published address
+    /// of the first node.
+    pub args: Vec<String>,
+    /// The duration to wait for a node to accept connections after it is started.
+    #[serde(deserialize_with = "deserialize_secs")]
+    pub ready_timeout_secs: u64,
+}
+
+/// Bring up the engula cluster in docker containers, so the workload and the chaos could be run
+/// on a laptop in one shot.
+pub struct Docker {
+    cfg: DockerConfig,
+}
+
+impl Docker {
+    pub fn new(cfg: DockerConfig) -> Self {
+        Docker { cfg }
+    }
+
+    pub fn node_names(&self) -> Vec<String> {
+        (0..self.cfg.nodes)
+            .map(|i| format!("{}-{}", self.cfg.name_prefix, i))
+            .collect()
+    }
+
+    /// The published addresses of the nodes, they replace `addrs`.
+    pub fn addrs(&self) -> Vec<String> {
+        (0..self.cfg.nodes).map(|i| self.addr(i)).collect()
+    }
+
+    fn port(&self, i: usize) -> usize {
+        self.cfg.base_port as usize + i
+    }
+
+    fn addr(&self, i: usize) -> String {
+        format!("{}:{}", self.cfg.listen_host, self.port(i))
+    }
+
+    fn index_of(&self, name: &str) -> Result<usize> {
+        match self.node_names().iter().position(|n| n == name) {
+            Some(i) => Ok(i),
+            None => anyhow::bail!("node {} is not found in docker", name),
+        }
+    }
+
+    /// Pull the image, create the network and start the containers, and wait until they are
+    /// ready. The containers left by a previous run are replaced.
+    pub async fn start(&self) -> Result<()> {
+        if self.cfg.pull {
+            let mut images = vec![self.cfg.image.clone()];
+            images.extend(self.cfg.node_images.iter().flatten().cloned());
+            images.sort_unstable();
+            images.dedup();
+            for image in &images {
+                self.docker(&["pull", image]).await?;
+            }
+        }
+        let exists = self
+            .docker(&[
+                "network",
+                "ls",
+                "-q",
+                "-f",
+                &format!("name=^{}$", self.cfg.network),
+            ])
+            .await?;
+        if exists.trim().is_empty() {
+            self.docker(&["network", "create", &self.cfg.network])
+                .await?;
+        }
+        for i in 0..self.cfg.nodes {
+            let image = match &self.cfg.node_images {
+                Some(images) if i < images.len() => &images[i],
+                _ => &self.cfg.image,
+            };
+            self.run_container(i, image).await?;
+        }
+        info!("docker cluster of {} nodes is ready", self.cfg.nodes);
+        Ok(())
+    }
+
+    /// Replace the container of the node by a new one of the image, and wait until it is ready.
+    /// The image is pulled if `pull` is enabled.
+    pub async fn upgrade_node(&self, name: &str, image: &str) -> Result<()> {
+        if self.cfg.pull {
+            self.docker(&["pull", image]).await?;
+        }
+        self.run_container(self.index_of(name)?, image).await
+    }
+
+    async fn run_container(&self, i: usize, image: &str) -> Result<()> {
+        let name = format!("{}-{}", self.cfg.name_prefix, i);
+        self.docker(&["rm", "-f", &name]).await?;
+        let publish = format!("{}:{}:{}", self.cfg.listen_host, self.port(i), self.port(i));
+        let mut args = vec![
+            "run",
+            "-d",
+            "--name",
+            &name,
+            "--hostname",
+            &name,
+            "--network",
+            &self.cfg.network,
+            "-p",
+            &publish,
+            image,
+        ]
+        .into_iter()
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+        args.extend(self.cfg.args.iter().map(|arg| {
+            arg.replace("{name}", &name)
+                .replace("{port}", &self.port(i).to_string())
+                .replace("{addr}", &self.addr(i))
+                .replace("{first_addr}", &self.addr(0))
+        }));
+        let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+        self.docker(&args).await?;
+        info!("container {} of image {} is started", name, image);
+        self.wait_ready(&name).await
+    }
+
+    /// Remove all containers, the network is kept for the next run.
+    pub async fn stop(&self) {
+        for name in self.node_names() {
+            if let Err(e) = self.docker(&["rm", "-f", &name]).await {
+                error!("remove container {}: {}", name, e);
+            }
+        }
+        info!("docker cluster is stopped");
+    }
+
+    async fn wait_ready(&self, name: &str) -> Result<()> {
+        let addr = self.addr(self.index_of(name)?);
+        let deadline = Instant::now() + Duration::from_secs(self.cfg.ready_timeout_secs);
+        loop {
+            match TcpStream::connect(&addr).await {
+                Ok(_) => {
+                    info!("node {} is ready at {}", name, addr);
+                    return Ok(());
+                }
+                Err(e) if Instant::now() >= deadline => {
+                    anyhow::bail!("node {} is not ready at {}: {}", name, addr, e);
+                }
+                Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+            }
+        }
+    }
+
+    async fn docker(&self, args: &[&str]) -> Result<String> {
+        let docker = self
+            .cfg
+            .docker
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("docker"));
+        let output = Command::new(docker)
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .await?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "docker {:?}: {}, {}",
+                args,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+pub fn build_fault(cfg: &FaultConfig, docker: Arc<Docker>) -> Result<Arc<dyn Fault>> {
+    let kind = match cfg {
+        FaultConfig::Kill => ContainerFault::Kill,
+        FaultConfig::Pause => ContainerFault::Pause,
+        FaultConfig::Partition { .. } => ContainerFault::Disconnect,
+        _ => anyhow::bail!("the fault {:?} is not supported in docker", cfg),
+    };
+    Ok(Arc::new(DockerFault { docker, kind }))
+}
+
+#[derive(Clone, Copy)]
+enum ContainerFault {
+    /// Kill the containers, and start them again once the fault is recovered.
+    Kill,
+    /// Freeze the processes of the containers.
+    Pause,
+    /// Disconnect the containers from the network, so they are isolated from the others and
+    /// the workload.
+    Disconnect,
+}
+
+struct DockerFault {
+    docker: Arc<Docker>,
+    kind: ContainerFault,
+}
+
+#[super::async_trait]
+impl Fault for DockerFault {
+    fn name(&self) -> &'static str {
+        match self.kind {
+            ContainerFault::Kill => "kill",
+            ContainerFault::Pause => "pause",
+            ContainerFault::Disconnect => "partition",
+        }
+    }
+
+    async fn inject(&self, targets: &[String]) -> Result<()> {
+        let network = self.docker.cfg.network.as_str();
+        for target in targets {
+            let target = target.as_str();
+            match self.kind {
+                ContainerFault::Kill => self.docker.docker(&["kill", target]).await?,
+                ContainerFault::Pause => self.docker.docker(&["pause", target]).await?,
+                ContainerFault::Disconnect => {
+                    self.docker
+                        .docker(&["network", "disconnect", "-f", network, target])
+                        .await?
+                }
+            };
+        }
+        Ok(())
+    }
+
+    async fn recover(&self, targets: &[String]) -> Result<()> {
+        let network = self.docker.cfg.network.as_str();
+        let mut result = Ok(());
+        for target in targets {
+            let target = target.as_str();
+            let recovered = match self.kind {
+                ContainerFault::Kill => match self.docker.docker(&["start", target]).await {
+                    Ok(_) => self.docker.wait_ready(target).await,
+                    Err(e) => Err(e),
+                },
+                ContainerFault::Pause => self.docker.docker(&["unpause", target]).await.map(drop),
+                ContainerFault::Disconnect => self
+                    .docker
+                    .docker(&["network", "connect", network, target])
+                    .await
+                    .map(drop),
+            };
+            if let Err(e) = recovered {
+                error!("recover {} of container {}: {}", self.name(), target, e);
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
@@ -1,17 +1,62 @@
-use rand::{prelude::SmallRng, Rng, SeedableRng};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeSet},
+    hash::{Hash, Hasher},
+};
 
-use crate::base::Config;
+use rand::{prelude::SmallRng, Rng, RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
 
+use crate::{base::Config, slot::hash_slot};
+
+#[derive(Clone, Debug)]
 pub enum NextOp {
     Put { key: Vec<u8>, value: Vec<u8> },
     Delete { key: Vec<u8> },
 }
 
+impl NextOp {
+    pub fn name(&self) -> &'static str {
+        match self {
+            NextOp::Put { .. } => "put",
+            NextOp::Delete { .. } => "delete",
+        }
+    }
+
+    pub fn key(&self) -> &[u8] {
+        match self {
+            NextOp::Put { key, .. } | NextOp::Delete { key } => key,
+        }
+    }
+}
+
+/// The state of a generator. The generator is deterministic, so the state could be restored by
+/// replaying the ops from the seed.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Snapshot {
+    pub seed: u64,
+    pub writer: u64,
+    pub num_ops: usize,
+}
+
 pub struct Generator {
     seed: u64,
     writer: u64,
     cfg: Config,
     rng: SmallRng,
+    /// Decides which ops are duplicated, it is separated from `rng` so the ops are the same no
+    /// matter whether duplication is enabled.
+    duplicate_rng: SmallRng,
+    duplicated: bool,
+
+    num_ops: usize,
+    num_random_ops: usize,
+    /// The keys which are put and not deleted yet, ordered so the cleanup is deterministic. They
+    /// are tracked only if the cleanup is enabled.
+    alive_keys: BTreeSet<Vec<u8>>,
+    /// The keys waiting to be deleted by the cleanup phase, in reverse order.
+    cleanup_keys: Vec<Vec<u8>>,
+    cleaned_keys: Vec<Vec<u8>>,
+    finished_cleanup: Option<Vec<Vec<u8>>>,
 }
 
 impl Generator {
@@ -22,6 +67,14 @@ impl Generator {
             writer,
             cfg,
             rng,
+            duplicate_rng: SmallRng::seed_from_u64(seed.rotate_left(32)),
+            duplicated: false,
+            num_ops: 0,
+            num_random_ops: 0,
+            alive_keys: BTreeSet::default(),
+            cleanup_keys: Vec::default(),
+            cleaned_keys: Vec::default(),
+            finished_cleanup: None,
         }
     }
 
@@ -35,12 +88,57 @@ impl Generator {
         self.cfg.clone()
     }
 
+    pub fn restore(snapshot: &Snapshot, cfg: Config) -> Self {
+        let mut gen = Generator::new(snapshot.seed, snapshot.writer, cfg);
+        gen.skip(snapshot.num_ops);
+        gen
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            seed: self.seed,
+            writer: self.writer,
+            num_ops: self.num_ops,
+        }
+    }
+
+    /// Generate and discard the next `n` ops.
+    pub fn skip(&mut self, n: usize) {
+        for _ in 0..n {
+            self.next_op();
+        }
+    }
+
     pub fn reset(&mut self) {
-        self.rng = SmallRng::seed_from_u64(self.seed);
+        *self = Generator::new(self.seed, self.writer, self.cfg.clone());
     }
 
     pub fn next_op(&mut self) -> NextOp {
-        match self.rng.gen_range(0..2) {
+        self.num_ops += 1;
+        self.duplicated = self.cfg.duplicate_ratio > 0.0
+            && self
+                .duplicate_rng
+                .gen_bool(self.cfg.duplicate_ratio.min(1.0));
+        if let Some(key) = self.cleanup_keys.pop() {
+            self.cleaned_keys.push(key.clone());
+            if self.cleanup_keys.is_empty() {
+                self.finished_cleanup = Some(std::mem::take(&mut self.cleaned_keys));
+            }
+            return NextOp::Delete { key };
+        }
+
+        self.num_random_ops += 1;
+        if let Some(interval) = self.cfg.cleanup_interval {
+            if interval > 0 && self.num_random_ops % interval == 0 && !self.alive_keys.is_empty() {
+                let alive_keys = std::mem::take(&mut self.alive_keys);
+                self.cleanup_keys = alive_keys.into_iter().rev().collect();
+                self.num_ops -= 1;
+                // The duplicate decision is drawn again by the first op of the cleanup phase.
+                return self.next_op();
+            }
+        }
+
+        let op = match self.rng.gen_range(0..2) {
             0 => NextOp::Put {
                 key: self.next_key(),
                 value: self.next_bytes(self.cfg.value_range.clone()),
@@ -49,17 +147,86 @@ impl Generator {
                 key: self.next_key(),
             },
             _ => unreachable!(),
+        };
+        if self.cleans_up() {
+            match &op {
+                NextOp::Put { key, .. } => {
+                    self.alive_keys.insert(key.clone());
+                }
+                NextOp::Delete { key } => {
+                    self.alive_keys.remove(key);
+                }
+            }
+        }
+        op
+    }
+
+    /// Return whether the cleanup phase is enabled, the alive keys are tracked only for it, since
+    /// they grow with every distinct key written otherwise.
+    #[inline]
+    fn cleans_up(&self) -> bool {
+        matches!(self.cfg.cleanup_interval, Some(interval) if interval > 0)
+    }
+
+    /// Return a digest of the state, the generators replaying the same ops have the same digest.
+    pub fn digest(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.num_ops.hash(&mut hasher);
+        self.num_random_ops.hash(&mut hasher);
+        if self.cleans_up() {
+            self.alive_keys.hash(&mut hasher);
+            self.cleanup_keys.hash(&mut hasher);
+            self.cleaned_keys.hash(&mut hasher);
         }
+        // The states of rngs are observed by the next values.
+        self.rng.clone().next_u64().hash(&mut hasher);
+        self.duplicate_rng.clone().next_u64().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Return whether the last op should be sent again after it is acknowledged.
+    #[inline]
+    pub fn is_duplicated(&self) -> bool {
+        self.duplicated
+    }
+
+    /// Return the keys deleted by the cleanup phase, if the last op finished a cleanup phase.
+    pub fn take_finished_cleanup(&mut self) -> Option<Vec<Vec<u8>>> {
+        self.finished_cleanup.take()
     }
 
     fn next_key(&mut self) -> Vec<u8> {
-        let mut bytes = self.next_bytes(self.cfg.key_range.clone());
-        bytes.extend_from_slice(self.writer.to_le_bytes().as_slice());
+        loop {
+            let mut bytes = Self::key_prefix(&self.cfg.namespace);
+            bytes.extend(self.next_bytes(self.cfg.key_range.clone()));
+            bytes.extend_from_slice(self.writer.to_le_bytes().as_slice());
+            match self.cfg.affinity_slot {
+                // Rejection sampling, it takes `hash_slots` tries on average.
+                Some(slot) if hash_slot(&bytes, self.cfg.hash_slots) != slot => continue,
+                _ => return bytes,
+            }
+        }
+    }
+
+    /// Return the prefix of the keys in the namespace, see [`Config::namespace`].
+    fn key_prefix(namespace: &str) -> Vec<u8> {
+        if namespace.is_empty() {
+            vec![]
+        } else {
+            format!("{}/", namespace).into_bytes()
+        }
+    }
+
+    /// Return the `n`-th key reserved for the negative lookups of the writer. It is never
+    /// generated, since the generated keys are alphanumeric before the suffix of writer.
+    pub fn absent_key(writer: u64, n: u64) -> Vec<u8> {
+        let mut bytes = format!("absent#{}", n).into_bytes();
+        bytes.extend_from_slice(writer.to_le_bytes().as_slice());
         bytes
     }
 
-    #[allow(unused)]
-    fn writer_from_key(key: &[u8]) -> u64 {
+    /// Return the index of the writer which generates the key, it is the suffix of the key.
+    pub fn writer_from_key(key: &[u8]) -> u64 {
         if key.len() <= 8 {
             panic!("key {key:?} does not contains writer index");
         }
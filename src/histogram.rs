@@ -0,0 +1,157 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// The values below `2^SUB_BUCKET_BITS` microseconds are counted exactly, and each larger power
+/// of two is split into `2^SUB_BUCKET_BITS` sub-buckets, so any recorded value is within 1% of
+/// the reported one.
+const SUB_BUCKET_BITS: u32 = 7;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+/// The values are clamped to `2^MAX_VALUE_BITS` microseconds, about 12 days.
+const MAX_VALUE_BITS: u32 = 40;
+const NUM_BUCKETS: usize = SUB_BUCKETS * (MAX_VALUE_BITS - SUB_BUCKET_BITS + 1) as usize;
+
+/// A lock-free latency histogram of high dynamic range, in the same layout as HdrHistogram: the
+/// buckets of each power of two are linear, so the precision of the tail is the same as the
+/// precision of the median.
+pub struct Histogram {
+    buckets: Box<[AtomicU64]>,
+    count: AtomicU64,
+    sum_us: AtomicU64,
+    max_us: AtomicU64,
+}
+
+/// A point-in-time copy of [`Histogram`].
+#[derive(Clone, Debug, Default)]
+pub struct HistogramSnapshot {
+    pub buckets: Vec<u64>,
+    pub count: u64,
+    pub sum_us: u64,
+    pub max_us: u64,
+}
+
+/// The latencies of each type of op.
+#[derive(Clone, Debug, Default)]
+pub struct OpLatencies {
+    pub put: HistogramSnapshot,
+    pub delete: HistogramSnapshot,
+    pub get: HistogramSnapshot,
+}
+
+fn bucket_of(us: u64) -> usize {
+    let us = us.min((1 << MAX_VALUE_BITS) - 1);
+    if us < SUB_BUCKETS as u64 {
+        return us as usize;
+    }
+    let exp = u64::BITS - 1 - us.leading_zeros();
+    let shift = exp - SUB_BUCKET_BITS;
+    let sub = (us >> shift) as usize & (SUB_BUCKETS - 1);
+    SUB_BUCKETS * (shift as usize + 1) + sub
+}
+
+/// Return the largest value counted by the bucket.
+fn upper_bound_of(bucket: usize) -> u64 {
+    if bucket < SUB_BUCKETS {
+        return bucket as u64;
+    }
+    let shift = (bucket / SUB_BUCKETS - 1) as u32;
+    let sub = (bucket % SUB_BUCKETS) as u64;
+    ((SUB_BUCKETS as u64 + sub + 1) << shift) - 1
+}
+
+impl Histogram {
+    pub fn record(&self, elapsed: Duration) {
+        let us = elapsed.as_micros().min(u64::MAX as u128) as u64;
+        self.buckets[bucket_of(us)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_us.fetch_add(us, Ordering::Relaxed);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        HistogramSnapshot {
+            buckets: self
+                .buckets
+                .iter()
+                .map(|b| b.load(Ordering::Relaxed))
+                .collect(),
+            count: self.count.load(Ordering::Relaxed),
+            sum_us: self.sum_us.load(Ordering::Relaxed),
+            max_us: self.max_us.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            buckets: (0..NUM_BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum_us: AtomicU64::new(0),
+            max_us: AtomicU64::new(0),
+        }
+    }
+}
+
+impl HistogramSnapshot {
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_micros(self.sum_us / self.count)
+    }
+
+    /// Return the upper bound of the bucket which contains the `q` quantile, `q` is in `[0, 1]`.
+    pub fn percentile(&self, q: f64) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        let target = ((self.count as f64) * q).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (idx, num) in self.buckets.iter().enumerate() {
+            seen += num;
+            if seen >= target {
+                return Duration::from_micros(upper_bound_of(idx).min(self.max_us));
+            }
+        }
+        Duration::from_micros(self.max_us)
+    }
+
+    /// Return the values recorded since the `prev` snapshot of the same histogram. The max is
+    /// the upper bound of the highest non-empty bucket, since the exact one is not tracked.
+    pub fn since(&self, prev: &HistogramSnapshot) -> HistogramSnapshot {
+        let buckets = self
+            .buckets
+            .iter()
+            .enumerate()
+            .map(|(i, num)| num - prev.buckets.get(i).cloned().unwrap_or_default())
+            .collect::<Vec<_>>();
+        let max_us = match buckets.iter().rposition(|num| *num > 0) {
+            Some(idx) => upper_bound_of(idx).min(self.max_us),
+            None => 0,
+        };
+        HistogramSnapshot {
+            buckets,
+            count: self.count - prev.count,
+            sum_us: self.sum_us - prev.sum_us,
+            max_us,
+        }
+    }
+}
+
+impl std::fmt::Display for HistogramSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "count {} mean {:?} p50 {:?} p90 {:?} p99 {:?} p999 {:?} max {:?}",
+            self.count,
+            self.mean(),
+            self.percentile(0.5),
+            self.percentile(0.9),
+            self.percentile(0.99),
+            self.percentile(0.999),
+            Duration::from_micros(self.max_us),
+        )
+    }
+}
@@ -0,0 +1,280 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use tokio::sync::{broadcast, Mutex};
+use tracing::{error, info, warn};
+
+use crate::{
+    base::{ExecCtx, Failure, FailureKind, Timeout},
+    gen::NextOp,
+    value::Value,
+};
+
+/// The number of events buffered for each subscriber, a slow subscriber loses the oldest events.
+const CAPACITY: usize = 1 << 16;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OpKind {
+    Put,
+    Delete,
+    Get,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum OpOutcome {
+    Ok,
+    Timeout,
+    Error(String),
+}
+
+impl OpOutcome {
+    pub fn from_result<T>(result: &anyhow::Result<T>) -> Self {
+        match result {
+            Ok(_) => OpOutcome::Ok,
+            Err(e) if e.is::<Timeout>() => OpOutcome::Timeout,
+            Err(e) => OpOutcome::Error(e.to_string()),
+        }
+    }
+}
+
+/// An attempt to execute an op by a writer, or to read a key of a writer by a reader.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OpEvent {
+    pub writer: usize,
+    /// The step of the op, or the step verified by the reader for a get.
+    pub step: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reader: Option<usize>,
+    pub kind: OpKind,
+    #[serde(serialize_with = "to_hex", deserialize_with = "from_hex")]
+    pub key: Vec<u8>,
+    #[serde(serialize_with = "to_hex_option", deserialize_with = "from_hex_option")]
+    pub value: Option<Vec<u8>>,
+    /// The step which wrote the value read by a successful get, `None` if the key is not found.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_step: Option<usize>,
+    pub outcome: OpOutcome,
+    /// The wall clock time in microseconds since unix epoch.
+    pub invoked_at_us: u64,
+    pub completed_at_us: u64,
+}
+
+impl OpEvent {
+    pub fn new(
+        writer: usize,
+        step: usize,
+        op: &NextOp,
+        outcome: OpOutcome,
+        invoked_at_us: u64,
+    ) -> Self {
+        let (kind, key, value) = match op {
+            NextOp::Put { key, value } => (OpKind::Put, key.clone(), Some(value.clone())),
+            NextOp::Delete { key } => (OpKind::Delete, key.clone(), None),
+        };
+        OpEvent {
+            writer,
+            step,
+            reader: None,
+            kind,
+            key,
+            value,
+            read_step: None,
+            outcome,
+            invoked_at_us,
+            completed_at_us: now_us(),
+        }
+    }
+
+    pub fn read(
+        reader: usize,
+        writer: usize,
+        step: usize,
+        key: &[u8],
+        result: &anyhow::Result<Option<Vec<u8>>>,
+        invoked_at_us: u64,
+    ) -> Self {
+        // A value which could not be decoded is recorded as a failed read, the reader fails the
+        // run by it.
+        let (got, outcome) = match result {
+            Ok(Some(value)) => match Value::decode(value) {
+                Ok(value) => (Some(value), OpOutcome::Ok),
+                Err(e) => {
+                    let failure = Failure::new(
+                        FailureKind::Verification,
+                        format!(
+                            "reader {} key {}: {}",
+                            reader,
+                            String::from_utf8_lossy(key),
+                            e
+                        ),
+                    );
+                    (None, OpOutcome::Error(failure.to_string()))
+                }
+            },
+            _ => (None, OpOutcome::from_result(result)),
+        };
+        OpEvent {
+            writer,
+            step,
+            reader: Some(reader),
+            kind: OpKind::Get,
+            key: key.to_owned(),
+            value: got.as_ref().map(Value::value),
+            read_step: got.as_ref().map(Value::index),
+            outcome,
+            invoked_at_us,
+            completed_at_us: now_us(),
+        }
+    }
+}
+
+/// The line recorded in place of the events lost by the recorder, the history with it is
+/// incomplete and could not be checked.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LostEvents {
+    pub lost_events: u64,
+}
+
+/// The channel of the op events, the subscribers (recorder, metrics, checkers) receive all
+/// events published after they subscribe.
+#[derive(Clone, Debug)]
+pub struct History {
+    sender: broadcast::Sender<Arc<OpEvent>>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        History { sender }
+    }
+
+    pub fn publish(&self, event: OpEvent) {
+        // It is fine that there is no subscriber.
+        self.sender.send(Arc::new(event)).unwrap_or_default();
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<OpEvent>> {
+        self.sender.subscribe()
+    }
+}
+
+/// Append the op events to a file, one json object per line. The events lost by a slow
+/// recorder are recorded as a [`LostEvents`] line.
+pub struct HistoryRecorder {
+    path: PathBuf,
+    receiver: Mutex<broadcast::Receiver<Arc<OpEvent>>>,
+}
+
+impl HistoryRecorder {
+    pub fn new(path: PathBuf, history: &History) -> Self {
+        HistoryRecorder {
+            path,
+            receiver: Mutex::new(history.subscribe()),
+        }
+    }
+}
+
+#[super::async_trait]
+impl super::base::Task for HistoryRecorder {
+    async fn run(&self, mut ctx: ExecCtx) {
+        let file = match File::create(&self.path) {
+            Ok(file) => file,
+            Err(e) => {
+                error!("create history file {}: {}", self.path.display(), e);
+                return;
+            }
+        };
+        info!("record history to {}", self.path.display());
+
+        let mut writer = BufWriter::new(file);
+        let mut receiver = self.receiver.lock().await;
+        loop {
+            tokio::select! {
+                event = receiver.recv() => {
+                    let result = match event {
+                        Ok(event) => write_line(&mut writer, event.as_ref()),
+                        Err(broadcast::error::RecvError::Lagged(num)) => {
+                            warn!("history recorder lost {} events", num);
+                            write_line(&mut writer, &LostEvents { lost_events: num })
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    };
+                    if let Err(e) = result {
+                        error!("write history file {}: {}", self.path.display(), e);
+                        return;
+                    }
+                }
+                result = ctx.wait_until_timeout_or_shutdown(Duration::from_secs(1)) => {
+                    writer.flush().unwrap_or_default();
+                    if result.is_none() {
+                        break;
+                    }
+                }
+            }
+        }
+        writer.flush().unwrap_or_default();
+    }
+}
+
+fn write_line<T: Serialize>(writer: &mut impl Write, line: &T) -> std::io::Result<()> {
+    serde_json::to_writer(&mut *writer, line)?;
+    writer.write_all(b"\n")
+}
+
+/// Return the wall clock time in microseconds since unix epoch.
+pub fn now_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+/// The keys and values are in hex, since the keys end with the index of writer in binary.
+fn to_hex<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        hex.push_str(&format!("{:02x}", b));
+    }
+    serializer.serialize_str(&hex)
+}
+
+fn decode_hex<E: serde::de::Error>(hex: &str) -> Result<Vec<u8>, E> {
+    if hex.len() % 2 != 0 {
+        return Err(E::custom(format!("odd length of hex {}", hex)));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+                .ok_or_else(|| E::custom(format!("invalid hex {}", hex)))
+        })
+        .collect()
+}
+
+fn from_hex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    decode_hex(&String::deserialize(deserializer)?)
+}
+
+fn from_hex_option<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<u8>>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|hex| decode_hex(&hex))
+        .transpose()
+}
+
+fn to_hex_option<S: Serializer>(bytes: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+    match bytes {
+        Some(bytes) => to_hex(bytes, serializer),
+        None => serializer.serialize_none(),
+    }
+}
@@ -0,0 +1,269 @@
+use std::{path::PathBuf, process::Stdio, sync::Arc};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{io::AsyncWriteExt, process::Command};
+use tracing::{error, info, warn};
+
+use crate::{
+    base::deserialize_secs,
+    chaos::{Fault, FaultConfig},
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct KubernetesConfig {
+    /// The path of `kubectl`, it is looked up in `PATH` if not specified. The current context
+    /// of its kubeconfig is used.
+    pub kubectl: Option<PathBuf>,
+    pub namespace: String,
+    /// The label selector of the pods of the engula cluster, eg `app=engula`.
+    pub selector: String,
+    /// Create the resources of chaos-mesh to inject the faults, otherwise only the pods could be
+    /// killed.
+    pub chaos_mesh: bool,
+    /// The duration to wait for a pod to be ready after it is killed.
+    #[serde(deserialize_with = "deserialize_secs")]
+    pub ready_timeout_secs: u64,
+}
+
+/// The engula cluster deployed in kubernetes, the faults are injected into its pods by kubectl.
+pub struct Kubernetes {
+    cfg: KubernetesConfig,
+    pods: Vec<String>,
+}
+
+impl Kubernetes {
+    /// Discover the pods of the cluster by the selector.
+    pub async fn discover(cfg: KubernetesConfig) -> Result<Self> {
+        let mut k8s = Kubernetes { cfg, pods: vec![] };
+        let output = k8s
+            .kubectl(
+                &[
+                    "get",
+                    "pods",
+                    "-l",
+                    &k8s.cfg.selector,
+                    "-o",
+                    "jsonpath={.items[*].metadata.name}",
+                ],
+                None,
+            )
+            .await?;
+        k8s.pods = output.split_whitespace().map(ToOwned::to_owned).collect();
+        if k8s.pods.is_empty() {
+            anyhow::bail!("there is no pod matching {}", k8s.cfg.selector);
+        }
+        info!("discover pods {:?} in kubernetes", k8s.pods);
+        Ok(k8s)
+    }
+
+    pub fn pod_names(&self) -> Vec<String> {
+        self.pods.clone()
+    }
+
+    async fn kubectl(&self, args: &[&str], stdin: Option<String>) -> Result<String> {
+        let kubectl = self
+            .cfg
+            .kubectl
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("kubectl"));
+        let mut child = Command::new(kubectl)
+            .args(["-n", &self.cfg.namespace])
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let mut input = child.stdin.take().unwrap();
+        if let Some(stdin) = stdin {
+            input.write_all(stdin.as_bytes()).await?;
+        }
+        drop(input);
+        let output = child.wait_with_output().await?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "kubectl {:?}: {}, {}",
+                args,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// The selector of chaos-mesh for the pods.
+    fn pod_selector(&self, pods: &[String]) -> serde_json::Value {
+        json!({ "pods": { self.cfg.namespace.clone(): pods } })
+    }
+}
+
+/// Build the fault injected into the pods, the spec of chaos-mesh is `None` if the fault is
+/// executed by kubectl directly.
+pub fn build_fault(cfg: &FaultConfig, k8s: Arc<Kubernetes>) -> Result<Arc<dyn Fault>> {
+    let (name, spec) = match cfg {
+        FaultConfig::Kill if !k8s.cfg.chaos_mesh => ("kill", None),
+        FaultConfig::Kill => (
+            "kill",
+            Some(("PodChaos", json!({ "action": "pod-failure" }))),
+        ),
+        FaultConfig::Partition { symmetric, .. } => (
+            "partition",
+            Some((
+                "NetworkChaos",
+                json!({
+                    "action": "partition",
+                    "direction": if *symmetric { "both" } else { "to" },
+                }),
+            )),
+        ),
+        FaultConfig::Netem {
+            delay_ms,
+            jitter_ms,
+            loss_percent,
+            reorder_percent,
+            ..
+        } => (
+            "netem",
+            Some((
+                "NetworkChaos",
+                json!({
+                    "action": "netem",
+                    "delay": {
+                        "latency": format!("{}ms", delay_ms),
+                        "jitter": format!("{}ms", jitter_ms),
+                        "reorder": { "reorder": reorder_percent.to_string(), "correlation": "0" },
+                    },
+                    "loss": { "loss": loss_percent.to_string(), "correlation": "0" },
+                }),
+            )),
+        ),
+        FaultConfig::ClockSkew {
+            offset_ms,
+            drift_ppm,
+            ..
+        } => {
+            if *drift_ppm != 0 {
+                warn!("the drift of clock skew is not supported by chaos-mesh");
+            }
+            (
+                "clock_skew",
+                Some((
+                    "TimeChaos",
+                    json!({ "timeOffset": format!("{}ms", offset_ms) }),
+                )),
+            )
+        }
+        FaultConfig::DiskError {
+            volume_path: Some(volume_path),
+            ..
+        } => (
+            "disk_error",
+            Some((
+                "IOChaos",
+                json!({
+                    "action": "fault",
+                    "volumePath": volume_path,
+                    "errno": 5,
+                    "percent": 100,
+                }),
+            )),
+        ),
+        _ => anyhow::bail!("the fault {:?} is not supported in kubernetes", cfg),
+    };
+    if spec.is_some() && !k8s.cfg.chaos_mesh {
+        anyhow::bail!("the fault {} in kubernetes requires chaos-mesh", name);
+    }
+    Ok(Arc::new(KubernetesFault { name, k8s, spec }))
+}
+
+struct KubernetesFault {
+    name: &'static str,
+    k8s: Arc<Kubernetes>,
+    /// The kind and the partial spec of the resource of chaos-mesh.
+    spec: Option<(&'static str, serde_json::Value)>,
+}
+
+impl KubernetesFault {
+    /// The faults of the same kind on different targets are active at the same time, so the
+    /// targets are a part of the name.
+    fn resource_name(&self, targets: &[String]) -> String {
+        format!(
+            "engula-chaos-{}-{}",
+            self.name.replace('_', "-"),
+            targets.join("-")
+        )
+    }
+}
+
+#[super::async_trait]
+impl Fault for KubernetesFault {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    async fn inject(&self, targets: &[String]) -> Result<()> {
+        let (kind, spec) = match &self.spec {
+            Some(spec) => spec,
+            None => {
+                for target in targets {
+                    self.k8s
+                        .kubectl(
+                            &["delete", "pod", target, "--grace-period=0", "--force"],
+                            None,
+                        )
+                        .await?;
+                }
+                return Ok(());
+            }
+        };
+        let mut spec = spec.clone();
+        spec["mode"] = json!("all");
+        spec["selector"] = self.k8s.pod_selector(targets);
+        if *kind == "NetworkChaos" && spec["action"] == "partition" {
+            let others = self
+                .k8s
+                .pods
+                .iter()
+                .filter(|p| !targets.contains(p))
+                .cloned()
+                .collect::<Vec<_>>();
+            spec["target"] = json!({ "mode": "all", "selector": self.k8s.pod_selector(&others) });
+        }
+        let resource = json!({
+            "apiVersion": "chaos-mesh.org/v1alpha1",
+            "kind": kind,
+            "metadata": { "name": self.resource_name(targets), "namespace": self.k8s.cfg.namespace },
+            "spec": spec,
+        });
+        self.k8s
+            .kubectl(&["apply", "-f", "-"], Some(resource.to_string()))
+            .await?;
+        Ok(())
+    }
+
+    async fn recover(&self, targets: &[String]) -> Result<()> {
+        match &self.spec {
+            Some((kind, _)) => {
+                self.k8s
+                    .kubectl(&["delete", kind, &self.resource_name(targets)], None)
+                    .await?;
+            }
+            None => {
+                // The killed pods are recreated by kubernetes.
+                let timeout = format!("--timeout={}s", self.k8s.cfg.ready_timeout_secs);
+                for target in targets {
+                    let pod = format!("pod/{}", target);
+                    if let Err(e) = self
+                        .k8s
+                        .kubectl(&["wait", "--for=condition=Ready", &pod, &timeout], None)
+                        .await
+                    {
+                        error!("wait pod {} ready: {}", target, e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,229 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use clap::ArgEnum;
+use opentelemetry::{
+    sdk::{trace, Resource},
+    KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use serde_json::{json, Map, Value};
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    Event, Subscriber,
+};
+use tracing_subscriber::{
+    filter::Targets,
+    fmt::{
+        format::Writer,
+        time::{FormatTime, SystemTime},
+        writer::BoxMakeWriter,
+        FmtContext, FormatEvent, FormatFields,
+    },
+    layer::{Context, SubscriberExt},
+    registry::LookupSpan,
+    reload,
+    util::SubscriberInitExt,
+    Layer, Registry,
+};
+
+use crate::{
+    base::{Failure, FailureKind},
+    dashboard::LogLines,
+};
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    /// One json object per line, the fields of the event and its spans are flattened into it,
+    /// eg `writer`, `reader`, `step`, `key`, `op` and `error_class`.
+    Json,
+}
+
+/// The filter of the log at startup.
+const DEFAULT_FILTER: &str = "info";
+
+/// The handle to change the filter of the global subscriber at runtime, eg to turn on `debug`
+/// for the writers only while diagnosing a long run.
+#[derive(Clone)]
+pub struct LogFilter {
+    handle: reload::Handle<Targets, Registry>,
+    current: Arc<Mutex<String>>,
+}
+
+impl LogFilter {
+    pub fn get(&self) -> String {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Replace the filter, it is a default level and the levels of targets separated by comma,
+    /// eg `info,engula_supervisor::writer=debug`.
+    pub fn set(&self, filter: &str) -> Result<()> {
+        let targets = filter.parse::<Targets>()?;
+        self.handle.reload(targets)?;
+        *self.current.lock().unwrap() = filter.to_owned();
+        Ok(())
+    }
+}
+
+/// Install the global subscriber, the log is written to `lines` instead of stdout if it is
+/// specified, and the spans are exported to `otlp_endpoint` if it is specified.
+pub fn init(
+    format: LogFormat,
+    lines: Option<LogLines>,
+    otlp_endpoint: Option<&str>,
+) -> Result<LogFilter> {
+    let tracer = match otlp_endpoint {
+        Some(endpoint) => Some(otlp_tracer(endpoint)?),
+        None => None,
+    };
+    let ansi = lines.is_none();
+    let writer = match lines {
+        Some(lines) => BoxMakeWriter::new(move || lines.clone()),
+        None => BoxMakeWriter::new(std::io::stdout),
+    };
+    let (filter, handle) = reload::Layer::new(DEFAULT_FILTER.parse::<Targets>().unwrap());
+    match format {
+        LogFormat::Text => tracing_subscriber::registry()
+            .with(filter)
+            .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)))
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(ansi)
+                    .with_writer(writer),
+            )
+            .init(),
+        LogFormat::Json => tracing_subscriber::registry()
+            .with(filter)
+            .with(tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer)))
+            .with(SpanFields)
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .event_format(JsonFormat)
+                    .with_writer(writer),
+            )
+            .init(),
+    }
+    Ok(LogFilter {
+        handle,
+        current: Arc::new(Mutex::new(DEFAULT_FILTER.to_owned())),
+    })
+}
+
+/// The tracer exporting the spans in batches to the OTLP gRPC `endpoint`, it has to be built
+/// inside the tokio runtime.
+fn otlp_tracer(endpoint: &str) -> Result<trace::Tracer> {
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                env!("CARGO_PKG_NAME"),
+            )])),
+        )
+        .install_batch(opentelemetry::runtime::Tokio)
+        .map_err(|e| {
+            Failure::new(
+                FailureKind::Config,
+                format!("install the OTLP exporter of {}: {}", endpoint, e),
+            )
+        })?;
+    Ok(tracer)
+}
+
+/// The fields of a span or an event, the names are kept as they are in the macros.
+#[derive(Default)]
+struct JsonFields(Map<String, Value>);
+
+impl Visit for JsonFields {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_owned(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_owned(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_owned(), json!(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_owned(), json!(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().to_owned(), json!(format!("{:?}", value)));
+    }
+}
+
+/// Keep the fields of each span in its extensions, so they could be flattened into the events
+/// inside it.
+struct SpanFields;
+
+impl<S> Layer<S> for SpanFields
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut fields = JsonFields::default();
+            attrs.record(&mut fields);
+            span.extensions_mut().insert(fields);
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(fields) = span.extensions_mut().get_mut::<JsonFields>() {
+                values.record(fields);
+            }
+        }
+    }
+}
+
+struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        let mut timestamp = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut timestamp))?;
+        let metadata = event.metadata();
+        let mut fields = JsonFields::default();
+        fields.0.insert("timestamp".to_owned(), json!(timestamp));
+        fields
+            .0
+            .insert("level".to_owned(), json!(metadata.level().as_str()));
+        fields
+            .0
+            .insert("target".to_owned(), json!(metadata.target()));
+        // The fields of the inner spans and the event override the outer ones.
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                if let Some(span_fields) = span.extensions().get::<JsonFields>() {
+                    for (name, value) in &span_fields.0 {
+                        fields.0.insert(name.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        event.record(&mut fields);
+        writeln!(writer, "{}", Value::Object(fields.0))
+    }
+}
@@ -1,142 +1,1506 @@
 #![feature(backtrace)]
 
+mod admin;
+mod audit;
 mod base;
+mod budget;
+mod cgroup;
+mod chaos;
+mod checker;
+mod checkpoint;
+mod cluster;
+mod control;
+mod coverage;
+mod dashboard;
+mod docker;
 mod gen;
+mod histogram;
+mod history;
+mod kubernetes;
+mod log;
+mod metrics;
+mod net;
+mod overrides;
+mod progress;
+mod pushgateway;
 mod reader;
+mod report;
+mod shadow;
+mod slot;
+mod statsd;
+mod summary;
+mod toxiproxy;
+mod upgrade;
 mod value;
+mod visibility;
 mod writer;
 
-use std::{path::PathBuf, sync::Arc, time::Duration};
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
 
+use admin::AdminServer;
 use anyhow::Result;
 use async_trait::async_trait;
+use audit::Auditor;
 use base::Config;
-use clap::Parser;
-use engula_client::{ClientOptions, EngulaClient, Partition};
+use budget::{ErrorBudget, ErrorBudgetConfig};
+use chaos::{ChaosEnv, ChaosScheduler, ChaosStep, FaultLog, Nemesis, NemesisConfig};
+use checkpoint::{Checkpoint, Checkpointer};
+use clap::{ArgEnum, Parser, Subcommand};
+use cluster::{Cluster, ClusterConfig};
+use control::{read_commands_from_stdin, stop_on_signals, Controller, WriterGroup};
+use coverage::CoverageTracker;
+use dashboard::{Dashboard, LogLines};
+use docker::{Docker, DockerConfig};
+use engula_client::{AppError, ClientOptions, Collection, Database, EngulaClient};
+use gen::{Generator, NextOp};
+use history::{now_us, History, HistoryRecorder};
+use kubernetes::{Kubernetes, KubernetesConfig};
+use log::{LogFilter, LogFormat};
+use progress::{LatencyReporter, ProgressReporter};
+use pushgateway::{Pushgateway, PushgatewayConfig};
 use rand::{rngs::OsRng, RngCore};
-use reader::Reader;
 use serde::{Deserialize, Serialize};
+use shadow::ShadowStore;
+use statsd::{StatsdConfig, StatsdExporter};
+use summary::{RunReport, RunReporter, VerifyOutcome};
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    sync::mpsc,
+    time::Instant,
+};
+use toxiproxy::{Toxiproxy, ToxiproxyConfig};
 use tracing::{error, info};
-use writer::Writer;
+use upgrade::{RollingUpgrade, UpgradeConfig};
+use visibility::VisibilityMeter;
 
-use crate::base::{ExecCtx, Task};
+use crate::base::{
+    deserialize_millis, deserialize_opt_millis, deserialize_opt_secs, deserialize_secs, ExecCtx,
+    Failure, FailureKind, PartitionConfig, ReaderOptions, Relaxation, Seeds, Task, ThinkTime,
+    TunableOptions, Tunables, WriterOptions, Writers,
+};
 
 #[derive(Parser)]
 struct Args {
+    /// The config file, its format is decided by the extension: TOML for `.toml` or no
+    /// extension, json for `.json`, and YAML for `.yaml` or `.yml`, the others are rejected.
     #[clap(required = true, short = 'c', long = "config", parse(from_os_str))]
     config: PathBuf,
 
-    #[clap(short = 'd', long = "dump")]
-    dump: Option<bool>,
+    /// Override `base_seed` of the config, eg to reproduce a failed run with the seed printed at
+    /// its startup.
+    #[clap(long = "base-seed")]
+    base_seed: Option<u64>,
+
+    /// Merge the profile of the config over its base, eg `soak` selects `[profiles.soak]`.
+    #[clap(long = "profile")]
+    profile: Option<String>,
+
+    /// Override a field of the config, eg `--set writers=8 --set
+    /// generator.value_range=1024..4096`.
+    #[clap(long = "set", value_name = "KEY=VALUE")]
+    set: Vec<String>,
+
+    #[clap(long = "log-format", arg_enum, default_value = "text")]
+    log_format: LogFormat,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Args, Clone, Default)]
+struct RunArgs {
+    /// Resume the writers and readers from the checkpoint in `state_file`.
+    #[clap(long = "resume")]
+    resume: bool,
+
+    /// Validate the config and print the planned run, with the first ops of each writer, without
+    /// connecting to the cluster.
+    #[clap(long = "dry-run")]
+    dry_run: bool,
+
+    /// Show a live dashboard of the writers, the readers and the active faults in the terminal,
+    /// the log is shown in it instead of stdout.
+    #[clap(long = "tui")]
+    tui: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the workload against the cluster, with the verification and the faults.
+    Run(RunArgs),
+    /// Write the default config to the config file.
+    DumpConfig,
+    /// Verify the contents of the collection against the final state of writers, by replaying
+    /// their generators without writing anything.
+    Audit {
+        /// The run id of the run, it is `run_id` of the config if it is not specified.
+        #[clap(long = "run-id")]
+        run_id: Option<String>,
+        #[clap(long = "base-seed")]
+        base_seed: u64,
+        #[clap(long = "writers")]
+        writers: usize,
+        /// The last step executed by each writer, separated by comma.
+        #[clap(long = "steps", required = true, use_value_delimiter = true)]
+        steps: Vec<usize>,
+    },
+    /// Delete the keys of a run from the collection and the index collection, by replaying the
+    /// generators of its writers like `audit`.
+    Clean {
+        #[clap(long = "run-id")]
+        run_id: String,
+        #[clap(long = "base-seed")]
+        base_seed: u64,
+        #[clap(long = "writers")]
+        writers: usize,
+        /// The last step executed by each writer, separated by comma.
+        #[clap(long = "steps", required = true, use_value_delimiter = true)]
+        steps: Vec<usize>,
+    },
+    /// Check that the history recorded in `history_file` is linearizable.
+    Check,
+    /// Regenerate the ops of a writer from its seed and print them, like `run --dry-run`.
+    Replay {
+        /// The run id of the run, it is `run_id` of the config if it is not specified.
+        #[clap(long = "run-id")]
+        run_id: Option<String>,
+        /// The index of the writer.
+        #[clap(long = "writer")]
+        writer: usize,
+        /// The number of ops to print.
+        #[clap(long = "steps")]
+        steps: usize,
+    },
+    /// Render the json report written to `report_file` by a run.
+    Report {
+        /// The report, it is `report_file` of the config if it is not specified.
+        #[clap(long = "file", parse(from_os_str))]
+        file: Option<PathBuf>,
+        #[clap(long = "format", arg_enum, default_value = "summary")]
+        format: ReportFormat,
+    },
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ReportFormat {
+    Summary,
+    /// The JUnit XML, like `junit_file`.
+    Junit,
+}
+
+/// A collection written by its own group of writers, in addition to `collection`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CollectionGroupConfig {
+    db: String,
+    collection: String,
+    index_collection: Option<String>,
+    /// The number of writers of the collection, they take the indexes after the writers of
+    /// `collection` and the former groups.
+    writers: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct AppConfig {
     writers: usize,
     readers: usize,
-    hash_slots: u32,
+    /// The number of readers which verify each writer independently.
+    readers_per_writer: usize,
 
     addrs: Vec<String>,
 
     db: String,
     collection: String,
+    /// The collection used to record an index entry for each key of `collection`, the readers
+    /// verify the consistency between them.
+    index_collection: Option<String>,
+
+    /// Stop the run with the final verification once it lasts `max_duration_secs`, or every
+    /// writer reaches `max_steps_per_writer`.
+    #[serde(default, deserialize_with = "deserialize_opt_secs")]
+    max_duration_secs: Option<u64>,
+    max_steps_per_writer: Option<usize>,
+
+    /// The deadline of each put/get/delete, in milliseconds.
+    #[serde(deserialize_with = "deserialize_millis")]
+    op_timeout_ms: u64,
+    /// Log each op and read slower than it at WARN, in milliseconds.
+    #[serde(default, deserialize_with = "deserialize_opt_millis")]
+    slow_op_threshold_ms: Option<u64>,
+    /// The timeout of the client to connect a node, in milliseconds.
+    #[serde(deserialize_with = "deserialize_millis")]
+    client_connect_timeout_ms: u64,
+    /// The timeout of the client for each request, including its retries, in milliseconds.
+    #[serde(deserialize_with = "deserialize_millis")]
+    client_timeout_ms: u64,
+    /// The duration since start, during which the latencies are not recorded and the staled reads
+    /// are retried instead of failing the run.
+    #[serde(deserialize_with = "deserialize_secs")]
+    warmup_secs: u64,
+    /// The number of steps a read value could lag behind the verified step of the writer.
+    max_lag_steps: usize,
+    /// The duration a staled read is retried before failing the run, in milliseconds.
+    #[serde(deserialize_with = "deserialize_millis")]
+    max_lag_ms: u64,
+    /// The duration the verification of an op is retried before failing the run, in seconds.
+    #[serde(deserialize_with = "deserialize_secs")]
+    verify_deadline_secs: u64,
+    /// Verify a deterministic sample of one in `verify_sample_interval` ops, 1 to verify all.
+    verify_sample_interval: usize,
+    /// Bound the memory of the model of a writer, by skipping the verification until the keys
+    /// read ahead of the model are resolved.
+    verify_max_expected_keys: Option<usize>,
+    /// Crash and respawn the verification of readers from their snapshots periodically, to test
+    /// the catch-up of the supervisor itself, in seconds.
+    #[serde(default, deserialize_with = "deserialize_opt_secs")]
+    reader_crash_interval_secs: Option<u64>,
+    /// The duration a key deleted by the cleanup phase could be still visible, in milliseconds.
+    #[serde(deserialize_with = "deserialize_millis")]
+    delete_grace_ms: u64,
+    /// Look up a key reserved to be never written for each traced writer periodically, in
+    /// milliseconds.
+    #[serde(default, deserialize_with = "deserialize_opt_millis")]
+    negative_lookup_interval_ms: Option<u64>,
+    /// The interval between two verification wakeups of a writer, in milliseconds.
+    #[serde(deserialize_with = "deserialize_millis")]
+    verify_interval_ms: u64,
+    /// The number of ops of a writer verified in each wakeup.
+    verify_ops_per_wakeup: usize,
+    /// The interval of wakeups once the verification catches up with a writer, in milliseconds.
+    #[serde(deserialize_with = "deserialize_millis")]
+    verify_idle_interval_ms: u64,
+    /// The fraction of puts that are read back by the writer itself, 0 to disable.
+    read_your_writes_ratio: f64,
+    /// Throttle a writer while the slowest reader tracing it lags more steps behind.
+    max_verify_lag_steps: Option<usize>,
+
+    /// Verify every key strictly once the run is stopped, after waiting `settle_secs` for the
+    /// cluster to settle.
+    final_verify: bool,
+    #[serde(deserialize_with = "deserialize_secs")]
+    settle_secs: u64,
+    /// Apply the ops of writers to a local shadow store, and diff it with the cluster in the
+    /// final verification.
+    shadow_store: bool,
+
+    /// The file to save the progress of writers periodically, see `--resume`.
+    state_file: Option<PathBuf>,
+    #[serde(deserialize_with = "deserialize_secs")]
+    checkpoint_interval_secs: u64,
+
+    /// The file to record every attempt to execute an op of writers, one json per line.
+    history_file: Option<PathBuf>,
+    /// The file to accumulate the chaos coverage across runs, the coverage of this run is
+    /// reported only if it is not specified.
+    coverage_file: Option<PathBuf>,
+    /// The file to write the json report of the run once it is finished or failed.
+    report_file: Option<PathBuf>,
+    /// The file to write the report as a JUnit XML, each checker of the run is a test case.
+    junit_file: Option<PathBuf>,
+    /// The address of the HTTP server to inspect and control the run, eg `127.0.0.1:9100`.
+    admin_addr: Option<String>,
+    /// Export the spans of the ops to an OpenTelemetry collector over OTLP gRPC, eg
+    /// `http://localhost:4317`.
+    otlp_endpoint: Option<String>,
+    /// Log a summary of the throughput, the errors and the lag of each writer and reader in
+    /// each interval, in seconds.
+    #[serde(default, deserialize_with = "deserialize_opt_secs")]
+    progress_report_secs: Option<u64>,
+    /// Log the latency percentiles of the ops completed in each interval, in seconds.
+    #[serde(default, deserialize_with = "deserialize_opt_secs")]
+    latency_report_secs: Option<u64>,
 
     base_seed: Option<u64>,
+    /// The namespace of the keys of the run, so the runs sharing a collection never collide, it
+    /// is `<base_seed>-<unix secs at startup>` if it is not specified, and empty to not prefix
+    /// the keys.
+    run_id: Option<String>,
+    /// The seeds of the first writers by their indexes, eg to reproduce a failed writer alone
+    /// with `writers = 1`, the others are `base_seed + index`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    seeds: Vec<u64>,
+    /// The partition of the collection and the index collection.
+    partition: PartitionConfig,
     generator: Config,
+    /// The think time between two ops of a writer.
+    think_time: Option<ThinkTime>,
+
+    /// Abort the run once too many ops of writers and readers fail.
+    error_budget: Option<ErrorBudgetConfig>,
+    /// Push the metrics to a StatsD agent periodically.
+    statsd: Option<StatsdConfig>,
+    /// Push the final metrics to a Prometheus Pushgateway once the run is finished.
+    pushgateway: Option<PushgatewayConfig>,
+    /// Bring up a local cluster before the run, its nodes replace `addrs`.
+    cluster: Option<ClusterConfig>,
+    /// Route the client traffic through the proxies of Toxiproxy, they replace `addrs`.
+    toxiproxy: Option<ToxiproxyConfig>,
+    /// Inject the faults into the pods of a cluster deployed in kubernetes, instead of the nodes
+    /// of `cluster`.
+    kubernetes: Option<KubernetesConfig>,
+    /// Bring up the cluster in docker containers before the run, their addresses replace
+    /// `addrs`.
+    docker: Option<DockerConfig>,
+    /// Upgrade the nodes of `cluster` or `docker` one by one during the run.
+    upgrade: Option<UpgradeConfig>,
+    /// The other collections written concurrently, each by its own group of writers, the
+    /// writers added at runtime write `collection`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    collection_groups: Vec<CollectionGroupConfig>,
+    /// Inject faults into the cluster periodically, the targets are the nodes of `cluster`, the
+    /// pods of `kubernetes`, the containers of `docker`, or the proxies of `toxiproxy`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    nemesis: Vec<NemesisConfig>,
+    /// The timed faults of the chaos schedule.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    chaos: Vec<ChaosStep>,
+}
+
+/// The fields of `AppConfig` which could be changed by reloading the config, see
+/// [`TunableOptions`]. The timing of `nemesis` is tunable too.
+const TUNABLE_FIELDS: &[&str] = &[
+    "read_your_writes_ratio",
+    "think_time",
+    "verify_interval_ms",
+    "verify_ops_per_wakeup",
+    "verify_idle_interval_ms",
+    "nemesis",
+];
+
+impl AppConfig {
+    fn load(args: &Args) -> Result<Self> {
+        let content = std::fs::read_to_string(&args.config).map_err(|e| {
+            Failure::new(
+                FailureKind::Config,
+                format!("read config {}: {}", args.config.display(), e),
+            )
+        })?;
+        let parse = || -> Result<AppConfig> {
+            let mut file = overrides::parse(&args.config, &content)?;
+            overrides::apply_profile(&mut file, args.profile.as_deref())?;
+            // The fields missing in the file are taken from the default config.
+            let mut root = toml::Value::try_from(AppConfig::default())?;
+            overrides::merge(&mut root, file);
+            // The environment variables override the file, and are overridden by `--set`.
+            let vars = std::env::vars_os()
+                .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)));
+            overrides::apply_env(&mut root, vars)?;
+            overrides::apply(&mut root, &args.set)?;
+            Ok(root.try_into()?)
+        };
+        let mut cfg = parse().map_err(|e| {
+            Failure::new(
+                FailureKind::Config,
+                format!("parse config {}: {}", args.config.display(), e),
+            )
+        })?;
+        cfg.generator.hash_slots = cfg.partition.hash_slots().unwrap_or_default();
+        if let Some(base_seed) = args.base_seed {
+            cfg.base_seed = Some(base_seed);
+        }
+        Ok(cfg)
+    }
+
+    fn client_options(&self) -> ClientOptions {
+        ClientOptions {
+            connect_timeout: Some(Duration::from_millis(self.client_connect_timeout_ms)),
+            timeout: Some(Duration::from_millis(self.client_timeout_ms)),
+        }
+    }
+
+    fn tunable_options(&self) -> TunableOptions {
+        TunableOptions {
+            read_your_writes_ratio: self.read_your_writes_ratio,
+            think_time: self.think_time.clone(),
+            verify_interval: Duration::from_millis(self.verify_interval_ms),
+            ops_per_wakeup: self.verify_ops_per_wakeup,
+            idle_interval: Duration::from_millis(self.verify_idle_interval_ms),
+        }
+    }
+
+    /// Return the fields changed by the new config, except the tunable ones. The faults of
+    /// `nemesis` could not be changed either.
+    fn structural_changes(&self, new: &AppConfig) -> Result<Vec<String>> {
+        let (old_value, new_value) = (serde_json::to_value(self)?, serde_json::to_value(new)?);
+        let mut changes = vec![];
+        if let (Some(old_fields), Some(new_fields)) = (old_value.as_object(), new_value.as_object())
+        {
+            let mut names = old_fields
+                .keys()
+                .chain(new_fields.keys())
+                .collect::<Vec<_>>();
+            names.sort_unstable();
+            names.dedup();
+            for name in names {
+                if !TUNABLE_FIELDS.contains(&name.as_str())
+                    && old_fields.get(name) != new_fields.get(name)
+                {
+                    changes.push(name.clone());
+                }
+            }
+        }
+        let old_faults = self.nemesis.iter().map(|n| serde_json::to_value(&n.fault));
+        let new_faults = new.nemesis.iter().map(|n| serde_json::to_value(&n.fault));
+        if self.nemesis.len() != new.nemesis.len()
+            || old_faults
+                .zip(new_faults)
+                .any(|(old, new)| old.ok() != new.ok())
+        {
+            changes.push("nemesis".to_owned());
+        }
+        Ok(changes)
+    }
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    tracing_subscriber::fmt::init();
+async fn main() {
+    let args = match Args::try_parse() {
+        Ok(args) => args,
+        Err(e) => {
+            // clap exits with 2 at a usage error, which is the code of availability failures.
+            let code = if e.use_stderr() {
+                FailureKind::Config.exit_code()
+            } else {
+                0
+            };
+            e.print().unwrap_or_default();
+            std::process::exit(code);
+        }
+    };
+    let tui = matches!(&args.command, Command::Run(run_args) if run_args.tui);
+    let log_lines = LogLines::default();
+    // The log is installed before the config is loaded by `run`, which reports its errors.
+    let otlp_endpoint = match &args.command {
+        Command::Run(_) => AppConfig::load(&args)
+            .ok()
+            .and_then(|cfg| cfg.otlp_endpoint),
+        _ => None,
+    };
+    let log_filter = match log::init(
+        args.log_format,
+        tui.then(|| log_lines.clone()),
+        otlp_endpoint.as_deref(),
+    ) {
+        Ok(log_filter) => log_filter,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            std::process::exit(FailureKind::of(&e).exit_code());
+        }
+    };
     install_panic_hook();
 
-    let args = Args::parse();
-    if args.dump.unwrap_or_default() {
+    let result = run(args, log_lines, log_filter).await;
+    // Flush the spans still buffered by the exporter.
+    if otlp_endpoint.is_some() {
+        opentelemetry::global::shutdown_tracer_provider();
+    }
+    if let Err(e) = result {
+        let kind = FailureKind::of(&e);
+        eprintln!("Error: {:?}", e);
+        std::process::exit(kind.exit_code());
+    }
+}
+
+/// Run the supervisor, the kind of the returned error decides the exit code, see
+/// [`FailureKind`].
+async fn run(args: Args, log_lines: LogLines, log_filter: LogFilter) -> Result<()> {
+    let run_args = match &args.command {
+        Command::Run(run_args) => run_args.clone(),
+        _ => RunArgs::default(),
+    };
+    if let Command::DumpConfig = args.command {
         let cfg = AppConfig::default();
-        std::fs::write(&args.config, toml::to_string_pretty(&cfg)?)?;
+        std::fs::write(&args.config, overrides::render(&args.config, &cfg)?)?;
         info!("dump default config to {} success", args.config.display());
         return Ok(());
     }
 
-    let content = std::fs::read_to_string(&args.config)?;
-    let cfg: AppConfig = toml::from_str(&content)?;
+    let mut cfg = AppConfig::load(&args)?;
+    // The commands printing a report or ops keep the stdout clean, so it could be redirected.
+    if !matches!(
+        args.command,
+        Command::Replay { .. } | Command::Report { .. }
+    ) {
+        info!(
+            "the effective config, with the defaults of the missing fields:\n{}",
+            overrides::render(&args.config, &cfg)?
+        );
+    }
+    // The addresses might be replaced by the cluster, keep the loaded one to compare with the
+    // reloaded config.
+    let loaded_cfg = cfg.clone();
+    let config_hash = format!("{:08x}", crc32fast::hash(toml::to_string(&cfg)?.as_bytes()));
+    if cfg.readers_per_writer == 0 || cfg.readers_per_writer > cfg.readers {
+        anyhow::bail!(Failure::new(
+            FailureKind::Config,
+            format!(
+                "readers_per_writer {} should be in [1, readers {}]",
+                cfg.readers_per_writer, cfg.readers
+            )
+        ));
+    }
+    if cfg.partition.hash_slots() == Some(0) {
+        anyhow::bail!(Failure::new(
+            FailureKind::Config,
+            "the hash partition should have at least one slot"
+        ));
+    }
+    if let Some(slot) = cfg.generator.affinity_slot {
+        match cfg.partition.hash_slots() {
+            Some(slots) if slot >= slots => anyhow::bail!(Failure::new(
+                FailureKind::Config,
+                format!("the affinity slot {} is out of hash slots {}", slot, slots)
+            )),
+            Some(_) => {}
+            // The keys of a slot are scattered over the ranges.
+            None => anyhow::bail!(Failure::new(
+                FailureKind::Config,
+                "the affinity slot requires the hash partition"
+            )),
+        }
+    }
+
+    if let Command::Check = args.command {
+        let path = match &cfg.history_file {
+            Some(path) => path,
+            None => anyhow::bail!(Failure::new(
+                FailureKind::Config,
+                "check requires the `history_file` config"
+            )),
+        };
+        return checker::check_history(path);
+    }
+    if let Command::Report { file, format } = &args.command {
+        let path = match file.as_ref().or(cfg.report_file.as_ref()) {
+            Some(path) => path,
+            None => anyhow::bail!(Failure::new(
+                FailureKind::Config,
+                "report requires `--file` or the `report_file` config"
+            )),
+        };
+        let content = std::fs::read_to_string(path).map_err(|e| {
+            Failure::new(
+                FailureKind::Config,
+                format!("read report {}: {}", path.display(), e),
+            )
+        })?;
+        let report: RunReport = serde_json::from_str(&content).map_err(|e| {
+            Failure::new(
+                FailureKind::Config,
+                format!("parse report {}: {}", path.display(), e),
+            )
+        })?;
+        match format {
+            ReportFormat::Summary => print!("{}", report::render_summary(&report)),
+            ReportFormat::Junit => print!("{}", report::render_junit(&report)),
+        }
+        return Ok(());
+    }
+
+    if cfg.shadow_store && !cfg.collection_groups.is_empty() {
+        anyhow::bail!(Failure::new(
+            FailureKind::Config,
+            "the shadow store could not diff the collections of `collection_groups`"
+        ));
+    }
+
+    let backends = [
+        cfg.cluster.is_some(),
+        cfg.kubernetes.is_some(),
+        cfg.docker.is_some(),
+    ];
+    if backends.into_iter().filter(|b| *b).count() > 1 {
+        anyhow::bail!(Failure::new(
+            FailureKind::Config,
+            "the `cluster`, `kubernetes` and `docker` configs are exclusive"
+        ));
+    }
 
-    let opts = ClientOptions {
-        connect_timeout: Some(Duration::from_millis(200)),
-        timeout: Some(Duration::from_millis(500)),
+    if run_args.dry_run {
+        let base_seed = cfg.base_seed.unwrap_or_else(|| OsRng.next_u64());
+        cfg.generator.namespace = cfg
+            .run_id
+            .clone()
+            .unwrap_or_else(|| default_run_id(base_seed));
+        print_plan(&cfg, base_seed)?;
+        return Ok(());
+    }
+    if let Command::Replay {
+        run_id,
+        writer,
+        steps,
+    } = &args.command
+    {
+        let base_seed = match cfg.base_seed {
+            Some(base_seed) => base_seed,
+            None => anyhow::bail!(Failure::new(
+                FailureKind::Config,
+                "replay requires `--base-seed` or the `base_seed` config"
+            )),
+        };
+        cfg.generator.namespace = match run_id.clone().or_else(|| cfg.run_id.clone()) {
+            Some(run_id) => run_id,
+            None => anyhow::bail!(Failure::new(
+                FailureKind::Config,
+                "replay requires `--run-id` or the `run_id` config"
+            )),
+        };
+        let writers = cfg.writers
+            + cfg
+                .collection_groups
+                .iter()
+                .map(|g| g.writers)
+                .sum::<usize>();
+        if *writer >= writers {
+            anyhow::bail!(Failure::new(
+                FailureKind::Config,
+                format!("writer {} is out of the {} writers", writer, writers)
+            ));
+        }
+        let seed = Seeds::new(base_seed, cfg.seeds.clone()).writer(*writer);
+        println!("writer {}, seed {}", writer, seed);
+        print_ops(
+            &mut Generator::new(seed, *writer as u64, cfg.generator.clone()),
+            *steps,
+        );
+        return Ok(());
+    }
+    // The offline commands read and write the existing cluster at `addrs`, without starting
+    // the backends.
+    if matches!(args.command, Command::Audit { .. } | Command::Clean { .. }) {
+        let client = EngulaClient::new(cfg.client_options(), cfg.addrs.clone()).await?;
+        info!("connect to engula cluster success");
+        match args.command {
+            Command::Audit {
+                run_id,
+                base_seed,
+                writers,
+                steps,
+            } => {
+                let writers = writer_steps(writers, steps)?;
+                let mut generator = cfg.generator.clone();
+                generator.namespace = match run_id.or_else(|| cfg.run_id.clone()) {
+                    Some(run_id) => run_id,
+                    None => anyhow::bail!(Failure::new(
+                        FailureKind::Config,
+                        "audit requires `--run-id` or the `run_id` config"
+                    )),
+                };
+                let db = client.open_database(cfg.db.clone()).await?;
+                let collection = db.open_collection(cfg.collection.clone()).await?;
+                let auditor = Auditor::new(
+                    Seeds::new(base_seed, cfg.seeds.clone()),
+                    generator,
+                    collection,
+                    Duration::from_millis(cfg.op_timeout_ms),
+                );
+                return auditor.audit(&writers).await;
+            }
+            Command::Clean {
+                run_id,
+                base_seed,
+                writers,
+                steps,
+            } => {
+                let writers = writer_steps(writers, steps)?;
+                let mut generator = cfg.generator.clone();
+                generator.namespace = run_id;
+                let db = client.open_database(cfg.db.clone()).await?;
+                for name in std::iter::once(&cfg.collection).chain(&cfg.index_collection) {
+                    let collection = db.open_collection(name.clone()).await?;
+                    let auditor = Auditor::new(
+                        Seeds::new(base_seed, cfg.seeds.clone()),
+                        generator.clone(),
+                        collection,
+                        Duration::from_millis(cfg.op_timeout_ms),
+                    );
+                    auditor.clean(&writers).await?;
+                    info!("clean collection {} success", name);
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+    }
+
+    let cluster = match &cfg.cluster {
+        Some(cluster_cfg) => {
+            let cluster = Arc::new(Cluster::new(cluster_cfg.clone()));
+            cluster.start().await?;
+            cfg.addrs = cluster.addrs();
+            Some(cluster)
+        }
+        None => None,
+    };
+    let docker = match &cfg.docker {
+        Some(docker_cfg) => {
+            let docker = Arc::new(Docker::new(docker_cfg.clone()));
+            docker.start().await?;
+            cfg.addrs = docker.addrs();
+            Some(docker)
+        }
+        None => None,
     };
-    let client = EngulaClient::new(opts, cfg.addrs).await?;
+    let toxiproxy = match &cfg.toxiproxy {
+        Some(toxiproxy_cfg) => {
+            let names = match (&cluster, &docker) {
+                (Some(cluster), _) => cluster.node_names(),
+                (None, Some(docker)) => docker.node_names(),
+                (None, None) => (0..cfg.addrs.len())
+                    .map(|i| format!("node-{}", i))
+                    .collect(),
+            };
+            let proxies = names.into_iter().zip(cfg.addrs.iter().cloned()).collect();
+            let toxiproxy = Arc::new(Toxiproxy::new(toxiproxy_cfg.clone(), proxies));
+            toxiproxy.start().await?;
+            cfg.addrs = toxiproxy.listen_addrs();
+            Some(toxiproxy)
+        }
+        None => None,
+    };
+    let kubernetes = match &cfg.kubernetes {
+        Some(k8s_cfg) => Some(Arc::new(Kubernetes::discover(k8s_cfg.clone()).await?)),
+        None => None,
+    };
+    let chaos_env = ChaosEnv {
+        cluster: cluster.clone(),
+        toxiproxy,
+        kubernetes,
+        docker: docker.clone(),
+    };
+    let faults = cfg
+        .nemesis
+        .iter()
+        .map(|c| chaos::build_fault(&c.fault, &chaos_env))
+        .collect::<Result<Vec<_>>>()?;
+    let node_names = chaos_env.node_names();
+    let mut chaos_steps = vec![];
+    for step in &cfg.chaos {
+        if let Some(target) = step.targets.iter().find(|t| !node_names.contains(t)) {
+            anyhow::bail!(Failure::new(
+                FailureKind::Config,
+                format!("the chaos target {} is not a node", target)
+            ));
+        }
+        let fault = chaos::build_fault(&step.fault, &chaos_env)?;
+        chaos_steps.push((step.clone(), fault));
+    }
+    let fault_log = Arc::new(FaultLog::default());
+    let upgrade = match &cfg.upgrade {
+        Some(upgrade_cfg) => Some(RollingUpgrade::new(
+            upgrade_cfg.clone(),
+            cluster.clone(),
+            docker.clone(),
+            fault_log.clone(),
+        )?),
+        None => None,
+    };
+
+    let client = EngulaClient::new(cfg.client_options(), cfg.addrs).await?;
     info!("connect to engula cluster success");
-    let db = client.create_database(cfg.db.clone()).await?;
-    info!("create database success");
-    let collection = db
-        .create_collection(cfg.collection.clone(), Some(Partition::Hash { slots: cfg.hash_slots }))
-        .await?;
-    info!("create collection success");
 
-    let base_seed = if let Some(base_seed) = cfg.base_seed {
+    let mut groups = vec![];
+    let mut databases = HashMap::new();
+    let main_group = CollectionGroupConfig {
+        db: cfg.db.clone(),
+        collection: cfg.collection.clone(),
+        index_collection: cfg.index_collection.clone(),
+        writers: cfg.writers,
+    };
+    for group_cfg in std::iter::once(&main_group).chain(&cfg.collection_groups) {
+        // The groups might share a database.
+        let db = match databases.get(&group_cfg.db) {
+            Some(db) => db,
+            None => {
+                let db = open_or_create_database(&client, &group_cfg.db).await?;
+                databases.entry(group_cfg.db.clone()).or_insert(db)
+            }
+        };
+        let collection =
+            open_or_create_collection(db, &group_cfg.collection, &cfg.partition).await?;
+        let index_collection = match &group_cfg.index_collection {
+            Some(name) => Some(open_or_create_collection(db, name, &cfg.partition).await?),
+            None => None,
+        };
+        groups.push(WriterGroup {
+            collection,
+            index_collection,
+            writers: group_cfg.writers,
+        });
+    }
+    let collection = groups[0].collection.clone();
+
+    let checkpoint = if run_args.resume {
+        let path = match &cfg.state_file {
+            Some(path) => path,
+            None => anyhow::bail!(Failure::new(
+                FailureKind::Config,
+                "--resume requires the `state_file` config"
+            )),
+        };
+        let checkpoint = Checkpoint::load(path)?;
+        if cfg
+            .base_seed
+            .map(|s| s != checkpoint.base_seed)
+            .unwrap_or_default()
+        {
+            anyhow::bail!(Failure::new(
+                FailureKind::Config,
+                format!(
+                    "the base seed {} of checkpoint is different from the config",
+                    checkpoint.base_seed
+                )
+            ));
+        }
+        if cfg
+            .run_id
+            .as_ref()
+            .map(|id| *id != checkpoint.run_id)
+            .unwrap_or_default()
+        {
+            anyhow::bail!(Failure::new(
+                FailureKind::Config,
+                format!(
+                    "the run id {:?} of checkpoint is different from the config",
+                    checkpoint.run_id
+                )
+            ));
+        }
+        info!("resume from checkpoint {}", path.display());
+        Some(checkpoint)
+    } else {
+        None
+    };
+
+    let base_seed = if let Some(checkpoint) = &checkpoint {
+        checkpoint.base_seed
+    } else if let Some(base_seed) = cfg.base_seed {
         base_seed
     } else {
         OsRng.next_u64()
     };
+    let run_id = match &checkpoint {
+        Some(checkpoint) => checkpoint.run_id.clone(),
+        None => cfg
+            .run_id
+            .clone()
+            .unwrap_or_else(|| default_run_id(base_seed)),
+    };
+    cfg.generator.namespace = run_id.clone();
 
-    info!("chaos start with base seed {}", base_seed);
+    info!(
+        "chaos start with base seed {}, reproduce the run with `--base-seed {}`",
+        base_seed, base_seed
+    );
+    info!(
+        "the keys of the run are in the namespace of run id {:?}",
+        run_id
+    );
+    if !cfg.seeds.is_empty() {
+        info!(
+            "the seeds of the first writers are pinned to {:?}",
+            cfg.seeds
+        );
+    }
+
+    let op_timeout = Duration::from_millis(cfg.op_timeout_ms);
+    let warmup_deadline = Instant::now() + Duration::from_secs(cfg.warmup_secs);
+    let history = History::new();
+    let relaxation = Arc::new(Relaxation::default());
+    let tunables = Arc::new(Tunables::new(loaded_cfg.tunable_options()));
+    let slow_op_threshold = cfg.slow_op_threshold_ms.map(Duration::from_millis);
+    let writer_opts = WriterOptions {
+        op_timeout,
+        slow_op_threshold,
+        warmup_deadline,
+        tunables: tunables.clone(),
+        history: history.clone(),
+        max_verify_lag_steps: cfg.max_verify_lag_steps,
+        max_steps: cfg.max_steps_per_writer,
+        relaxation: relaxation.clone(),
+        paused: Arc::default(),
+    };
+    let paused = writer_opts.paused.clone();
+    let reader_opts = ReaderOptions {
+        op_timeout,
+        slow_op_threshold,
+        warmup_deadline,
+        max_lag_steps: cfg.max_lag_steps,
+        max_lag: Duration::from_millis(cfg.max_lag_ms),
+        verify_deadline: Duration::from_secs(cfg.verify_deadline_secs),
+        sample_interval: cfg.verify_sample_interval,
+        max_expected_keys: cfg.verify_max_expected_keys,
+        crash_interval: cfg.reader_crash_interval_secs.map(Duration::from_secs),
+        delete_grace: Duration::from_millis(cfg.delete_grace_ms),
+        negative_lookup_interval: cfg.negative_lookup_interval_ms.map(Duration::from_millis),
+        tunables: tunables.clone(),
+        history: history.clone(),
+        relaxation: relaxation.clone(),
+        fault_log: fault_log.clone(),
+    };
 
     let exec_ctx = ExecCtx::new();
 
-    let mut writers: Vec<Arc<dyn crate::base::Writer>> = vec![];
-    let mut writer_handles = vec![];
-    for idx in 0..cfg.writers {
-        let seed = base_seed.wrapping_add(idx as u64);
-        let writer = Arc::new(Writer::new(
-            idx,
-            seed,
-            cfg.generator.clone(),
-            collection.clone(),
-        ));
-        writers.push(writer.clone());
+    // Subscribe before any writer is spawned, so no event is missed.
+    let mut recorder_handle = None;
+    if let Some(path) = &cfg.history_file {
+        let recorder = HistoryRecorder::new(path.clone(), &history);
+        let cloned_ctx = exec_ctx.clone();
+        recorder_handle = Some(tokio::spawn(async move {
+            recorder.run(cloned_ctx).await;
+        }));
+    }
+
+    let mut cluster_handle = None;
+    if let Some(cluster) = &cluster {
+        let cloned_cluster = cluster.clone();
+        let cloned_ctx = exec_ctx.clone();
+        cluster_handle = Some(tokio::spawn(async move {
+            cloned_cluster.run(cloned_ctx).await;
+        }));
+    }
+
+    let visibility_meter = Arc::new(VisibilityMeter::new(&history));
+    let cloned_meter = visibility_meter.clone();
+    let cloned_ctx = exec_ctx.clone();
+    let visibility_handle = tokio::spawn(async move {
+        cloned_meter.run(cloned_ctx).await;
+    });
+
+    // The coverage is tracked only if any fault is configured.
+    let mut coverage = None;
+    if !cfg.nemesis.is_empty() || !cfg.chaos.is_empty() || cfg.upgrade.is_some() {
+        let tracker = Arc::new(CoverageTracker::new(&history, fault_log.clone()));
+        let cloned_tracker = tracker.clone();
         let cloned_ctx = exec_ctx.clone();
         let handle = tokio::spawn(async move {
-            writer.run(cloned_ctx).await;
+            cloned_tracker.run(cloned_ctx).await;
         });
-        writer_handles.push(handle);
+        coverage = Some((tracker, handle));
     }
 
-    let mut readers: Vec<Arc<dyn crate::base::Reader>> = vec![];
-    let mut reader_handles = vec![];
-    for idx in 0..cfg.readers {
-        if idx >= cfg.writers {
-            break;
+    let mut shadow = None;
+    if cfg.shadow_store {
+        // The store has its own context, so it is stopped once the writers are stopped.
+        let shadow_ctx = ExecCtx::new();
+        let store = Arc::new(ShadowStore::new(&history));
+        let cloned_store = store.clone();
+        let cloned_ctx = shadow_ctx.clone();
+        let handle = tokio::spawn(async move {
+            cloned_store.run(cloned_ctx).await;
+        });
+        shadow = Some((store, shadow_ctx, handle));
+    }
+
+    let mut controller = Controller::new(
+        Seeds::new(base_seed, cfg.seeds.clone()),
+        cfg.generator.clone(),
+        groups,
+        writer_opts,
+        reader_opts,
+    );
+    if let Some(checkpoint) = &checkpoint {
+        for snapshot in &checkpoint.writers {
+            controller.spawn_writer(Some(snapshot));
         }
-        let mut traced_writers = vec![];
-        let mut writer_idx = idx;
-        while writer_idx < cfg.writers {
-            traced_writers.push(writers[writer_idx].clone());
-            writer_idx += cfg.readers;
+    } else {
+        let writers = cfg.writers
+            + cfg
+                .collection_groups
+                .iter()
+                .map(|g| g.writers)
+                .sum::<usize>();
+        for _ in 0..writers {
+            controller.spawn_writer(None);
         }
+    }
+    controller.spawn_readers(
+        cfg.readers,
+        cfg.readers_per_writer,
+        checkpoint.as_ref().map(|c| c.trackers.as_slice()),
+    );
 
-        let reader = Arc::new(Reader::new(idx, traced_writers, collection.clone()));
-        readers.push(reader.clone());
-        let cloned_ctx = ExecCtx::new();
-        let handle = tokio::spawn(async move {
-            reader.run(cloned_ctx).await;
-        });
-        reader_handles.push(handle);
+    let mut checkpointer_handle = None;
+    if let Some(path) = &cfg.state_file {
+        let checkpointer = Checkpointer::new(
+            base_seed,
+            run_id.clone(),
+            path.clone(),
+            Duration::from_secs(cfg.checkpoint_interval_secs),
+            controller.writers(),
+            controller.readers(),
+        );
+        let cloned_ctx = exec_ctx.clone();
+        checkpointer_handle = Some(tokio::spawn(async move {
+            checkpointer.run(cloned_ctx).await;
+        }));
+    }
+
+    // The faults are recovered before the run is stopped, so they have their own context.
+    let chaos_ctx = ExecCtx::new();
+    let mut nemesis_handles = vec![];
+    let mut nemeses = vec![];
+    for (i, (nemesis_cfg, fault)) in cfg.nemesis.iter().zip(faults).enumerate() {
+        let nemesis = Arc::new(Nemesis::new(
+            nemesis_cfg.clone(),
+            base_seed.wrapping_add(i as u64).rotate_left(16),
+            fault,
+            chaos_env.node_names(),
+            fault_log.clone(),
+            relaxation.clone(),
+        ));
+        nemeses.push(nemesis.clone());
+        let cloned_ctx = chaos_ctx.clone();
+        nemesis_handles.push(tokio::spawn(async move {
+            nemesis.run(cloned_ctx).await;
+        }));
+    }
+
+    if !chaos_steps.is_empty() {
+        let scheduler = ChaosScheduler::new(chaos_steps, fault_log.clone(), relaxation.clone());
+        let cloned_ctx = chaos_ctx.clone();
+        nemesis_handles.push(tokio::spawn(async move {
+            scheduler.run(cloned_ctx).await;
+        }));
+    }
+    if let Some(upgrade) = upgrade {
+        let cloned_ctx = chaos_ctx.clone();
+        nemesis_handles.push(tokio::spawn(async move {
+            upgrade.run(cloned_ctx).await;
+        }));
+    }
+
+    let (sender, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(read_commands_from_stdin(sender.clone()));
+    tokio::spawn(stop_on_signals(sender.clone(), fault_log.clone()));
+    let tui = run_args.tui;
+    tokio::spawn(reload_on_hangup(args, loaded_cfg, tunables, nemeses));
+    let mut admin_handle = None;
+    if let Some(addr) = &cfg.admin_addr {
+        let server = AdminServer::bind(
+            addr,
+            controller.writers(),
+            controller.readers(),
+            paused.clone(),
+            sender.clone(),
+            log_filter,
+        )
+        .await?;
+        let cloned_ctx = exec_ctx.clone();
+        admin_handle = Some(tokio::spawn(async move {
+            server.run(cloned_ctx).await;
+        }));
+    }
+
+    let mut statsd_handle = None;
+    if let Some(statsd_cfg) = cfg.statsd.clone() {
+        let exporter =
+            StatsdExporter::connect(statsd_cfg, controller.writers(), controller.readers()).await?;
+        let cloned_ctx = exec_ctx.clone();
+        statsd_handle = Some(tokio::spawn(async move {
+            exporter.run(cloned_ctx).await;
+        }));
+    }
+    let mut progress_handle = None;
+    if let Some(secs) = cfg.progress_report_secs {
+        let reporter = ProgressReporter::new(
+            controller.writers(),
+            controller.readers(),
+            Duration::from_secs(secs),
+        );
+        let cloned_ctx = exec_ctx.clone();
+        progress_handle = Some(tokio::spawn(async move {
+            reporter.run(cloned_ctx).await;
+        }));
+    }
+    let mut latency_handle = None;
+    if let Some(secs) = cfg.latency_report_secs {
+        let reporter = LatencyReporter::new(
+            controller.writers(),
+            controller.readers(),
+            Duration::from_secs(secs),
+        );
+        let cloned_ctx = exec_ctx.clone();
+        latency_handle = Some(tokio::spawn(async move {
+            reporter.run(cloned_ctx).await;
+        }));
+    }
+    let mut dashboard_handle = None;
+    if tui {
+        let dashboard = Dashboard::new(
+            controller.writers(),
+            controller.readers(),
+            fault_log.clone(),
+            paused,
+            log_lines,
+        );
+        let cloned_ctx = exec_ctx.clone();
+        dashboard_handle = Some(tokio::spawn(async move {
+            dashboard.run(cloned_ctx).await;
+        }));
     }
 
+    let error_budget = cfg
+        .error_budget
+        .clone()
+        .map(|c| ErrorBudget::new(c, controller.writers(), controller.readers()));
+
+    let mut reporter = None;
+    if cfg.report_file.is_some() || cfg.junit_file.is_some() {
+        reporter = Some(RunReporter::new(
+            cfg.report_file.clone(),
+            cfg.junit_file.clone(),
+            base_seed,
+            config_hash.clone(),
+            controller.writers(),
+            controller.readers(),
+            fault_log.clone(),
+        ));
+    }
+
+    let pushgateway = match cfg.pushgateway.clone() {
+        Some(pushgateway_cfg) => Some(Pushgateway::new(
+            pushgateway_cfg,
+            run_id,
+            base_seed,
+            config_hash,
+            controller.writers(),
+            controller.readers(),
+        )?),
+        None => None,
+    };
+
     info!("chaos is running");
 
-    for writer in writer_handles {
-        writer.await.unwrap_or_default();
+    let limits = wait_for_limits(
+        controller.writers(),
+        cfg.max_duration_secs.map(Duration::from_secs),
+        cfg.max_steps_per_writer,
+    );
+    // The exhausted error budget fails the run without the final verification, but the faults,
+    // the tasks and the backends are still stopped as usual.
+    let mut budget_result = Ok(());
+    tokio::select! {
+        _ = controller.run(receiver) => {}
+        _ = limits => {}
+        result = async move {
+            match error_budget {
+                Some(error_budget) => error_budget.watch().await,
+                None => std::future::pending().await,
+            }
+        } => {
+            budget_result = result;
+        }
+    }
+    let budget_exhausted = budget_result.is_err();
+    drop(sender);
+    info!("chaos is stopping");
+    drop(chaos_ctx);
+    for handle in nemesis_handles {
+        handle.await.unwrap_or_default();
+    }
+    controller.stop_writers().await;
+    controller.stop_readers().await;
+    let shadow = match shadow {
+        Some((store, shadow_ctx, handle)) => {
+            drop(shadow_ctx);
+            handle.await.unwrap_or_default();
+            Some(store)
+        }
+        None => None,
+    };
+    if let Some(reporter) = &reporter {
+        if !budget_exhausted {
+            reporter.start_final_verify();
+        }
+    }
+    let mut result = if budget_exhausted {
+        budget_result
+    } else if cfg.final_verify {
+        controller
+            .verify_quiescent(Duration::from_secs(cfg.settle_secs))
+            .await
+    } else {
+        Ok(())
+    };
+    if let Some(store) = &shadow {
+        if cfg.final_verify && result.is_ok() {
+            result = store.diff(&collection, op_timeout).await;
+        }
+    }
+
+    drop(exec_ctx);
+    if let Some(handle) = checkpointer_handle {
+        handle.await.unwrap_or_default();
+    }
+    if let Some(handle) = recorder_handle {
+        handle.await.unwrap_or_default();
+    }
+    visibility_handle.await.unwrap_or_default();
+    if let Some(handle) = admin_handle {
+        handle.await.unwrap_or_default();
+    }
+    if let Some(handle) = statsd_handle {
+        handle.await.unwrap_or_default();
+    }
+    if let Some(handle) = progress_handle {
+        handle.await.unwrap_or_default();
+    }
+    if let Some(handle) = latency_handle {
+        handle.await.unwrap_or_default();
+    }
+    if let Some(handle) = dashboard_handle {
+        handle.await.unwrap_or_default();
+    }
+    if let Some((tracker, handle)) = coverage {
+        handle.await.unwrap_or_default();
+        if let Err(e) = tracker.finish(cfg.coverage_file.as_ref(), &chaos_env.node_names()) {
+            error!("report chaos coverage: {}", e);
+        }
+    }
+    if let Some(handle) = cluster_handle {
+        handle.await.unwrap_or_default();
+    }
+    if let Some(cluster) = &cluster {
+        cluster.stop().await;
+    }
+    if let Some(docker) = &docker {
+        docker.stop().await;
+    }
+    info!(
+        "read-after-write visibility latency: {}",
+        visibility_meter.latency()
+    );
+    if let Some(reporter) = &reporter {
+        let final_verify = match (&result, cfg.final_verify) {
+            _ if budget_exhausted => VerifyOutcome::NotRun,
+            (_, false) => VerifyOutcome::Skipped,
+            (Ok(()), true) => VerifyOutcome::Passed,
+            (Err(_), true) => VerifyOutcome::Failed,
+        };
+        reporter.finish(final_verify, result.as_ref().err().map(ToString::to_string));
+    }
+    if let Some(pushgateway) = &pushgateway {
+        pushgateway.finish(result.is_ok()).await;
+    }
+
+    result
+}
+
+/// Reload the config at SIGHUP, and apply the changes of the tunable options and the timing of
+/// nemeses. The config is rejected if any other field is changed, since the writers, the seeds
+/// and the schedule are a part of the deterministic state of the run.
+async fn reload_on_hangup(
+    args: Args,
+    mut cfg: AppConfig,
+    tunables: Arc<Tunables>,
+    nemeses: Vec<Arc<Nemesis>>,
+) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            error!("install SIGHUP handler: {}", e);
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        info!("receive SIGHUP, reload config {}", args.config.display());
+        let new_cfg = match AppConfig::load(&args) {
+            Ok(new_cfg) => new_cfg,
+            Err(e) => {
+                error!("reload config: {}", e);
+                continue;
+            }
+        };
+        match cfg.structural_changes(&new_cfg) {
+            Ok(changes) if changes.is_empty() => {}
+            Ok(changes) => {
+                error!(
+                    "reject to reload config, the changes of {:?} require a restart",
+                    changes
+                );
+                continue;
+            }
+            Err(e) => {
+                error!("reload config: {}", e);
+                continue;
+            }
+        }
+        tunables.set(new_cfg.tunable_options());
+        for (nemesis, nemesis_cfg) in nemeses.iter().zip(&new_cfg.nemesis) {
+            nemesis.update(nemesis_cfg.clone());
+        }
+        info!("reload config success, {:?}", tunables.get());
+        cfg = new_cfg;
+    }
+}
+
+/// Wait until the run lasts `max_duration`, or every writer reaches `max_steps`.
+async fn wait_for_limits(
+    writers: Writers,
+    max_duration: Option<Duration>,
+    max_steps: Option<usize>,
+) {
+    if max_duration.is_none() && max_steps.is_none() {
+        return std::future::pending().await;
+    }
+    let start = Instant::now();
+    loop {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        if let Some(max_duration) = max_duration {
+            if start.elapsed() >= max_duration {
+                info!("the run reaches the max duration {:?}", max_duration);
+                return;
+            }
+        }
+        if let Some(max_steps) = max_steps {
+            let writers = writers.lock().unwrap();
+            if !writers.is_empty() && writers.iter().all(|w| w.current_step() >= max_steps) {
+                info!("all writers reach the max steps {}", max_steps);
+                return;
+            }
+        }
+    }
+}
+
+/// The number of ops of each writer printed by the dry run.
+const DRY_RUN_OPS: usize = 5;
+
+/// Open the database, or create it if it does not exist, so the runs could share a cluster.
+async fn open_or_create_database(client: &EngulaClient, name: &str) -> Result<Database> {
+    match client.open_database(name.to_owned()).await {
+        Ok(db) => {
+            info!("open database {} success", name);
+            return Ok(db);
+        }
+        Err(AppError::NotFound(_)) => {}
+        Err(e) => return Err(e.into()),
+    }
+    match client.create_database(name.to_owned()).await {
+        Ok(db) => {
+            info!("create database {} success", name);
+            Ok(db)
+        }
+        // Another run creates it concurrently.
+        Err(AppError::AlreadyExists(_)) => Ok(client.open_database(name.to_owned()).await?),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Open the collection, or create it with the partition if it does not exist, so the runs could
+/// share a collection, see [`AppConfig::run_id`]. The partition of an existing collection is
+/// not changed.
+async fn open_or_create_collection(
+    db: &Database,
+    name: &str,
+    partition: &PartitionConfig,
+) -> Result<Collection> {
+    match db.open_collection(name.to_owned()).await {
+        Ok(collection) => {
+            info!("open collection {} success", name);
+            return Ok(collection);
+        }
+        Err(AppError::NotFound(_)) => {}
+        Err(e) => return Err(e.into()),
+    }
+    match db
+        .create_collection(name.to_owned(), Some(partition.to_partition()))
+        .await
+    {
+        Ok(collection) => {
+            info!("create collection {} success", name);
+            Ok(collection)
+        }
+        Err(AppError::AlreadyExists(_)) => Ok(db.open_collection(name.to_owned()).await?),
+        Err(e) => Err(e.into()),
     }
+}
+
+/// Return the run id if it is not specified, see [`AppConfig::run_id`].
+fn default_run_id(base_seed: u64) -> String {
+    format!("{}-{}", base_seed, now_us() / 1_000_000)
+}
 
-    for reader in reader_handles {
-        reader.await.unwrap_or_default();
+/// Pair the last step executed by each writer with its index, for `audit` and `clean`.
+fn writer_steps(writers: usize, steps: Vec<usize>) -> Result<Vec<(usize, usize)>> {
+    if steps.len() != writers {
+        anyhow::bail!(Failure::new(
+            FailureKind::Config,
+            format!(
+                "the number of steps {} is different from writers {}",
+                steps.len(),
+                writers
+            )
+        ));
     }
+    Ok(steps.into_iter().enumerate().collect())
+}
 
+/// Print the writers, their seeds and first ops, the workload, the partition and the chaos
+/// schedule of the run.
+fn print_plan(cfg: &AppConfig, base_seed: u64) -> Result<()> {
+    let seeds = Seeds::new(base_seed, cfg.seeds.clone());
+    println!("base seed: {}", base_seed);
+    println!("run id: {:?}", cfg.generator.namespace);
+    println!("partition: {:?}", cfg.partition);
+    println!(
+        "workload: key range {:?}, value range {:?}, cleanup interval {:?}, duplicate ratio {}, read-your-writes ratio {}, think time {:?}",
+        cfg.generator.key_range,
+        cfg.generator.value_range,
+        cfg.generator.cleanup_interval,
+        cfg.generator.duplicate_ratio,
+        cfg.read_your_writes_ratio,
+        cfg.think_time,
+    );
+    println!(
+        "limits: duration {:?} secs, steps per writer {:?}, final verify {}",
+        cfg.max_duration_secs, cfg.max_steps_per_writer, cfg.final_verify
+    );
+    println!(
+        "readers: {}, each writer is verified by {}",
+        cfg.readers, cfg.readers_per_writer
+    );
+    let groups = std::iter::once((&cfg.db, &cfg.collection, cfg.writers)).chain(
+        cfg.collection_groups
+            .iter()
+            .map(|g| (&g.db, &g.collection, g.writers)),
+    );
+    let mut idx = 0;
+    for (db, collection, writers) in groups {
+        for _ in 0..writers {
+            let seed = seeds.writer(idx);
+            println!("writer {} of {}/{}, seed {}", idx, db, collection, seed);
+            print_ops(
+                &mut Generator::new(seed, idx as u64, cfg.generator.clone()),
+                DRY_RUN_OPS,
+            );
+            idx += 1;
+        }
+    }
+    for nemesis in &cfg.nemesis {
+        println!("nemesis: {}", serde_json::to_string(nemesis)?);
+    }
+    for step in &cfg.chaos {
+        println!("chaos: {}", serde_json::to_string(step)?);
+    }
     Ok(())
 }
 
+/// Print the next `steps` ops of the generator.
+fn print_ops(gen: &mut Generator, steps: usize) {
+    for step in 1..=steps {
+        let op = gen.next_op();
+        // The key ends with the index of writer in binary.
+        let key = op.key();
+        let key = String::from_utf8_lossy(&key[..key.len().saturating_sub(8)]);
+        match &op {
+            NextOp::Put { value, .. } => {
+                println!("  step {} put {} ({} bytes)", step, key, value.len())
+            }
+            NextOp::Delete { .. } => println!("  step {} delete {}", step, key),
+        }
+    }
+}
+
 fn install_panic_hook() {
     use std::{panic, process};
     let orig_hook = panic::take_hook();
@@ -145,7 +1509,8 @@ fn install_panic_hook() {
         orig_hook(panic_info);
         error!("{:#?}", panic_info);
         error!("{:#?}", std::backtrace::Backtrace::force_capture());
-        process::exit(1);
+        summary::report_panic(panic_info.to_string());
+        process::exit(FailureKind::of_panic().exit_code());
     }));
 }
 
@@ -154,15 +1519,69 @@ impl Default for AppConfig {
         AppConfig {
             writers: 1,
             readers: 1,
-            hash_slots: 255,
+            readers_per_writer: 1,
             addrs: vec!["127.0.0.1:21805".to_owned()],
             db: "chaos-db".to_owned(),
             collection: "collection".to_owned(),
+            index_collection: None,
+            max_duration_secs: None,
+            max_steps_per_writer: None,
+            op_timeout_ms: 2000,
+            client_connect_timeout_ms: 200,
+            client_timeout_ms: 500,
+            slow_op_threshold_ms: None,
+            warmup_secs: 0,
+            max_lag_steps: 1,
+            max_lag_ms: 0,
+            verify_deadline_secs: 120,
+            verify_sample_interval: 1,
+            verify_max_expected_keys: None,
+            reader_crash_interval_secs: None,
+            delete_grace_ms: 0,
+            negative_lookup_interval_ms: None,
+            verify_interval_ms: 10,
+            verify_ops_per_wakeup: 1,
+            verify_idle_interval_ms: 10,
+            read_your_writes_ratio: 0.0,
+            max_verify_lag_steps: None,
+            think_time: None,
             base_seed: None,
+            run_id: None,
+            seeds: vec![],
+            partition: PartitionConfig::Hash { slots: 255 },
             generator: Config {
+                cleanup_interval: None,
+                duplicate_ratio: 0.0,
+                affinity_slot: None,
+                hash_slots: 255,
+                namespace: String::new(),
                 key_range: 16..32,
                 value_range: 512..2048,
             },
+            final_verify: true,
+            settle_secs: 5,
+            shadow_store: false,
+            state_file: None,
+            checkpoint_interval_secs: 10,
+            history_file: None,
+            coverage_file: None,
+            report_file: None,
+            junit_file: None,
+            admin_addr: None,
+            otlp_endpoint: None,
+            progress_report_secs: Some(10),
+            latency_report_secs: None,
+            error_budget: None,
+            statsd: None,
+            pushgateway: None,
+            cluster: None,
+            toxiproxy: None,
+            kubernetes: None,
+            docker: None,
+            upgrade: None,
+            collection_groups: vec![],
+            nemesis: vec![],
+            chaos: vec![],
         }
     }
 }
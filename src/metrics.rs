@@ -0,0 +1,153 @@
+use std::{fmt::Write, sync::Arc};
+
+use crate::{
+    base::{reader_lag, ErrorStats, Reader, Writer},
+    histogram::HistogramSnapshot,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetricKind {
+    Gauge,
+    Counter,
+}
+
+/// A sample of a metric, in the data model of Prometheus.
+#[derive(Clone, Debug)]
+pub struct Metric {
+    pub name: &'static str,
+    pub kind: MetricKind,
+    pub labels: Vec<(&'static str, String)>,
+    pub value: f64,
+}
+
+impl Metric {
+    fn new(
+        name: &'static str,
+        kind: MetricKind,
+        labels: Vec<(&'static str, String)>,
+        value: f64,
+    ) -> Self {
+        Metric {
+            name,
+            kind,
+            labels,
+            value,
+        }
+    }
+}
+
+const QUANTILES: &[(&str, f64)] = &[("0.5", 0.5), ("0.9", 0.9), ("0.99", 0.99), ("0.999", 0.999)];
+
+fn ops_metrics(metrics: &mut Vec<Metric>, task: &'static str, index: usize, stats: &ErrorStats) {
+    let labels = vec![("task", task.to_owned()), ("index", index.to_string())];
+    metrics.push(Metric::new(
+        "supervisor_ops_total",
+        MetricKind::Counter,
+        labels.clone(),
+        stats.succeeds() as f64,
+    ));
+    for (class, num) in stats.breakdown() {
+        let mut labels = labels.clone();
+        labels.push(("class", class.as_str().to_owned()));
+        metrics.push(Metric::new(
+            "supervisor_errors_total",
+            MetricKind::Counter,
+            labels,
+            num as f64,
+        ));
+    }
+}
+
+fn latency_metrics(
+    metrics: &mut Vec<Metric>,
+    task: &'static str,
+    index: usize,
+    op: &'static str,
+    latency: &HistogramSnapshot,
+) {
+    let labels = vec![
+        ("task", task.to_owned()),
+        ("index", index.to_string()),
+        ("op", op.to_owned()),
+    ];
+    for (name, q) in QUANTILES {
+        let mut labels = labels.clone();
+        labels.push(("quantile", (*name).to_owned()));
+        metrics.push(Metric::new(
+            "supervisor_latency_seconds",
+            MetricKind::Gauge,
+            labels,
+            latency.percentile(*q).as_secs_f64(),
+        ));
+    }
+}
+
+/// Collect the metrics of the writers and the readers, the latencies are over the whole run.
+pub fn collect(writers: &[Arc<dyn Writer>], readers: &[Arc<dyn Reader>]) -> Vec<Metric> {
+    let mut metrics = vec![];
+    for w in writers {
+        let labels = vec![("index", w.index().to_string())];
+        let step = w.current_step();
+        metrics.push(Metric::new(
+            "supervisor_writer_step",
+            MetricKind::Gauge,
+            labels.clone(),
+            step as f64,
+        ));
+        metrics.push(Metric::new(
+            "supervisor_writer_verify_lag_steps",
+            MetricKind::Gauge,
+            labels,
+            w.verify_progress().lag(step).unwrap_or_default() as f64,
+        ));
+        ops_metrics(&mut metrics, "writer", w.index(), w.error_stats());
+        let latencies = w.latencies();
+        latency_metrics(&mut metrics, "writer", w.index(), "put", &latencies.put);
+        latency_metrics(
+            &mut metrics,
+            "writer",
+            w.index(),
+            "delete",
+            &latencies.delete,
+        );
+        latency_metrics(&mut metrics, "writer", w.index(), "get", &latencies.get);
+    }
+    for r in readers {
+        metrics.push(Metric::new(
+            "supervisor_reader_lag_steps",
+            MetricKind::Gauge,
+            vec![("index", r.index().to_string())],
+            reader_lag(r.index(), writers) as f64,
+        ));
+        ops_metrics(&mut metrics, "reader", r.index(), r.error_stats());
+        latency_metrics(&mut metrics, "reader", r.index(), "get", &r.get_latency());
+    }
+    metrics
+}
+
+/// Render the metrics in the text exposition format of Prometheus.
+pub fn render_prometheus(metrics: &[Metric]) -> String {
+    // The samples of a metric must be grouped together.
+    let mut metrics = metrics.iter().collect::<Vec<_>>();
+    metrics.sort_by_key(|m| m.name);
+    let mut out = String::new();
+    let mut last_name = "";
+    for m in metrics {
+        if m.name != last_name {
+            let kind = match m.kind {
+                MetricKind::Gauge => "gauge",
+                MetricKind::Counter => "counter",
+            };
+            writeln!(out, "# TYPE {} {}", m.name, kind).unwrap();
+            last_name = m.name;
+        }
+        let labels = m
+            .labels
+            .iter()
+            .map(|(k, v)| format!("{}=\"{}\"", k, v))
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(out, "{}{{{}}} {}", m.name, labels, m.value).unwrap();
+    }
+    out
+}
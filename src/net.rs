@@ -0,0 +1,247 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+use tracing::{debug, error};
+
+use crate::{chaos::Fault, cluster::Cluster};
+
+/// The comment (or table) attached to the rules installed by the supervisor.
+const RULE_TAG: &str = "engula_chaos";
+
+/// The backend to drop the packets between nodes.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Firewall {
+    Iptables,
+    Nftables,
+}
+
+impl Firewall {
+    /// The commands to drop the packets from `src` on the host of the receiver.
+    fn block(self, src: &str) -> Vec<Vec<String>> {
+        match self {
+            Firewall::Iptables => vec![iptables_rule("-I", src)],
+            Firewall::Nftables => vec![
+                args(&["nft", "add", "table", "inet", RULE_TAG]),
+                args(&[
+                    "nft",
+                    "add",
+                    "chain",
+                    "inet",
+                    RULE_TAG,
+                    "input",
+                    "{ type filter hook input priority 0 ; }",
+                ]),
+                args(&[
+                    "nft", "add", "rule", "inet", RULE_TAG, "input", "ip", "saddr", src, "drop",
+                ]),
+            ],
+        }
+    }
+
+    fn unblock(self, src: &str) -> Vec<Vec<String>> {
+        match self {
+            Firewall::Iptables => vec![iptables_rule("-D", src)],
+            // All rules are removed with the table.
+            Firewall::Nftables => vec![args(&["nft", "delete", "table", "inet", RULE_TAG])],
+        }
+    }
+}
+
+fn iptables_rule(op: &str, src: &str) -> Vec<String> {
+    args(&[
+        "iptables",
+        op,
+        "INPUT",
+        "-s",
+        src,
+        "-m",
+        "comment",
+        "--comment",
+        RULE_TAG,
+        "-j",
+        "DROP",
+    ])
+}
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|s| s.to_string()).collect()
+}
+
+/// Return the host of a node address `host:port`.
+pub fn host_of(addr: &str) -> &str {
+    addr.rsplit_once(':').map(|(host, _)| host).unwrap_or(addr)
+}
+
+/// Run a command on the host, through `remote_shell` if it is specified, eg `["ssh", "{host}"]`.
+pub async fn run_on(host: &str, remote_shell: &Option<Vec<String>>, cmd: &[String]) -> Result<()> {
+    let mut argv = match remote_shell {
+        Some(shell) => shell.iter().map(|s| s.replace("{host}", host)).collect(),
+        None => vec![],
+    };
+    argv.extend(cmd.iter().cloned());
+    debug!("run {:?} on host {}", argv, host);
+    let output = Command::new(&argv[0]).args(&argv[1..]).output().await?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "run {:?} on host {}: {}, {}",
+            argv,
+            host,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Partition the targets from the other nodes. The packets from the others to the targets are
+/// dropped, and the reverse direction too if it is symmetric. The nodes must have distinct hosts.
+pub struct PartitionFault {
+    pub cluster: Arc<Cluster>,
+    pub symmetric: bool,
+    pub firewall: Firewall,
+    pub remote_shell: Option<Vec<String>>,
+}
+
+impl PartitionFault {
+    /// Return the hosts which drop the packets, and the sources of the dropped packets.
+    fn links(&self, targets: &[String]) -> Result<Vec<(String, String)>> {
+        let mut target_hosts = vec![];
+        let mut other_hosts = vec![];
+        for node in self.cluster.node_configs() {
+            let host = host_of(&node.addr).to_owned();
+            if targets.contains(&node.name) {
+                target_hosts.push(host);
+            } else {
+                other_hosts.push(host);
+            }
+        }
+        let mut links = vec![];
+        for target in &target_hosts {
+            for other in &other_hosts {
+                if target == other {
+                    anyhow::bail!("the partitioned nodes share the host {}", target);
+                }
+                links.push((target.clone(), other.clone()));
+                if self.symmetric {
+                    links.push((other.clone(), target.clone()));
+                }
+            }
+        }
+        Ok(links)
+    }
+}
+
+#[super::async_trait]
+impl Fault for PartitionFault {
+    fn name(&self) -> &'static str {
+        if self.symmetric {
+            "partition"
+        } else {
+            "asymmetric_partition"
+        }
+    }
+
+    async fn inject(&self, targets: &[String]) -> Result<()> {
+        for (host, src) in self.links(targets)? {
+            for cmd in self.firewall.block(&src) {
+                run_on(&host, &self.remote_shell, &cmd).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn recover(&self, targets: &[String]) -> Result<()> {
+        // Heal the others even if a rule fails to be removed.
+        let mut cmds = vec![];
+        for (host, src) in self.links(targets)? {
+            for cmd in self.firewall.unblock(&src) {
+                // The table of nftables is deleted once per host.
+                if !cmds.contains(&(host.clone(), cmd.clone())) {
+                    cmds.push((host.clone(), cmd));
+                }
+            }
+        }
+        let mut result = Ok(());
+        for (host, cmd) in cmds {
+            if let Err(e) = run_on(&host, &self.remote_shell, &cmd).await {
+                error!("heal partition: {}", e);
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
+
+/// Add latency, jitter, reordering and loss to the packets sent from the interface of the
+/// targets by tc/netem.
+pub struct NetemFault {
+    pub cluster: Arc<Cluster>,
+    pub interface: String,
+    pub delay_ms: u64,
+    pub jitter_ms: u64,
+    pub loss_percent: f64,
+    pub reorder_percent: f64,
+    pub remote_shell: Option<Vec<String>>,
+}
+
+impl NetemFault {
+    fn hosts(&self, targets: &[String]) -> Vec<String> {
+        let mut hosts = vec![];
+        for node in self.cluster.node_configs() {
+            let host = host_of(&node.addr).to_owned();
+            if targets.contains(&node.name) && !hosts.contains(&host) {
+                hosts.push(host);
+            }
+        }
+        hosts
+    }
+}
+
+#[super::async_trait]
+impl Fault for NetemFault {
+    fn name(&self) -> &'static str {
+        "netem"
+    }
+
+    async fn inject(&self, targets: &[String]) -> Result<()> {
+        let mut cmd = args(&[
+            "tc",
+            "qdisc",
+            "add",
+            "dev",
+            &self.interface,
+            "root",
+            "netem",
+        ]);
+        cmd.extend([
+            "delay".to_owned(),
+            format!("{}ms", self.delay_ms),
+            format!("{}ms", self.jitter_ms),
+            "loss".to_owned(),
+            format!("{}%", self.loss_percent),
+        ]);
+        // The reordering takes effect only with a delay.
+        if self.reorder_percent > 0.0 {
+            cmd.extend(["reorder".to_owned(), format!("{}%", self.reorder_percent)]);
+        }
+        for host in self.hosts(targets) {
+            run_on(&host, &self.remote_shell, &cmd).await?;
+        }
+        Ok(())
+    }
+
+    async fn recover(&self, targets: &[String]) -> Result<()> {
+        let cmd = args(&["tc", "qdisc", "del", "dev", &self.interface, "root"]);
+        let mut result = Ok(());
+        for host in self.hosts(targets) {
+            if let Err(e) = run_on(&host, &self.remote_shell, &cmd).await {
+                error!("recover netem: {}", e);
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,344 @@
+use std::path::Path;
+
+use anyhow::Result;
+use serde::Serialize;
+use toml::{value::Table, Value};
+
+use crate::base::{Failure, FailureKind};
+
+/// The prefix of the environment variables of the config, the nested keys are separated by
+/// `__` too, eg `SUPERVISOR__GENERATOR__VALUE_RANGE`.
+const ENV_PREFIX: &str = "SUPERVISOR__";
+
+/// The format of the config file, by its extension.
+enum Format {
+    Toml,
+    Json,
+    Yaml,
+}
+
+/// Return the format of the config file by its extension, the file without extension is in
+/// TOML.
+fn format_of(path: &Path) -> Result<Format> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        None | Some("toml") => Ok(Format::Toml),
+        Some("json") => Ok(Format::Json),
+        Some("yaml" | "yml") => Ok(Format::Yaml),
+        Some(ext) => anyhow::bail!(Failure::new(
+            FailureKind::Config,
+            format!(
+                "the extension {:?} of config {} is not one of toml, json, yaml or yml",
+                ext,
+                path.display()
+            )
+        )),
+    }
+}
+
+/// Parse the content of the config file, the json and the YAML are converted to TOML so the
+/// profiles and the overrides are applied in the same way.
+pub fn parse(path: &Path, content: &str) -> Result<Value> {
+    let mut value: serde_json::Value = match format_of(path)? {
+        Format::Toml => return Ok(toml::from_str(content)?),
+        Format::Json => serde_json::from_str(content)?,
+        Format::Yaml => serde_yaml::from_str(content)?,
+    };
+    // TOML has no null, the missing fields are `None` too.
+    strip_nulls(&mut value);
+    Ok(Value::try_from(value)?)
+}
+
+/// Render the config in the format of the file, see [`format_of`].
+pub fn render<T: Serialize>(path: &Path, cfg: &T) -> Result<String> {
+    match format_of(path)? {
+        Format::Toml => Ok(toml::to_string_pretty(cfg)?),
+        Format::Json => Ok(serde_json::to_string_pretty(cfg)?),
+        Format::Yaml => Ok(serde_yaml::to_string(cfg)?),
+    }
+}
+
+fn strip_nulls(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            map.retain(|_, v| !v.is_null());
+            map.values_mut().for_each(strip_nulls);
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(strip_nulls),
+        _ => {}
+    }
+}
+
+/// Parse the value of an override. It is a TOML value, eg `8`, `0.5`, `true` or `["a", "b"]`,
+/// or a range `start..end` which is a table of `start` and `end`. Otherwise it is taken as a
+/// string, so the strings could be given without quotes, and the values separated by comma are
+/// taken as a list, eg `127.0.0.1:21805,127.0.0.1:21806`.
+fn parse_value(raw: &str) -> Value {
+    if let Some((start, end)) = raw.split_once("..") {
+        if let (Ok(start), Ok(end)) = (start.trim().parse::<i64>(), end.trim().parse::<i64>()) {
+            let mut range = Table::new();
+            range.insert("start".to_owned(), Value::Integer(start));
+            range.insert("end".to_owned(), Value::Integer(end));
+            return Value::Table(range);
+        }
+    }
+    match toml::from_str::<Table>(&format!("v = {}", raw)) {
+        Ok(mut table) => table.remove("v").unwrap(),
+        Err(_) if raw.contains(',') => {
+            Value::Array(raw.split(',').map(|v| parse_value(v.trim())).collect())
+        }
+        Err(_) => Value::String(raw.to_owned()),
+    }
+}
+
+/// Set the field at the dotted `key` of the config, eg `generator.value_range`, the missing
+/// tables on the way are created.
+pub fn set(root: &mut Value, key: &str, raw: &str) -> Result<()> {
+    let mut parts = key.split('.').collect::<Vec<_>>();
+    let last = parts.pop().unwrap_or_default();
+    if last.is_empty() || parts.iter().any(|p| p.is_empty()) {
+        anyhow::bail!("invalid config key {:?}", key);
+    }
+    let mut table = root;
+    for part in parts {
+        let entry = match table {
+            Value::Table(t) => t
+                .entry(part.to_owned())
+                .or_insert_with(|| Value::Table(Table::new())),
+            _ => anyhow::bail!("the parent of config key {:?} is not a table", key),
+        };
+        table = entry;
+    }
+    match table {
+        Value::Table(t) => {
+            let mut value = parse_value(raw);
+            // A single item of a list, eg `addrs=127.0.0.1:21805`.
+            if matches!(t.get(last), Some(Value::Array(_))) && !value.is_array() {
+                value = Value::Array(vec![value]);
+            }
+            t.insert(last.to_owned(), value);
+            Ok(())
+        }
+        _ => anyhow::bail!("the parent of config key {:?} is not a table", key),
+    }
+}
+
+/// Apply the overrides in the form of `key=value`, see [`set`].
+pub fn apply(root: &mut Value, overrides: &[String]) -> Result<()> {
+    for o in overrides {
+        let (key, raw) = match o.split_once('=') {
+            Some(kv) => kv,
+            None => anyhow::bail!("the override {:?} should be in the form of key=value", o),
+        };
+        set(root, key.trim(), raw.trim())?;
+    }
+    Ok(())
+}
+
+/// Merge the `overlay` into the `base`, the tables are merged recursively and the other values
+/// are replaced.
+pub fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Table(base), Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(v) => merge(v, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+/// Take the `profiles` table of the config, and merge the selected profile over the rest of
+/// it, eg `[profiles.soak]` is selected by `soak`.
+pub fn apply_profile(root: &mut Value, profile: Option<&str>) -> Result<()> {
+    let mut profiles = match root.as_table_mut().and_then(|t| t.remove("profiles")) {
+        Some(Value::Table(profiles)) => profiles,
+        Some(_) => anyhow::bail!("the profiles of config should be a table"),
+        None => Table::new(),
+    };
+    if let Some(name) = profile {
+        match profiles.remove(name) {
+            Some(overlay) => merge(root, overlay),
+            None => anyhow::bail!(
+                "the profile {:?} is not found in {:?}",
+                name,
+                profiles.keys().collect::<Vec<_>>()
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Apply the environment variables prefixed by `SUPERVISOR__`, eg `SUPERVISOR__WRITERS=8`, see
+/// [`set`].
+pub fn apply_env(root: &mut Value, vars: impl Iterator<Item = (String, String)>) -> Result<()> {
+    for (name, raw) in vars {
+        if let Some(key) = name.strip_prefix(ENV_PREFIX) {
+            let key = key.to_lowercase().replace("__", ".");
+            set(root, &key, raw.trim())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toml(s: &str) -> Value {
+        toml::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn parse_value_of_types() {
+        assert_eq!(parse_value("8"), Value::Integer(8));
+        assert_eq!(parse_value("0.5"), Value::Float(0.5));
+        assert_eq!(parse_value("true"), Value::Boolean(true));
+        assert_eq!(parse_value("abc"), Value::String("abc".to_owned()));
+        assert_eq!(parse_value(r#"["a", "b"]"#), toml(r#"v = ["a", "b"]"#)["v"]);
+    }
+
+    #[test]
+    fn parse_value_of_range() {
+        assert_eq!(parse_value("1024..4096"), toml("start = 1024\nend = 4096"));
+        assert_eq!(parse_value("1 .. 2"), toml("start = 1\nend = 2"));
+        // Not a range of integers.
+        assert_eq!(parse_value("a..b"), Value::String("a..b".to_owned()));
+    }
+
+    #[test]
+    fn parse_value_of_list() {
+        assert_eq!(
+            parse_value("127.0.0.1:21805, 127.0.0.1:21806"),
+            toml(r#"v = ["127.0.0.1:21805", "127.0.0.1:21806"]"#)["v"]
+        );
+        assert_eq!(parse_value("1,2"), toml("v = [1, 2]")["v"]);
+    }
+
+    #[test]
+    fn set_fields() {
+        let mut root = toml("writers = 1\naddrs = [\"a\"]");
+        set(&mut root, "writers", "8").unwrap();
+        // A single item is wrapped into the existing list.
+        set(&mut root, "addrs", "b").unwrap();
+        // The missing tables are created.
+        set(&mut root, "generator.value_range", "1..2").unwrap();
+        assert_eq!(
+            root,
+            toml(
+                r#"
+                writers = 8
+                addrs = ["b"]
+                [generator.value_range]
+                start = 1
+                end = 2
+                "#
+            )
+        );
+
+        assert!(set(&mut root, "", "1").is_err());
+        assert!(set(&mut root, "generator..seed", "1").is_err());
+        // The parent is not a table.
+        assert!(set(&mut root, "writers.num", "1").is_err());
+    }
+
+    #[test]
+    fn apply_overrides() {
+        let mut root = toml("writers = 1");
+        apply(
+            &mut root,
+            &["writers = 2".to_owned(), "run_id=x".to_owned()],
+        )
+        .unwrap();
+        assert_eq!(root, toml("writers = 2\nrun_id = \"x\""));
+        assert!(apply(&mut root, &["writers".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn merge_tables() {
+        let mut base = toml(
+            r#"
+            writers = 1
+            addrs = ["a", "b"]
+            [generator]
+            seed = 1
+            duplicate_ratio = 0.5
+            "#,
+        );
+        let overlay = toml(
+            r#"
+            addrs = ["c"]
+            [generator]
+            seed = 2
+            [statsd]
+            addr = "x"
+            "#,
+        );
+        merge(&mut base, overlay);
+        assert_eq!(
+            base,
+            toml(
+                r#"
+                writers = 1
+                addrs = ["c"]
+                [generator]
+                seed = 2
+                duplicate_ratio = 0.5
+                [statsd]
+                addr = "x"
+                "#
+            )
+        );
+    }
+
+    #[test]
+    fn apply_profiles() {
+        let config = r#"
+            writers = 1
+            [profiles.soak]
+            writers = 8
+            "#;
+        let mut root = toml(config);
+        apply_profile(&mut root, Some("soak")).unwrap();
+        assert_eq!(root, toml("writers = 8"));
+
+        let mut root = toml(config);
+        apply_profile(&mut root, None).unwrap();
+        assert_eq!(root, toml("writers = 1"));
+
+        assert!(apply_profile(&mut toml(config), Some("smoke")).is_err());
+    }
+
+    #[test]
+    fn apply_env_vars() {
+        let mut root = toml("writers = 1");
+        let vars = [
+            ("SUPERVISOR__WRITERS", "8"),
+            ("SUPERVISOR__A__B", "x"),
+            ("SUPERVISOR__GENERATOR__VALUE_RANGE", " 1..2 "),
+            ("PATH", "/bin"),
+        ];
+        apply_env(
+            &mut root,
+            vars.iter().map(|(k, v)| (k.to_string(), v.to_string())),
+        )
+        .unwrap();
+        assert_eq!(
+            root,
+            toml(
+                r#"
+                writers = 8
+                [a]
+                b = "x"
+                [generator.value_range]
+                start = 1
+                end = 2
+                "#
+            )
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use tracing::info;
+
+use crate::{
+    base::{reader_lag, ExecCtx, Reader, Writers},
+    histogram::{HistogramSnapshot, OpLatencies},
+};
+
+/// Log a summary line of each writer and reader periodically, so the shape of the run could be
+/// seen by a quick scroll of the log.
+pub struct ProgressReporter {
+    writers: Writers,
+    readers: Vec<Arc<dyn Reader>>,
+    interval: Duration,
+}
+
+impl ProgressReporter {
+    pub fn new(writers: Writers, readers: Vec<Arc<dyn Reader>>, interval: Duration) -> Self {
+        ProgressReporter {
+            writers,
+            readers,
+            interval,
+        }
+    }
+}
+
+#[super::async_trait]
+impl super::base::Task for ProgressReporter {
+    async fn run(&self, mut ctx: ExecCtx) {
+        let secs = self.interval.as_secs_f64();
+        let mut last_writers: HashMap<usize, usize> = HashMap::new();
+        let mut last_readers: HashMap<usize, usize> = HashMap::new();
+        while ctx
+            .wait_until_timeout_or_shutdown(self.interval)
+            .await
+            .is_some()
+        {
+            let writers = self.writers.lock().unwrap().clone();
+            for writer in &writers {
+                let stats = writer.error_stats();
+                let succeeds = stats.succeeds();
+                let last = last_writers.insert(writer.index(), succeeds);
+                let step = writer.current_step();
+                info!(
+                    "writer {} at step {}, {:.1} ops/s, verify lag {} steps, {}",
+                    writer.index(),
+                    step,
+                    (succeeds - last.unwrap_or_default()) as f64 / secs,
+                    writer.verify_progress().lag(step).unwrap_or_default(),
+                    stats,
+                );
+            }
+            for reader in &self.readers {
+                let stats = reader.error_stats();
+                let succeeds = stats.succeeds();
+                let last = last_readers.insert(reader.index(), succeeds);
+                info!(
+                    "reader {} lags {} steps, {:.1} reads/s, {}",
+                    reader.index(),
+                    reader_lag(reader.index(), &writers),
+                    (succeeds - last.unwrap_or_default()) as f64 / secs,
+                    stats,
+                );
+            }
+        }
+    }
+}
+
+/// Log the latency percentiles of each writer and reader periodically, over the ops completed
+/// in the last interval, so the tail latency during a fault is not hidden by the whole run.
+pub struct LatencyReporter {
+    writers: Writers,
+    readers: Vec<Arc<dyn Reader>>,
+    interval: Duration,
+}
+
+impl LatencyReporter {
+    pub fn new(writers: Writers, readers: Vec<Arc<dyn Reader>>, interval: Duration) -> Self {
+        LatencyReporter {
+            writers,
+            readers,
+            interval,
+        }
+    }
+}
+
+#[super::async_trait]
+impl super::base::Task for LatencyReporter {
+    async fn run(&self, mut ctx: ExecCtx) {
+        let mut last_writers: HashMap<usize, OpLatencies> = HashMap::new();
+        let mut last_readers: HashMap<usize, HistogramSnapshot> = HashMap::new();
+        while ctx
+            .wait_until_timeout_or_shutdown(self.interval)
+            .await
+            .is_some()
+        {
+            let writers = self.writers.lock().unwrap().clone();
+            for writer in writers {
+                let latencies = writer.latencies();
+                let last = last_writers.entry(writer.index()).or_default();
+                info!(
+                    "writer {} latency in the last {:?}, put: {}, delete: {}, get: {}",
+                    writer.index(),
+                    self.interval,
+                    latencies.put.since(&last.put),
+                    latencies.delete.since(&last.delete),
+                    latencies.get.since(&last.get),
+                );
+                *last = latencies;
+            }
+            for reader in &self.readers {
+                let latency = reader.get_latency();
+                let last = last_readers.entry(reader.index()).or_default();
+                info!(
+                    "reader {} latency in the last {:?}, get: {}",
+                    reader.index(),
+                    self.interval,
+                    latency.since(last),
+                );
+                *last = latency;
+            }
+        }
+    }
+}
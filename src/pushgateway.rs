@@ -0,0 +1,119 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tracing::{error, info};
+
+use crate::{
+    base::{Failure, FailureKind, Reader, Writers},
+    metrics::{self, Metric, MetricKind},
+    net::host_of,
+};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PushgatewayConfig {
+    /// The address of the Pushgateway, eg `127.0.0.1:9091`.
+    pub addr: String,
+    /// The `job` label of the pushed metrics.
+    pub job: String,
+    /// The `run_id` label of the pushed metrics, it is the run id of the run if it is not
+    /// specified, so the runs of a job do not replace the metrics of each other.
+    pub run_id: Option<String>,
+}
+
+/// Push the final metrics of a run to a Prometheus Pushgateway, for the short runs which end
+/// before they are scraped.
+pub struct Pushgateway {
+    cfg: PushgatewayConfig,
+    run_id: String,
+    base_seed: u64,
+    config_hash: String,
+    writers: Writers,
+    readers: Vec<Arc<dyn Reader>>,
+}
+
+impl Pushgateway {
+    pub fn new(
+        cfg: PushgatewayConfig,
+        run_id: String,
+        base_seed: u64,
+        config_hash: String,
+        writers: Writers,
+        readers: Vec<Arc<dyn Reader>>,
+    ) -> Result<Self> {
+        let run_id = cfg.run_id.clone().unwrap_or(run_id);
+        // The labels of the grouping key are the segments of the path.
+        if cfg.job.is_empty() || cfg.job.contains('/') || run_id.is_empty() || run_id.contains('/')
+        {
+            anyhow::bail!(Failure::new(
+                FailureKind::Config,
+                format!(
+                    "the job {:?} and the run id {:?} of pushgateway should be non-empty and without '/'",
+                    cfg.job, run_id
+                )
+            ));
+        }
+        Ok(Pushgateway {
+            cfg,
+            run_id,
+            base_seed,
+            config_hash,
+            writers,
+            readers,
+        })
+    }
+
+    async fn push(&self, passed: bool) -> Result<()> {
+        let writers = self.writers.lock().unwrap().clone();
+        let mut metrics = metrics::collect(&writers, &self.readers);
+        metrics.push(Metric {
+            name: "supervisor_run_passed",
+            kind: MetricKind::Gauge,
+            labels: vec![
+                ("base_seed", self.base_seed.to_string()),
+                ("config_hash", self.config_hash.clone()),
+            ],
+            value: if passed { 1.0 } else { 0.0 },
+        });
+        let body = metrics::render_prometheus(&metrics);
+        let path = format!("/metrics/job/{}/run_id/{}", self.cfg.job, self.run_id);
+        // PUT replaces all metrics of the group, so a retried push does not leave stale ones.
+        let request = format!(
+            "PUT {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            path,
+            host_of(&self.cfg.addr),
+            body.len(),
+            body
+        );
+        let mut stream = TcpStream::connect(&self.cfg.addr).await?;
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = vec![];
+        stream.read_to_end(&mut response).await?;
+        let response = String::from_utf8_lossy(&response);
+        let status_line = response.lines().next().unwrap_or_default();
+        let status = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|s| s.parse::<u16>().ok())
+            .unwrap_or_default();
+        if !(200..300).contains(&status) {
+            anyhow::bail!("pushgateway PUT {}: {}", path, status_line);
+        }
+        Ok(())
+    }
+
+    /// Push the final metrics, the errors are logged only since the run is finished.
+    pub async fn finish(&self, passed: bool) {
+        match self.push(passed).await {
+            Ok(()) => info!(
+                "push metrics to pushgateway {}, job {} run id {}",
+                self.cfg.addr, self.cfg.job, self.run_id
+            ),
+            Err(e) => error!("push metrics to pushgateway {}: {}", self.cfg.addr, e),
+        }
+    }
+}
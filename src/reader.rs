@@ -2,23 +2,56 @@ use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use anyhow::Result;
 use engula_client::Collection;
-use tokio::sync::Mutex;
-use tracing::{error, info};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::Mutex, task::JoinHandle, time::Instant};
+use tracing::{error, info, info_span, warn, Instrument};
 
 use crate::{
-    base::{ExecCtx, Writer},
-    gen::{Generator, NextOp},
+    base::{with_timeout, ErrorStats, ExecCtx, ReaderOptions, Unexpected, Writer},
+    chaos::FaultWindow,
+    gen::{Generator, NextOp, Snapshot},
+    histogram::{Histogram, HistogramSnapshot},
+    history::{now_us, OpEvent},
     value::Value,
 };
 
+/// The number of times the index entry and the data of a key are read before a mismatch between
+/// them fails the run.
+const INDEX_VERIFY_ATTEMPTS: usize = 10;
+const INDEX_VERIFY_INTERVAL: Duration = Duration::from_millis(50);
+
 pub struct Reader {
-    core: Mutex<CoreReader>,
+    index: usize,
+    opts: ReaderOptions,
+    error_stats: Arc<ErrorStats>,
+    /// The age of the older versions observed by the reader.
+    staleness: Arc<Histogram>,
+    /// The latency of the reads of the data collection.
+    get_latency: Arc<Histogram>,
+    /// Each traced writer is verified by its own task, so a slow writer does not stall the
+    /// verification of others.
+    trackers: Mutex<Vec<TrackerTask>>,
+}
+
+struct TrackerTask {
+    writer_index: usize,
+    verifier: Arc<Mutex<TrackerVerifier>>,
+    /// The task is spawned once the reader is running.
+    handle: Option<JoinHandle<()>>,
 }
 
-struct CoreReader {
+struct TrackerVerifier {
     index: usize,
     collection: Collection,
-    trackers: Vec<WriterTracker>,
+    index_collection: Option<Collection>,
+    opts: ReaderOptions,
+    error_stats: Arc<ErrorStats>,
+    staleness: Arc<Histogram>,
+    get_latency: Arc<Histogram>,
+    tracker: WriterTracker,
+    /// The number of negative lookups issued, it selects the next absent key.
+    negative_lookups: u64,
+    last_negative_lookup: Instant,
 }
 
 struct WriterTracker {
@@ -26,68 +59,452 @@ struct WriterTracker {
     gen: Generator,
     writer: Arc<dyn Writer>,
     expected: HashMap<Vec<u8>, TrackerExpectStatus>,
+    /// Whether the op of `accessed_step` is sent twice by the writer.
+    duplicated: bool,
+    /// When the op of `accessed_step` was first read staled.
+    stale_since: Option<Instant>,
+    /// Whether the reads are skipped until the expected keys are resolved.
+    resolving: bool,
+    /// The value last read for the op of `accessed_step`, used to report the failure.
+    last_observed: Option<String>,
+    /// The step of the last delete of a cleanup phase, and the keys deleted by that phase.
+    pending_sweep: Option<(usize, Vec<Vec<u8>>)>,
+    /// The keys observed deleted by this reader after the delete was acknowledged, they are kept
+    /// across rounds until the key is put again.
+    tombstones: HashMap<Vec<u8>, Tombstone>,
 }
 
+/// A delete acknowledged to the writer and observed by the reader, the value written before it
+/// must never be read again.
+#[derive(Debug)]
+struct Tombstone {
+    step: usize,
+    observed_at: Instant,
+}
+
+/// The verification progress of a writer by a reader, which is saved to the checkpoint so the
+/// verification could be resumed in the middle of a round. The tombstones are not saved.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TrackerSnapshot {
+    pub reader: usize,
+    pub writer: usize,
+    accessed_step: usize,
+    expected: Vec<(Vec<u8>, TrackerExpectStatus)>,
+    pending_sweep: Option<(usize, Vec<Vec<u8>>)>,
+}
+
+/// The context of an op which could not be verified before the deadline.
 #[allow(unused)]
 #[derive(Debug)]
+struct VerifyFailure {
+    reader: usize,
+    writer: usize,
+    step: usize,
+    key: String,
+    expected: String,
+    last_observed: Option<String>,
+    duplicated: bool,
+    /// The errors of all retries.
+    errors: Vec<String>,
+    /// The faults injected since the op was verified.
+    faults: Vec<FaultWindow>,
+}
+
+#[allow(unused)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 enum TrackerExpectStatus {
     Existed { value: Vec<u8>, step: usize },
     Deleted,
 }
 
 impl Reader {
-    pub fn new(index: usize, writers: Vec<Arc<dyn Writer>>, collection: Collection) -> Self {
+    pub fn new(index: usize, writers: Vec<Arc<dyn Writer>>, opts: ReaderOptions) -> Self {
+        let mut reader = Reader {
+            index,
+            opts,
+            error_stats: Arc::new(ErrorStats::default()),
+            staleness: Arc::new(Histogram::default()),
+            get_latency: Arc::new(Histogram::default()),
+            trackers: Mutex::default(),
+        };
         let trackers = writers
             .into_iter()
-            .map(|w| WriterTracker {
-                accessed_step: 0,
-                gen: Generator::new(w.seed(), w.index() as u64, w.config()),
-                expected: HashMap::new(),
-                writer: w,
-            })
+            .map(|w| reader.tracker_task(w))
             .collect();
-        Reader {
-            core: Mutex::new(CoreReader {
-                index,
-                collection,
-                trackers,
-            }),
+        *reader.trackers.get_mut() = trackers;
+        reader
+    }
+
+    /// Resume the verification from the snapshots of trackers, used when the writers are
+    /// restored from a checkpoint. The writers without a snapshot are verified from their current
+    /// steps.
+    pub fn resume(&mut self, snapshots: &[TrackerSnapshot]) {
+        for task in self.trackers.get_mut() {
+            // The tasks are not spawned yet, so the verifiers are not shared.
+            let verifier = Arc::get_mut(&mut task.verifier)
+                .expect("resume a running reader")
+                .get_mut();
+            let tracker = &mut verifier.tracker;
+            let snapshot = snapshots
+                .iter()
+                .find(|s| s.reader == self.index && s.writer == task.writer_index);
+            let step = tracker.restore(snapshot);
+            tracker.writer.verify_progress().update(self.index, step);
+            info!(
+                "reader {} resume writer {} at step {}, {} expected keys",
+                self.index,
+                tracker.writer.index(),
+                step,
+                tracker.expected.len()
+            );
+        }
+    }
+
+    /// Drop the verification tasks and respawn them from their snapshots, like the supervisor
+    /// restarts, to verify that the replayed model converges to the one of the dropped task, and
+    /// to the generator of the writer replayed to the same step.
+    async fn crash_trackers(&self, trackers: &mut [TrackerTask]) {
+        for task in trackers.iter_mut() {
+            let (snapshot, digest, writer) = {
+                // Wait until the verifying step is finished, then drop the task.
+                let verifier = task.verifier.lock().await;
+                if let Some(handle) = task.handle.take() {
+                    handle.abort();
+                }
+                let tracker = &verifier.tracker;
+                (
+                    tracker.snapshot(self.index),
+                    tracker.gen.digest(),
+                    tracker.writer.clone(),
+                )
+            };
+
+            let mut new_task = self.tracker_task(writer.clone());
+            let verifier = Arc::get_mut(&mut new_task.verifier)
+                .expect("the respawned verifier is not shared")
+                .get_mut();
+            let step = verifier.tracker.restore(Some(&snapshot));
+            // The reference is replayed from the seed of the writer, not from the tracker.
+            let reference = Generator::restore(
+                &Snapshot {
+                    num_ops: step,
+                    ..writer.snapshot()
+                },
+                writer.config(),
+            );
+            let restored = verifier.tracker.gen.digest();
+            if step != snapshot.accessed_step
+                || restored != digest
+                || restored != reference.digest()
+            {
+                panic!(
+                    "reader {} replays writer {} to step {} after crash, but the model diverges from the one at step {}",
+                    self.index, task.writer_index, step, snapshot.accessed_step,
+                );
+            }
+            info!(
+                "reader {} crashes and respawns the verification of writer {} at step {}",
+                self.index, task.writer_index, step
+            );
+            *task = new_task;
+        }
+    }
+
+    fn tracker_task(&self, writer: Arc<dyn Writer>) -> TrackerTask {
+        writer.verify_progress().update(self.index, 0);
+        // The writer is verified by reading the collections it writes.
+        let (collection, index_collection) = writer.collections();
+        let verifier = TrackerVerifier {
+            index: self.index,
+            collection,
+            index_collection,
+            opts: self.opts.clone(),
+            error_stats: self.error_stats.clone(),
+            staleness: self.staleness.clone(),
+            get_latency: self.get_latency.clone(),
+            tracker: WriterTracker::new(writer),
+            negative_lookups: 0,
+            last_negative_lookup: Instant::now(),
+        };
+        TrackerTask {
+            writer_index: verifier.tracker.writer.index(),
+            verifier: Arc::new(Mutex::new(verifier)),
+            handle: None,
         }
     }
 }
 
-impl CoreReader {
-    async fn verify(&mut self, tracker_index: usize) {
-        let tracker = &mut self.trackers[tracker_index];
+impl TrackerVerifier {
+    /// Verify the next step of the writer, return false if the tracker has caught up with the
+    /// writer and there is nothing to verify.
+    async fn verify(&mut self) -> bool {
+        let tracker = &mut self.tracker;
         let current_step = tracker.writer.current_step();
+        if let Some((step, _)) = &tracker.pending_sweep {
+            if *step >= current_step {
+                // Wait until the last delete of the cleanup phase is acknowledged.
+                return false;
+            }
+            let (step, keys) = tracker.pending_sweep.take().unwrap();
+            self.sweep_cleaned_keys(step, keys).await;
+            return true;
+        }
+
         if tracker.accessed_step == current_step {
             info!(
-                "reader {} verify one round of writer {}, accessed step {}",
+                "reader {} verify one round of writer {}, accessed step {}, the slowest reader lags {} steps behind",
                 self.index,
                 tracker.writer.index(),
-                tracker.accessed_step
+                tracker.accessed_step,
+                tracker
+                    .writer
+                    .verify_progress()
+                    .lag(current_step)
+                    .unwrap_or_default(),
             );
-            self.verify_and_reset_tracker(tracker_index);
-            return;
+            self.verify_and_reset_tracker();
+            return false;
         }
 
         debug_assert!(tracker.accessed_step < current_step);
         tracker.accessed_step += 1;
+        tracker
+            .writer
+            .verify_progress()
+            .update(self.index, tracker.accessed_step);
         let next_op = tracker.gen.next_op();
-        for _ in 0..120 {
-            match self.verify_next_op(tracker_index, &next_op).await {
-                Ok(()) => return,
+        tracker.duplicated = tracker.gen.is_duplicated();
+        tracker.stale_since = None;
+        tracker.last_observed = None;
+        if let Some(keys) = tracker.gen.take_finished_cleanup() {
+            tracker.pending_sweep = Some((tracker.accessed_step, keys));
+        }
+        let step = tracker.accessed_step;
+        if !self.is_sampled(step) || self.is_expected_full() {
+            // Only advance the model.
+            self.advance_expect_status(&next_op);
+            return true;
+        }
+        let started_us = now_us();
+        let deadline = Instant::now() + self.opts.verify_deadline;
+        let mut errors = vec![];
+        // The op could be unavailable during a fault, the deadline is extended until the fault is
+        // recovered. The staled and the lost values still fail the run.
+        let writer_index = self.tracker.writer.index();
+        while Instant::now() < deadline || self.is_disturbed() {
+            let span = info_span!(
+                "verify",
+                reader = self.index,
+                writer = writer_index,
+                step,
+                key = %String::from_utf8_lossy(next_op.key()),
+            );
+            match self.verify_next_op(&next_op).instrument(span).await {
+                Ok(()) => {
+                    self.error_stats.record_success();
+                    return true;
+                }
                 Err(e) => {
-                    tracing::error!("{}", e);
+                    let class = self.error_stats.record(&e);
+                    if !class.is_retryable() {
+                        panic!(
+                            "reader {} verify op: {}, the {:?} error is not retryable",
+                            self.index, e, class
+                        );
+                    }
+                    if self.is_disturbed() {
+                        warn!(
+                            error_class = class.as_str(),
+                            "reader {} verify op during fault: {}, total {} timeouts and {} errors",
+                            self.index,
+                            e,
+                            self.error_stats.timeouts(),
+                            self.error_stats.errors(),
+                        );
+                    } else {
+                        error!(
+                            error_class = class.as_str(),
+                            "reader {} verify op: {}, total {} timeouts and {} errors",
+                            self.index,
+                            e,
+                            self.error_stats.timeouts(),
+                            self.error_stats.errors(),
+                        );
+                    }
+                    errors.push(e.to_string());
                     tokio::time::sleep(Duration::from_secs(1)).await;
                 }
             }
         }
-        panic!("could not verify op after 120 secs");
+        let tracker = &self.tracker;
+        let (key, expected) = match &next_op {
+            NextOp::Put { key, value } => (
+                key,
+                format!(
+                    "value of step {}: {}",
+                    tracker.accessed_step,
+                    String::from_utf8_lossy(value)
+                ),
+            ),
+            NextOp::Delete { key } => (key, "deleted".to_owned()),
+        };
+        let failure = VerifyFailure {
+            reader: self.index,
+            writer: tracker.writer.index(),
+            step: tracker.accessed_step,
+            key: String::from_utf8_lossy(key).into_owned(),
+            expected,
+            last_observed: tracker.last_observed.clone(),
+            duplicated: tracker.duplicated,
+            errors,
+            faults: self
+                .opts
+                .fault_log
+                .windows()
+                .into_iter()
+                .filter(|w| !matches!(w.end_us, Some(end_us) if end_us < started_us))
+                .collect(),
+        };
+        panic!(
+            "could not verify op after {:?}: {:#?}",
+            self.opts.verify_deadline, failure
+        );
+    }
+
+    #[inline]
+    fn is_disturbed(&self) -> bool {
+        self.opts.fault_log.is_disturbed(self.opts.verify_deadline)
+    }
+
+    /// Return whether the expected map reaches its bound. The reads are skipped until the
+    /// expected keys are resolved by advancing the model, so the memory is bounded.
+    fn is_expected_full(&mut self) -> bool {
+        let max_keys = match self.opts.max_expected_keys {
+            Some(max_keys) => max_keys,
+            None => return false,
+        };
+        let tracker = &mut self.tracker;
+        let full = tracker.expected.len() >= max_keys;
+        if full != tracker.resolving {
+            tracker.resolving = full;
+            if full {
+                warn!(
+                    "reader {} skips verifying writer {} from step {}, {} expected keys are not resolved",
+                    self.index,
+                    tracker.writer.index(),
+                    tracker.accessed_step,
+                    tracker.expected.len()
+                );
+            } else {
+                info!(
+                    "reader {} resumes verifying writer {} at step {}",
+                    self.index,
+                    tracker.writer.index(),
+                    tracker.accessed_step
+                );
+            }
+        }
+        full
+    }
+
+    /// Return whether the op of `step` should be verified. The sample is decided by the seed of
+    /// the writer, so all readers and runs verify the same steps.
+    fn is_sampled(&self, step: usize) -> bool {
+        let interval = self.opts.sample_interval as u64;
+        if interval <= 1 {
+            return true;
+        }
+        let hash = (self.tracker.gen.seed() ^ step as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        (hash >> 32) % interval == 0
     }
 
-    fn advance_expect_status(&mut self, tracker: usize, next_op: &NextOp) {
-        let tracker = &mut self.trackers[tracker];
+    /// Verify that none of the keys deleted by the cleanup phase, which finished at `step`, is
+    /// still visible. A key put again after the cleanup phase is skipped.
+    async fn sweep_cleaned_keys(&mut self, step: usize, keys: Vec<Vec<u8>>) {
+        for key in &keys {
+            let mut verified = false;
+            // When the deleted key is first read visible.
+            let mut visible_since: Option<Instant> = None;
+            let deadline = Instant::now() + self.opts.verify_deadline + self.opts.delete_grace;
+            while Instant::now() < deadline {
+                match self.get(key).await {
+                    Ok(got_value) => {
+                        if let Some(got_value) = got_value {
+                            let v = Value::from(got_value.as_slice());
+                            self.check_owner(key, &v);
+                            self.check_tombstone(key, &v);
+                            if v.index() <= step {
+                                let since = *visible_since.get_or_insert_with(Instant::now);
+                                if since.elapsed() < self.opts.delete_grace {
+                                    // Check it again, the delete might be propagating.
+                                    tokio::time::sleep(Duration::from_secs(1)).await;
+                                    continue;
+                                }
+                                panic!(
+                                    "reader {} read key {} writted by writer {} step {}, but it should has been deleted by cleanup phase finished at step {}, it is still visible after {:?}",
+                                    self.index,
+                                    String::from_utf8_lossy(key),
+                                    self.tracker.writer.index(),
+                                    v.index(),
+                                    step,
+                                    since.elapsed(),
+                                );
+                            }
+                        }
+                        if let Some(since) = visible_since {
+                            info!(
+                                "reader {} observe the delete of key {} by writer {} after {:?}",
+                                self.index,
+                                String::from_utf8_lossy(key),
+                                self.tracker.writer.index(),
+                                since.elapsed(),
+                            );
+                        }
+                        self.error_stats.record_success();
+                        verified = true;
+                        break;
+                    }
+                    Err(e) => {
+                        let class = self.error_stats.record(&e);
+                        if !class.is_retryable() {
+                            panic!(
+                                "reader {} sweep cleaned key: {}, the {:?} error is not retryable",
+                                self.index, e, class
+                            );
+                        }
+                        error!(
+                            "reader {} sweep cleaned key: {}, total {} timeouts and {} errors",
+                            self.index,
+                            e,
+                            self.error_stats.timeouts(),
+                            self.error_stats.errors(),
+                        );
+                        tokio::time::sleep(Duration::from_secs(1)).await;
+                    }
+                }
+            }
+            if !verified {
+                panic!(
+                    "reader {} could not sweep key {} cleaned by writer {} at step {} after {:?}",
+                    self.index,
+                    String::from_utf8_lossy(key),
+                    self.tracker.writer.index(),
+                    step,
+                    self.opts.verify_deadline
+                );
+            }
+        }
+        info!(
+            "reader {} verify cleanup phase of writer {} finished at step {}, {} keys are deleted",
+            self.index,
+            self.tracker.writer.index(),
+            step,
+            keys.len()
+        );
+    }
+
+    fn advance_expect_status(&mut self, next_op: &NextOp) {
+        let tracker = &mut self.tracker;
         match next_op {
             NextOp::Delete { key } => {
                 if let Some(expect_status) = tracker.expected.get(key) {
@@ -97,6 +514,10 @@ impl CoreReader {
                 }
             }
             NextOp::Put { key, .. } => {
+                if matches!(tracker.tombstones.get(key), Some(t) if t.step < tracker.accessed_step)
+                {
+                    tracker.tombstones.remove(key);
+                }
                 if let Some(status) = tracker.expected.get(key) {
                     if matches!(status, TrackerExpectStatus::Existed { step, .. } if *step == tracker.accessed_step)
                     {
@@ -107,22 +528,59 @@ impl CoreReader {
         }
     }
 
-    async fn verify_next_op(&mut self, tracker: usize, next_op: &NextOp) -> Result<()> {
-        self.advance_expect_status(tracker, next_op);
+    async fn verify_next_op(&mut self, next_op: &NextOp) -> Result<()> {
+        self.advance_expect_status(next_op);
 
-        let tracker = &mut self.trackers[tracker];
+        let key = match next_op {
+            NextOp::Put { key, .. } | NextOp::Delete { key } => key,
+        };
+        let got = self.get(key).await?;
+        self.tracker.last_observed = Some(match &got {
+            Some(value) => {
+                let v = Value::from(value.as_slice());
+                format!(
+                    "value of writer {} step {}: {}",
+                    v.writer(),
+                    v.index(),
+                    String::from_utf8_lossy(&v.value())
+                )
+            }
+            None => "nothing".to_owned(),
+        });
+        if let Some(value) = &got {
+            let v = Value::from(value.as_slice());
+            self.check_owner(key, &v);
+            self.check_tombstone(key, &v);
+            if v.index() < self.tracker.accessed_step {
+                // An older version is observed, measure how long ago it was written.
+                let staleness = now_us().saturating_sub(v.written_at_us());
+                self.staleness.record(Duration::from_micros(staleness));
+            }
+        }
+        let tracker = &mut self.tracker;
         match next_op {
             NextOp::Delete { key } => {
-                if let Some(value) = self.collection.get(key.clone()).await? {
+                if let Some(value) = got {
                     let v = Value::from(value.as_slice());
                     let value = v.value();
-                    if v.index() + 1 < tracker.accessed_step {
+                    if self.opts.is_stale(v.index(), tracker.accessed_step) {
+                        if self.opts.tolerate_stale(&mut tracker.stale_since) {
+                            return Err(Unexpected(format!(
+                                "reader {} read a staled key {} writted by writer {} step {}, retry it",
+                                self.index,
+                                String::from_utf8_lossy(key.as_slice()),
+                                tracker.writer.index(),
+                                v.index(),
+                            ))
+                            .into());
+                        }
                         panic!(
-                            "reader {} read a staled key {} writted by writer {}, values is {}",
+                            "reader {} read a staled key {} writted by writer {}, values is {}, duplicated: {}",
                             self.index,
                             String::from_utf8_lossy(value.as_slice()),
                             tracker.writer.index(),
                             String::from_utf8_lossy(value.as_slice()),
+                            tracker.duplicated,
                         );
                     }
 
@@ -134,28 +592,49 @@ impl CoreReader {
                             step: v.index(),
                         },
                     );
+                } else if tracker.accessed_step < tracker.writer.current_step() {
+                    // The writer has moved to the next step, so the delete is acknowledged.
+                    tracker.tombstones.insert(
+                        key.clone(),
+                        Tombstone {
+                            step: tracker.accessed_step,
+                            observed_at: Instant::now(),
+                        },
+                    );
                 }
             }
             NextOp::Put { key, value } => {
-                match self.collection.get(key.clone()).await? {
+                match got {
                     Some(got_value) => {
                         let v = Value::from(got_value.as_slice());
                         let got_value = v.value();
-                        if v.index() + 1 < tracker.accessed_step {
+                        if self.opts.is_stale(v.index(), tracker.accessed_step) {
+                            if self.opts.tolerate_stale(&mut tracker.stale_since) {
+                                return Err(Unexpected(format!(
+                                    "reader {} read a staled key {} writted by writer {} step {}, retry it",
+                                    self.index,
+                                    String::from_utf8_lossy(key.as_slice()),
+                                    tracker.writer.index(),
+                                    v.index(),
+                                ))
+                                .into());
+                            }
                             panic!(
-                                "reader {} read a staled key {} writted by writer {} step {}, values is {}",
+                                "reader {} read a staled key {} writted by writer {} step {}, values is {}, duplicated: {}",
                                 self.index,
                                 String::from_utf8_lossy(key.as_slice()),
                                 tracker.writer.index(),
                                 v.index(),
                                 String::from_utf8_lossy(value.as_slice()),
+                                tracker.duplicated,
                             );
                         } else if v.index() == tracker.accessed_step {
                             if got_value != *value {
-                                panic!("reader {} read a key {} writted by writer {} with different value",
+                                panic!("reader {} read a key {} writted by writer {} with different value, duplicated: {}",
                                     self.index,
                                     String::from_utf8_lossy(value.as_slice()),
                                     tracker.writer.index(),
+                                    tracker.duplicated,
                                 );
                             }
                         } else {
@@ -177,11 +656,200 @@ impl CoreReader {
                 };
             }
         }
+
+        if self.index_collection.is_some() {
+            self.verify_index_entry(key).await?;
+        }
         Ok(())
     }
 
-    fn verify_and_reset_tracker(&mut self, tracker_index: usize) {
-        let tracker = &mut self.trackers[tracker_index];
+    /// Verify that the index entry of the key never points to a step newer than the data, since
+    /// the writer always puts the data before the index entry and deletes it after the entry.
+    /// The entry and the data are read by two ops, so a mismatch is read again, in case the
+    /// writer changed the key between them.
+    async fn verify_index_entry(&self, key: &[u8]) -> Result<()> {
+        let mut mismatch = None;
+        for attempt in 0..INDEX_VERIFY_ATTEMPTS {
+            if attempt > 0 {
+                tokio::time::sleep(INDEX_VERIFY_INTERVAL).await;
+            }
+            mismatch = self.read_index_mismatch(key).await?;
+            if mismatch.is_none() {
+                return Ok(());
+            }
+        }
+        panic!(
+            "reader {} {} after {} attempts",
+            self.index,
+            mismatch.unwrap_or_default(),
+            INDEX_VERIFY_ATTEMPTS
+        );
+    }
+
+    /// Read the index entry of the key and the data, and describe the mismatch between them.
+    async fn read_index_mismatch(&self, key: &[u8]) -> Result<Option<String>> {
+        let index_collection = match &self.index_collection {
+            Some(index_collection) => index_collection,
+            None => return Ok(None),
+        };
+        let tracker = &self.tracker;
+        let entry = match with_timeout(
+            self.opts.effective_op_timeout(),
+            index_collection.get(key.to_owned()),
+        )
+        .await?
+        {
+            Some(entry) => Value::from(entry.as_slice()),
+            None => return Ok(None),
+        };
+        // The entry of a key never points to another key, no matter when it is read.
+        if entry.value() != key {
+            panic!(
+                "reader {} read index entry of key {} writted by writer {}, but it points to key {}",
+                self.index,
+                String::from_utf8_lossy(key),
+                tracker.writer.index(),
+                String::from_utf8_lossy(entry.value().as_slice()),
+            );
+        }
+
+        let mismatch = match self.get(key).await? {
+            Some(data) => {
+                let v = Value::from(data.as_slice());
+                if v.index() >= entry.index() {
+                    return Ok(None);
+                }
+                format!(
+                    "read index entry of key {} at step {} writted by writer {}, but the data is at step {}",
+                    String::from_utf8_lossy(key),
+                    entry.index(),
+                    tracker.writer.index(),
+                    v.index(),
+                )
+            }
+            None => format!(
+                "read index entry of key {} at step {} writted by writer {}, but the data is not found",
+                String::from_utf8_lossy(key),
+                entry.index(),
+                tracker.writer.index(),
+            ),
+        };
+        Ok(Some(mismatch))
+    }
+
+    /// Verify that a key reserved to be never written by the writer does not exist, which catches
+    /// the phantom keys from stale shard data, bad compactions or collisions of key encoding.
+    async fn verify_absent_key(&mut self) {
+        match self.opts.negative_lookup_interval {
+            Some(interval) if self.last_negative_lookup.elapsed() >= interval => {}
+            _ => return,
+        }
+        let writer = self.tracker.writer.index();
+        let key = Generator::absent_key(writer as u64, self.negative_lookups);
+        match self.get(&key).await {
+            Ok(None) => {
+                self.error_stats.record_success();
+                self.negative_lookups += 1;
+            }
+            Ok(Some(data)) => {
+                let v = Value::from(data.as_slice());
+                panic!(
+                    "reader {} read key {} reserved to be never written by writer {}, but got value of writer {} step {}",
+                    self.index,
+                    String::from_utf8_lossy(&key),
+                    writer,
+                    v.writer(),
+                    v.index(),
+                );
+            }
+            Err(e) => {
+                let class = self.error_stats.record(&e);
+                if !class.is_retryable() {
+                    panic!(
+                        "reader {} negative lookup: {}, the {:?} error is not retryable",
+                        self.index, e, class
+                    );
+                }
+                warn!("reader {} negative lookup: {}, retry later", self.index, e);
+            }
+        }
+        self.last_negative_lookup = Instant::now();
+    }
+
+    /// Verify that the key and the value read are owned by the traced writer, otherwise the data
+    /// of another writer is routed to this key, or the keys of writers collide.
+    fn check_owner(&self, key: &[u8], v: &Value) {
+        let writer = self.tracker.writer.index();
+        let key_writer = Generator::writer_from_key(key) as usize;
+        if key_writer != writer || v.writer() != writer {
+            panic!(
+                "reader {} read key {} of writer {}, but the key belongs to writer {} and the value is written by writer {} step {}",
+                self.index,
+                String::from_utf8_lossy(key),
+                writer,
+                key_writer,
+                v.writer(),
+                v.index(),
+            );
+        }
+    }
+
+    /// Verify that the value read is not written before a delete observed by this reader, which
+    /// means the deleted value is resurrected.
+    fn check_tombstone(&self, key: &[u8], v: &Value) {
+        let tracker = &self.tracker;
+        if let Some(tombstone) = tracker.tombstones.get(key) {
+            if v.index() < tombstone.step {
+                panic!(
+                    "reader {} read key {} writted by writer {} step {}, but it is resurrected after the delete at step {}, which is observed {:?} ago",
+                    self.index,
+                    String::from_utf8_lossy(key),
+                    tracker.writer.index(),
+                    v.index(),
+                    tombstone.step,
+                    tombstone.observed_at.elapsed(),
+                );
+            }
+        }
+    }
+
+    /// Read the key of the data collection, the read is published to the history.
+    async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let invoked_at_us = now_us();
+        let start = Instant::now();
+        let result = with_timeout(
+            self.opts.effective_op_timeout(),
+            self.collection.get(key.to_owned()),
+        )
+        .await;
+        if result.is_ok() && Instant::now() >= self.opts.warmup_deadline {
+            self.get_latency.record(start.elapsed());
+        }
+        if matches!(self.opts.slow_op_threshold, Some(threshold) if start.elapsed() > threshold) {
+            warn!(
+                "reader {} slow get of key {} of writer {} at step {}, elapsed {:?}, ok: {}",
+                self.index,
+                String::from_utf8_lossy(key),
+                self.tracker.writer.index(),
+                self.tracker.accessed_step,
+                start.elapsed(),
+                result.is_ok(),
+            );
+        }
+        let tracker = &self.tracker;
+        self.opts.history.publish(OpEvent::read(
+            self.index,
+            tracker.writer.index(),
+            tracker.accessed_step,
+            key,
+            &result,
+            invoked_at_us,
+        ));
+        result
+    }
+
+    fn verify_and_reset_tracker(&mut self) {
+        let tracker = &mut self.tracker;
 
         for (key, expect_status) in &tracker.expected {
             match expect_status {
@@ -219,28 +887,182 @@ impl CoreReader {
 }
 
 impl WriterTracker {
+    fn new(writer: Arc<dyn Writer>) -> Self {
+        WriterTracker {
+            accessed_step: 0,
+            gen: Generator::new(writer.seed(), writer.index() as u64, writer.config()),
+            expected: HashMap::new(),
+            duplicated: false,
+            stale_since: None,
+            resolving: false,
+            last_observed: None,
+            pending_sweep: None,
+            tombstones: HashMap::new(),
+            writer,
+        }
+    }
+
+    fn snapshot(&self, reader: usize) -> TrackerSnapshot {
+        TrackerSnapshot {
+            reader,
+            writer: self.writer.index(),
+            accessed_step: self.accessed_step,
+            expected: self
+                .expected
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            pending_sweep: self.pending_sweep.clone(),
+        }
+    }
+
+    /// Restore a new tracker from the snapshot and return the restored step. The tracker starts
+    /// from the current step of the writer if there is no available snapshot.
+    fn restore(&mut self, snapshot: Option<&TrackerSnapshot>) -> usize {
+        let current_step = self.writer.current_step();
+        let step = match snapshot {
+            // The writer is restored one step back, so the op might be verified again.
+            Some(snapshot) if snapshot.accessed_step <= current_step + 1 => {
+                self.expected = snapshot
+                    .expected
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+                self.pending_sweep = snapshot.pending_sweep.clone();
+                snapshot.accessed_step.min(current_step)
+            }
+            _ => current_step,
+        };
+        self.gen.skip(step);
+        self.accessed_step = step;
+        step
+    }
+
     fn reset(&mut self) {
         self.accessed_step = 0;
         self.gen.reset();
         self.expected = HashMap::new();
+        self.resolving = false;
+        self.pending_sweep = None;
     }
 }
 
 #[super::async_trait]
 impl super::base::Task for Reader {
     async fn run(&self, mut ctx: ExecCtx) {
-        let mut core = self.core.lock().await;
+        let mut last_crashed = Instant::now();
         while ctx
             .wait_until_timeout_or_shutdown(Duration::from_millis(10))
             .await
             .is_some()
         {
-            for tracker in 0..core.trackers.len() {
-                core.verify(tracker).await;
+            let mut trackers = self.trackers.lock().await;
+            if let Some(interval) = self.opts.crash_interval {
+                if last_crashed.elapsed() >= interval {
+                    self.crash_trackers(&mut trackers).await;
+                    last_crashed = Instant::now();
+                }
+            }
+
+            // Spawn the tasks of the newly traced (or respawned) writers.
+            for task in trackers.iter_mut().filter(|t| t.handle.is_none()) {
+                let verifier = task.verifier.clone();
+                let cloned_ctx = ctx.clone();
+                task.handle = Some(tokio::spawn(async move {
+                    verify_tracker(verifier, cloned_ctx).await;
+                }));
+            }
+        }
+
+        let trackers = std::mem::take(&mut *self.trackers.lock().await);
+        for task in trackers {
+            if let Some(handle) = task.handle {
+                handle.await.unwrap_or_default();
             }
         }
     }
 }
 
+async fn verify_tracker(verifier: Arc<Mutex<TrackerVerifier>>, mut ctx: ExecCtx) {
+    let tunables = verifier.lock().await.opts.tunables.clone();
+    let mut interval = tunables.get().verify_interval;
+    while ctx.wait_until_timeout_or_shutdown(interval).await.is_some() {
+        // Release the lock between wakeups, so the tracker could be removed.
+        let mut verifier = verifier.lock().await;
+        // The options might be changed by reloading the config.
+        let opts = tunables.get();
+        verifier.verify_absent_key().await;
+        let mut idle = false;
+        for _ in 0..opts.ops_per_wakeup.max(1) {
+            if !verifier.verify().await {
+                idle = true;
+                break;
+            }
+        }
+        interval = if idle {
+            opts.idle_interval.max(opts.verify_interval)
+        } else {
+            opts.verify_interval
+        };
+    }
+}
+
 #[super::async_trait]
-impl super::base::Reader for Reader {}
+impl super::base::Reader for Reader {
+    fn index(&self) -> usize {
+        self.index
+    }
+
+    fn error_stats(&self) -> &ErrorStats {
+        &self.error_stats
+    }
+
+    async fn snapshot(&self) -> Vec<TrackerSnapshot> {
+        let trackers = self.trackers.lock().await;
+        let mut snapshots = vec![];
+        for task in trackers.iter() {
+            let verifier = task.verifier.lock().await;
+            snapshots.push(verifier.tracker.snapshot(self.index));
+        }
+        snapshots
+    }
+
+    fn staleness(&self) -> HistogramSnapshot {
+        self.staleness.snapshot()
+    }
+
+    fn get_latency(&self) -> HistogramSnapshot {
+        self.get_latency.snapshot()
+    }
+
+    async fn add_writer(&self, writer: Arc<dyn Writer>) {
+        info!(
+            "reader {} start tracing writer {}",
+            self.index,
+            writer.index()
+        );
+        let task = self.tracker_task(writer);
+        self.trackers.lock().await.push(task);
+    }
+
+    async fn remove_writer(&self, writer_index: usize) -> Option<Arc<dyn Writer>> {
+        let mut trackers = self.trackers.lock().await;
+        let pos = trackers
+            .iter()
+            .position(|t| t.writer_index == writer_index)?;
+        let task = trackers.remove(pos);
+        // Wait until the verifying step is finished, then stop the task.
+        let verifier = task.verifier.lock().await;
+        if let Some(handle) = task.handle {
+            handle.abort();
+        }
+        verifier.tracker.writer.verify_progress().remove(self.index);
+        info!("reader {} stop tracing writer {}", self.index, writer_index);
+        Some(verifier.tracker.writer.clone())
+    }
+
+    async fn traced_writers(&self) -> Vec<usize> {
+        let trackers = self.trackers.lock().await;
+        trackers.iter().map(|t| t.writer_index).collect()
+    }
+}
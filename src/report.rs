@@ -0,0 +1,220 @@
+use std::{fmt::Write, path::Path};
+
+use anyhow::Result;
+
+use crate::summary::{RunReport, VerifyOutcome};
+
+enum CaseResult {
+    Passed,
+    Failed(String),
+    Skipped(&'static str),
+}
+
+struct TestCase {
+    name: String,
+    time_secs: f64,
+    result: CaseResult,
+}
+
+/// The failure of the run is attributed to the task which its message starts with, eg the panic
+/// `reader 1 read a staled key ...`.
+fn blames(failure: &Option<String>, task: &str) -> bool {
+    match failure {
+        Some(msg) => msg
+            .trim_start_matches("panicked at '")
+            .starts_with(&format!("{} ", task)),
+        None => false,
+    }
+}
+
+fn test_cases(report: &RunReport) -> Vec<TestCase> {
+    let time_secs = report.finished_at_us.saturating_sub(report.started_at_us) as f64 / 1e6;
+    let mut cases = vec![];
+    let task_case = |task: String| {
+        let result = if blames(&report.failure, &task) {
+            CaseResult::Failed(report.failure.clone().unwrap_or_default())
+        } else {
+            CaseResult::Passed
+        };
+        TestCase {
+            name: format!("verification of {}", task),
+            time_secs,
+            result,
+        }
+    };
+    for w in &report.writers {
+        cases.push(task_case(format!("writer {}", w.index)));
+    }
+    for r in &report.readers {
+        cases.push(task_case(format!("reader {}", r.index)));
+    }
+    let result = match report.final_verify {
+        VerifyOutcome::Passed => CaseResult::Passed,
+        VerifyOutcome::Failed => CaseResult::Failed(report.failure.clone().unwrap_or_default()),
+        VerifyOutcome::Skipped => CaseResult::Skipped("the final verification is disabled"),
+        VerifyOutcome::NotRun => CaseResult::Skipped("the run is failed before it"),
+    };
+    cases.push(TestCase {
+        name: "final verification".to_owned(),
+        time_secs: 0.0,
+        result,
+    });
+    let result = match &report.failure {
+        Some(failure) => CaseResult::Failed(failure.clone()),
+        None => CaseResult::Passed,
+    };
+    cases.push(TestCase {
+        name: "run".to_owned(),
+        time_secs,
+        result,
+    });
+    cases
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Render the report as a JUnit XML, each checker of the run is a test case, so the test
+/// summaries of CI show which one fails.
+pub fn render_junit(report: &RunReport) -> String {
+    let cases = test_cases(report);
+    let failures = cases
+        .iter()
+        .filter(|c| matches!(c.result, CaseResult::Failed(_)))
+        .count();
+    let skipped = cases
+        .iter()
+        .filter(|c| matches!(c.result, CaseResult::Skipped(_)))
+        .count();
+    let mut out = String::new();
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(
+        out,
+        r#"<testsuite name="engula-supervisor" tests="{}" failures="{}" skipped="{}" time="{:.3}">"#,
+        cases.len(),
+        failures,
+        skipped,
+        report.finished_at_us.saturating_sub(report.started_at_us) as f64 / 1e6,
+    )
+    .unwrap();
+    writeln!(out, "  <properties>").unwrap();
+    writeln!(
+        out,
+        r#"    <property name="base_seed" value="{}"/>"#,
+        report.base_seed
+    )
+    .unwrap();
+    writeln!(
+        out,
+        r#"    <property name="config_hash" value="{}"/>"#,
+        escape(&report.config_hash)
+    )
+    .unwrap();
+    writeln!(out, "  </properties>").unwrap();
+    for case in &cases {
+        write!(
+            out,
+            r#"  <testcase classname="engula-supervisor" name="{}" time="{:.3}""#,
+            escape(&case.name),
+            case.time_secs
+        )
+        .unwrap();
+        match &case.result {
+            CaseResult::Passed => writeln!(out, "/>").unwrap(),
+            CaseResult::Failed(msg) => {
+                writeln!(out, ">").unwrap();
+                let first_line = msg.lines().next().unwrap_or_default();
+                writeln!(
+                    out,
+                    r#"    <failure message="{}">{}</failure>"#,
+                    escape(first_line),
+                    escape(msg)
+                )
+                .unwrap();
+                writeln!(out, "  </testcase>").unwrap();
+            }
+            CaseResult::Skipped(reason) => {
+                writeln!(out, ">").unwrap();
+                writeln!(out, r#"    <skipped message="{}"/>"#, escape(reason)).unwrap();
+                writeln!(out, "  </testcase>").unwrap();
+            }
+        }
+    }
+    writeln!(out, "</testsuite>").unwrap();
+    out
+}
+
+pub fn write_junit(report: &RunReport, path: &Path) -> Result<()> {
+    std::fs::write(path, render_junit(report))?;
+    Ok(())
+}
+
+/// Render the report as a text summary for humans, eg to inspect the report of a run in CI.
+pub fn render_summary(report: &RunReport) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "run {}, base seed {}, config hash {}, lasted {:.3}s",
+        if report.passed { "passed" } else { "failed" },
+        report.base_seed,
+        report.config_hash,
+        report.finished_at_us.saturating_sub(report.started_at_us) as f64 / 1e6,
+    )
+    .unwrap();
+    writeln!(out, "final verification: {:?}", report.final_verify).unwrap();
+    if let Some(failure) = &report.failure {
+        writeln!(out, "failure: {}", failure).unwrap();
+    }
+    for w in &report.writers {
+        write!(
+            out,
+            "writer {}, seed {}, step {}, {} ops, errors {:?}",
+            w.index, w.seed, w.step, w.ops, w.errors
+        )
+        .unwrap();
+        for (op, latency) in &w.latencies {
+            write!(
+                out,
+                ", {} p50 {}us p99 {}us",
+                op, latency.p50_us, latency.p99_us
+            )
+            .unwrap();
+        }
+        writeln!(out).unwrap();
+    }
+    for r in &report.readers {
+        writeln!(
+            out,
+            "reader {}, {} reads, errors {:?}, get p50 {}us p99 {}us, staleness p50 {}us p99 {}us",
+            r.index,
+            r.reads,
+            r.errors,
+            r.get_latency.p50_us,
+            r.get_latency.p99_us,
+            r.staleness.p50_us,
+            r.staleness.p99_us,
+        )
+        .unwrap();
+    }
+    for f in &report.faults {
+        match f.end_us {
+            Some(end_us) => writeln!(
+                out,
+                "fault {} on {:?}, from {} to {}",
+                f.fault, f.targets, f.start_us, end_us
+            ),
+            None => writeln!(
+                out,
+                "fault {} on {:?}, from {}, not recovered",
+                f.fault, f.targets, f.start_us
+            ),
+        }
+        .unwrap();
+    }
+    out
+}
@@ -0,0 +1,181 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use engula_client::Collection;
+use tokio::sync::{broadcast, Mutex};
+use tracing::{error, info, warn};
+
+use crate::{
+    base::{with_timeout, ExecCtx, Failure, FailureKind},
+    history::{History, OpEvent, OpKind, OpOutcome},
+    value::Value,
+};
+
+/// The value of a key, the step and the content of the last put, `None` if it is deleted.
+type Version = Option<(usize, Vec<u8>)>;
+
+#[derive(Clone)]
+struct Entry {
+    writer: usize,
+    /// The version of the last acknowledged op.
+    acked: Version,
+    /// The version of the failed attempts after the last acknowledged op, they might take effect
+    /// or not.
+    pending: Vec<Version>,
+}
+
+/// A local copy of the collection, built by applying the ops of writers from the history. It is
+/// independent of the replayed models of readers, and is compared with the cluster at quiescent
+/// points.
+pub struct ShadowStore {
+    receiver: Mutex<broadcast::Receiver<Arc<OpEvent>>>,
+    entries: std::sync::Mutex<BTreeMap<Vec<u8>, Entry>>,
+    /// The number of events lost since the store lags behind the history, the store is
+    /// incomplete and could not be diffed once any is lost.
+    lost_events: AtomicUsize,
+}
+
+impl ShadowStore {
+    pub fn new(history: &History) -> Self {
+        ShadowStore {
+            receiver: Mutex::new(history.subscribe()),
+            entries: std::sync::Mutex::default(),
+            lost_events: AtomicUsize::new(0),
+        }
+    }
+
+    fn lose(&self, num: u64) {
+        warn!("shadow store lost {} events, the diff will be skipped", num);
+        self.lost_events.fetch_add(num as usize, Ordering::AcqRel);
+    }
+
+    fn apply(&self, event: &OpEvent) {
+        let version = match event.kind {
+            OpKind::Put => Some((event.step, event.value.clone().unwrap_or_default())),
+            OpKind::Delete => None,
+            OpKind::Get => return,
+        };
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.entry(event.key.clone()).or_insert_with(|| Entry {
+            writer: event.writer,
+            acked: None,
+            pending: vec![],
+        });
+        match event.outcome {
+            OpOutcome::Ok => {
+                entry.acked = version;
+                entry.pending.clear();
+            }
+            _ => entry.pending.push(version),
+        }
+    }
+
+    /// Compare every key of the shadow store with the cluster. It should be called after the
+    /// writers are stopped and the store is stopped, so all ops are applied.
+    pub async fn diff(&self, collection: &Collection, op_timeout: Duration) -> Result<()> {
+        let lost_events = self.lost_events.load(Ordering::Acquire);
+        if lost_events > 0 {
+            anyhow::bail!(Failure::new(
+                FailureKind::Internal,
+                format!(
+                    "shadow store lost {} events, the diff is skipped",
+                    lost_events
+                )
+            ));
+        }
+        let entries = self.entries.lock().unwrap().clone();
+        let mut mismatches = 0;
+        for (key, entry) in &entries {
+            let got = get(collection, key, op_timeout).await?;
+            let got_version = got.as_ref().map(|v| (v.index(), v.value()));
+            let owned = got
+                .as_ref()
+                .map(|v| v.writer() == entry.writer)
+                .unwrap_or(true);
+            if owned && (got_version == entry.acked || entry.pending.contains(&got_version)) {
+                continue;
+            }
+            mismatches += 1;
+            error!(
+                "shadow store key {} of writer {} expect step {:?}, or one of {} failed attempts, but got value of writer {:?} step {:?}",
+                String::from_utf8_lossy(key),
+                entry.writer,
+                entry.acked.as_ref().map(|(step, _)| *step),
+                entry.pending.len(),
+                got.as_ref().map(|v| v.writer()),
+                got.as_ref().map(|v| v.index()),
+            );
+        }
+        if mismatches > 0 {
+            anyhow::bail!(Failure::new(
+                FailureKind::Verification,
+                format!("shadow store found {} mismatched keys", mismatches)
+            ));
+        }
+        info!("shadow store verify {} keys success", entries.len());
+        Ok(())
+    }
+}
+
+async fn get(collection: &Collection, key: &[u8], op_timeout: Duration) -> Result<Option<Value>> {
+    let mut last_err = None;
+    for _ in 0..120 {
+        match with_timeout(op_timeout, collection.get(key.to_owned())).await {
+            Ok(value) => {
+                return value.map(|v| Value::decode(&v)).transpose().map_err(|e| {
+                    Failure::new(
+                        FailureKind::Verification,
+                        format!("shadow store key {}: {}", String::from_utf8_lossy(key), e),
+                    )
+                    .into()
+                })
+            }
+            Err(e) => {
+                error!(
+                    "shadow store get key {}: {}",
+                    String::from_utf8_lossy(key),
+                    e
+                );
+                last_err = Some(e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        }
+    }
+    Err(last_err.unwrap())
+}
+
+#[super::async_trait]
+impl super::base::Task for ShadowStore {
+    async fn run(&self, mut ctx: ExecCtx) {
+        let mut receiver = self.receiver.lock().await;
+        loop {
+            tokio::select! {
+                event = receiver.recv() => match event {
+                    Ok(event) => self.apply(&event),
+                    Err(broadcast::error::RecvError::Lagged(num)) => self.lose(num),
+                    Err(broadcast::error::RecvError::Closed) => return,
+                },
+                result = ctx.wait_until_timeout_or_shutdown(Duration::from_secs(1)) => {
+                    if result.is_none() {
+                        break;
+                    }
+                }
+            }
+        }
+        // Apply the events published before shutdown.
+        loop {
+            match receiver.try_recv() {
+                Ok(event) => self.apply(&event),
+                Err(broadcast::error::TryRecvError::Lagged(num)) => self.lose(num),
+                Err(_) => break,
+            }
+        }
+    }
+}
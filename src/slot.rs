@@ -0,0 +1,5 @@
+/// Return the hash slot of the key, in the same way as the hash partition of engula, which maps
+/// a key to `crc32(key) % slots`.
+pub fn hash_slot(key: &[u8], slots: u32) -> u32 {
+    crc32fast::hash(key) % slots
+}
@@ -0,0 +1,137 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::{net::UdpSocket, sync::Mutex};
+use tracing::{info, warn};
+
+use crate::{
+    base::{deserialize_secs, ExecCtx, Reader, Writers},
+    metrics::{self, Metric, MetricKind},
+};
+
+/// The max size of a datagram, so it is not fragmented on the common networks.
+const MAX_DATAGRAM_SIZE: usize = 1432;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StatsdConfig {
+    /// The address of the StatsD agent, eg `127.0.0.1:8125`.
+    pub addr: String,
+    /// The prefix of the names of metrics, eg `engula.supervisor`.
+    pub prefix: String,
+    #[serde(deserialize_with = "deserialize_secs")]
+    pub interval_secs: u64,
+    /// Send the labels as the tags of DogStatsD, otherwise their values are appended to the
+    /// names of metrics, eg `<prefix>.supervisor_ops_total.writer.0`.
+    pub dogstatsd: bool,
+}
+
+/// Push the metrics of the `/metrics` endpoint of the admin server to a StatsD agent
+/// periodically. The gauges are sent as they are, and the counters are sent as the increments
+/// since the last push.
+pub struct StatsdExporter {
+    cfg: StatsdConfig,
+    socket: UdpSocket,
+    writers: Writers,
+    readers: Vec<Arc<dyn Reader>>,
+    /// The values of the counters at the last push, by the name of the line.
+    last_counters: Mutex<HashMap<String, f64>>,
+}
+
+impl StatsdExporter {
+    pub async fn connect(
+        cfg: StatsdConfig,
+        writers: Writers,
+        readers: Vec<Arc<dyn Reader>>,
+    ) -> Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.connect(&cfg.addr).await?;
+        info!("push metrics to statsd {}", cfg.addr);
+        Ok(StatsdExporter {
+            cfg,
+            socket,
+            writers,
+            readers,
+            last_counters: Mutex::default(),
+        })
+    }
+
+    /// Return the name of the metric, and the tags of DogStatsD.
+    fn name_of(&self, metric: &Metric) -> (String, String) {
+        if self.cfg.dogstatsd {
+            let tags = metric
+                .labels
+                .iter()
+                .map(|(k, v)| format!("{}:{}", k, v))
+                .collect::<Vec<_>>()
+                .join(",");
+            (format!("{}.{}", self.cfg.prefix, metric.name), tags)
+        } else {
+            let mut name = format!("{}.{}", self.cfg.prefix, metric.name);
+            for (_, v) in &metric.labels {
+                name.push('.');
+                name.push_str(&v.replace('.', "_"));
+            }
+            (name, String::new())
+        }
+    }
+
+    async fn push(&self) -> Result<()> {
+        let writers = self.writers.lock().unwrap().clone();
+        let metrics = metrics::collect(&writers, &self.readers);
+        let mut last_counters = self.last_counters.lock().await;
+        let mut lines = vec![];
+        for metric in &metrics {
+            let (name, tags) = self.name_of(metric);
+            let line = match metric.kind {
+                MetricKind::Gauge => format!("{}:{}|g", name, metric.value),
+                MetricKind::Counter => {
+                    let key = format!("{}|{}", name, tags);
+                    let last = last_counters.insert(key, metric.value).unwrap_or_default();
+                    let delta = metric.value - last;
+                    if delta <= 0.0 {
+                        continue;
+                    }
+                    format!("{}:{}|c", name, delta)
+                }
+            };
+            if tags.is_empty() {
+                lines.push(line);
+            } else {
+                lines.push(format!("{}|#{}", line, tags));
+            }
+        }
+        let mut datagram = String::new();
+        for line in lines {
+            if !datagram.is_empty() && datagram.len() + line.len() + 1 > MAX_DATAGRAM_SIZE {
+                self.socket.send(datagram.as_bytes()).await?;
+                datagram.clear();
+            }
+            if !datagram.is_empty() {
+                datagram.push('\n');
+            }
+            datagram.push_str(&line);
+        }
+        if !datagram.is_empty() {
+            self.socket.send(datagram.as_bytes()).await?;
+        }
+        Ok(())
+    }
+}
+
+#[super::async_trait]
+impl super::base::Task for StatsdExporter {
+    async fn run(&self, mut ctx: ExecCtx) {
+        let interval = Duration::from_secs(self.cfg.interval_secs);
+        loop {
+            let stopped = ctx.wait_until_timeout_or_shutdown(interval).await.is_none();
+            // The last push is made at shutdown, so the final values are not lost.
+            if let Err(e) = self.push().await {
+                warn!("push metrics to statsd {}: {}", self.cfg.addr, e);
+            }
+            if stopped {
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{
+    base::{ErrorStats, Reader, Writers},
+    chaos::{FaultLog, FaultWindow},
+    histogram::HistogramSnapshot,
+    history::now_us,
+    report,
+};
+
+/// The reporter of the run, it is registered so the report is written at panic too.
+static REPORTER: Mutex<Option<Arc<RunReporter>>> = Mutex::new(None);
+
+/// The machine-readable report of a run, it is written once the run is finished, either
+/// cleanly or by a failure.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RunReport {
+    pub base_seed: u64,
+    /// The crc32 of the loaded config.
+    pub config_hash: String,
+    pub started_at_us: u64,
+    pub finished_at_us: u64,
+    pub passed: bool,
+    pub final_verify: VerifyOutcome,
+    /// The message of the failure, eg the panic of a verification or the exceeded error budget.
+    pub failure: Option<String>,
+    pub writers: Vec<WriterReport>,
+    pub readers: Vec<ReaderReport>,
+    pub faults: Vec<FaultWindow>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum VerifyOutcome {
+    Passed,
+    Failed,
+    /// The final verification is disabled.
+    Skipped,
+    /// The run is failed before the final verification.
+    NotRun,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WriterReport {
+    pub index: usize,
+    pub seed: u64,
+    pub step: usize,
+    pub ops: usize,
+    /// The number of failures by the class of error.
+    pub errors: BTreeMap<String, usize>,
+    /// The latencies by the type of op.
+    pub latencies: BTreeMap<String, LatencyReport>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReaderReport {
+    pub index: usize,
+    /// The number of verified reads.
+    pub reads: usize,
+    pub errors: BTreeMap<String, usize>,
+    pub get_latency: LatencyReport,
+    pub staleness: LatencyReport,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LatencyReport {
+    pub count: u64,
+    pub mean_us: u64,
+    pub p50_us: u64,
+    pub p90_us: u64,
+    pub p99_us: u64,
+    pub p999_us: u64,
+    pub max_us: u64,
+}
+
+impl From<&HistogramSnapshot> for LatencyReport {
+    fn from(h: &HistogramSnapshot) -> Self {
+        let us = |q| h.percentile(q).as_micros() as u64;
+        LatencyReport {
+            count: h.count,
+            mean_us: h.mean().as_micros() as u64,
+            p50_us: us(0.5),
+            p90_us: us(0.9),
+            p99_us: us(0.99),
+            p999_us: us(0.999),
+            max_us: h.max_us,
+        }
+    }
+}
+
+fn errors_of(stats: &ErrorStats) -> BTreeMap<String, usize> {
+    stats
+        .breakdown()
+        .into_iter()
+        .map(|(class, num)| (class.as_str().to_owned(), num))
+        .collect()
+}
+
+pub struct RunReporter {
+    path: Option<PathBuf>,
+    /// The file to write the report as a JUnit XML, see [`report::render_junit`].
+    junit_path: Option<PathBuf>,
+    base_seed: u64,
+    config_hash: String,
+    started_at_us: u64,
+    writers: Writers,
+    readers: Vec<Arc<dyn Reader>>,
+    fault_log: Arc<FaultLog>,
+    /// Whether the final verification is started, a panic after it fails the verification.
+    final_verify_started: AtomicBool,
+}
+
+impl RunReporter {
+    pub fn new(
+        path: Option<PathBuf>,
+        junit_path: Option<PathBuf>,
+        base_seed: u64,
+        config_hash: String,
+        writers: Writers,
+        readers: Vec<Arc<dyn Reader>>,
+        fault_log: Arc<FaultLog>,
+    ) -> Arc<Self> {
+        let reporter = Arc::new(RunReporter {
+            path,
+            junit_path,
+            base_seed,
+            config_hash,
+            started_at_us: now_us(),
+            writers,
+            readers,
+            fault_log,
+            final_verify_started: AtomicBool::new(false),
+        });
+        *REPORTER.lock().unwrap() = Some(reporter.clone());
+        reporter
+    }
+
+    pub fn start_final_verify(&self) {
+        self.final_verify_started.store(true, Ordering::Release);
+    }
+
+    pub fn build(&self, final_verify: VerifyOutcome, failure: Option<String>) -> RunReport {
+        // The writers might be locked by the panicked thread.
+        let writers = match self.writers.try_lock() {
+            Ok(writers) => writers.clone(),
+            Err(_) => vec![],
+        };
+        let writers = writers
+            .iter()
+            .map(|w| {
+                let latencies = w.latencies();
+                WriterReport {
+                    index: w.index(),
+                    seed: w.seed(),
+                    step: w.current_step(),
+                    ops: w.error_stats().succeeds(),
+                    errors: errors_of(w.error_stats()),
+                    latencies: [
+                        ("put".to_owned(), (&latencies.put).into()),
+                        ("delete".to_owned(), (&latencies.delete).into()),
+                        ("get".to_owned(), (&latencies.get).into()),
+                    ]
+                    .into_iter()
+                    .collect(),
+                }
+            })
+            .collect();
+        let readers = self
+            .readers
+            .iter()
+            .map(|r| ReaderReport {
+                index: r.index(),
+                reads: r.error_stats().succeeds(),
+                errors: errors_of(r.error_stats()),
+                get_latency: (&r.get_latency()).into(),
+                staleness: (&r.staleness()).into(),
+            })
+            .collect();
+        RunReport {
+            base_seed: self.base_seed,
+            config_hash: self.config_hash.clone(),
+            started_at_us: self.started_at_us,
+            finished_at_us: now_us(),
+            passed: failure.is_none() && final_verify != VerifyOutcome::Failed,
+            final_verify,
+            failure,
+            writers,
+            readers,
+            faults: self.fault_log.windows(),
+        }
+    }
+
+    pub fn write(&self, report: &RunReport) -> Result<()> {
+        if let Some(path) = &self.path {
+            std::fs::write(path, serde_json::to_string_pretty(report)?)?;
+            info!("write run report to {}", path.display());
+        }
+        if let Some(path) = &self.junit_path {
+            report::write_junit(report, path)?;
+            info!("write junit report to {}", path.display());
+        }
+        Ok(())
+    }
+
+    /// Build and write the report, the errors are logged only since the run is finished.
+    pub fn finish(&self, final_verify: VerifyOutcome, failure: Option<String>) -> RunReport {
+        let report = self.build(final_verify, failure);
+        if let Err(e) = self.write(&report) {
+            error!("write run report: {}", e);
+        }
+        report
+    }
+}
+
+/// Write the report of the registered reporter with the panic as the failure.
+pub fn report_panic(msg: String) {
+    let reporter = match REPORTER.try_lock() {
+        Ok(reporter) => reporter.clone(),
+        Err(_) => None,
+    };
+    if let Some(reporter) = reporter {
+        let final_verify = if reporter.final_verify_started.load(Ordering::Acquire) {
+            VerifyOutcome::Failed
+        } else {
+            VerifyOutcome::NotRun
+        };
+        reporter.finish(final_verify, Some(msg));
+    }
+}
@@ -0,0 +1,211 @@
+use std::{collections::BTreeMap, path::PathBuf, process::Stdio, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    process::{Child, Command},
+    sync::Mutex,
+    time::Instant,
+};
+use tracing::{error, info};
+
+use crate::{chaos::Fault, net::host_of};
+
+/// The name of the toxics added by the supervisor.
+const TOXIC_NAME: &str = "engula_chaos";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ToxiproxyConfig {
+    /// The path of `toxiproxy-server`, it is spawned by the supervisor if specified, otherwise
+    /// the server at `api_addr` is used.
+    pub binary: Option<PathBuf>,
+    /// The address of the HTTP API.
+    pub api_addr: String,
+    /// The proxy of the i-th node listens on `listen_host:(base_port + i)`.
+    pub listen_host: String,
+    pub base_port: u16,
+}
+
+/// Route the client traffic to each node through a proxy of Toxiproxy, so faults could be
+/// injected where iptables and tc are unavailable. Only the traffic to the addresses given to
+/// the client is proxied, not the one between nodes.
+pub struct Toxiproxy {
+    cfg: ToxiproxyConfig,
+    /// The name and the upstream address of each proxy.
+    proxies: Vec<(String, String)>,
+    server: Mutex<Option<Child>>,
+}
+
+impl Toxiproxy {
+    pub fn new(cfg: ToxiproxyConfig, proxies: Vec<(String, String)>) -> Self {
+        Toxiproxy {
+            cfg,
+            proxies,
+            server: Mutex::default(),
+        }
+    }
+
+    pub fn proxy_names(&self) -> Vec<String> {
+        self.proxies.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// The addresses of the proxies, they replace the addresses of nodes given to the client.
+    pub fn listen_addrs(&self) -> Vec<String> {
+        (0..self.proxies.len())
+            .map(|i| self.listen_addr(i))
+            .collect()
+    }
+
+    fn listen_addr(&self, i: usize) -> String {
+        format!(
+            "{}:{}",
+            self.cfg.listen_host,
+            self.cfg.base_port as usize + i
+        )
+    }
+
+    /// Spawn the server if required, and create the proxies. The proxies left by a previous run
+    /// are replaced.
+    pub async fn start(&self) -> Result<()> {
+        if let Some(binary) = &self.cfg.binary {
+            let (host, port) = self
+                .cfg
+                .api_addr
+                .rsplit_once(':')
+                .unwrap_or((&self.cfg.api_addr, "8474"));
+            let child = Command::new(binary)
+                .args(["-host", host, "-port", port])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .kill_on_drop(true)
+                .spawn()?;
+            info!("toxiproxy server is started, pid {:?}", child.id());
+            *self.server.lock().await = Some(child);
+            self.wait_ready().await?;
+        }
+        for (i, (name, upstream)) in self.proxies.iter().enumerate() {
+            self.request("DELETE", &format!("/proxies/{}", name), None)
+                .await
+                .unwrap_or_default();
+            let body = json!({
+                "name": name,
+                "listen": self.listen_addr(i),
+                "upstream": upstream,
+                "enabled": true,
+            });
+            self.request("POST", "/proxies", Some(body)).await?;
+            info!(
+                "toxiproxy {} listens on {}, upstream {}",
+                name,
+                self.listen_addr(i),
+                upstream
+            );
+        }
+        Ok(())
+    }
+
+    async fn wait_ready(&self) -> Result<()> {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            match TcpStream::connect(&self.cfg.api_addr).await {
+                Ok(_) => return Ok(()),
+                Err(e) if Instant::now() >= deadline => {
+                    anyhow::bail!("toxiproxy is not ready at {}: {}", self.cfg.api_addr, e);
+                }
+                Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+            }
+        }
+    }
+
+    async fn add_toxic(
+        &self,
+        proxy: &str,
+        toxic: &str,
+        attributes: &BTreeMap<String, i64>,
+    ) -> Result<()> {
+        let body = json!({
+            "name": TOXIC_NAME,
+            "type": toxic,
+            "stream": "downstream",
+            "toxicity": 1.0,
+            "attributes": attributes,
+        });
+        self.request("POST", &format!("/proxies/{}/toxics", proxy), Some(body))
+            .await
+    }
+
+    async fn remove_toxic(&self, proxy: &str) -> Result<()> {
+        let path = format!("/proxies/{}/toxics/{}", proxy, TOXIC_NAME);
+        self.request("DELETE", &path, None).await
+    }
+
+    /// Send a request to the HTTP API, fail if the response is not 2xx.
+    async fn request(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<()> {
+        let body = body.map(|b| b.to_string()).unwrap_or_default();
+        let request = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            method,
+            path,
+            host_of(&self.cfg.api_addr),
+            body.len(),
+            body
+        );
+        let mut stream = TcpStream::connect(&self.cfg.api_addr).await?;
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = vec![];
+        stream.read_to_end(&mut response).await?;
+        let response = String::from_utf8_lossy(&response);
+        let status_line = response.lines().next().unwrap_or_default();
+        let status = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|s| s.parse::<u16>().ok())
+            .unwrap_or_default();
+        if !(200..300).contains(&status) {
+            anyhow::bail!("toxiproxy {} {}: {}", method, path, status_line);
+        }
+        Ok(())
+    }
+}
+
+/// Add a toxic to the proxies of the targets, eg `latency`, `bandwidth` or `reset_peer`.
+pub struct ToxicFault {
+    pub toxiproxy: Arc<Toxiproxy>,
+    pub toxic: String,
+    pub attributes: BTreeMap<String, i64>,
+}
+
+#[super::async_trait]
+impl Fault for ToxicFault {
+    fn name(&self) -> &'static str {
+        "toxic"
+    }
+
+    async fn inject(&self, targets: &[String]) -> Result<()> {
+        for target in targets {
+            self.toxiproxy
+                .add_toxic(target, &self.toxic, &self.attributes)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn recover(&self, targets: &[String]) -> Result<()> {
+        let mut result = Ok(());
+        for target in targets {
+            if let Err(e) = self.toxiproxy.remove_toxic(target).await {
+                error!("remove toxic of {}: {}", target, e);
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
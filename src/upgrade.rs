@@ -0,0 +1,141 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use tracing::info;
+
+use crate::{
+    base::{deserialize_duration, serialize_duration, ExecCtx, FailureKind},
+    chaos::FaultLog,
+    cluster::Cluster,
+    docker::Docker,
+};
+
+/// Restart the nodes one by one with a new version while the workload runs, eg
+/// `{ at = "5m", binary = "/opt/engula-next", settle = "30s", max_unavailable = "1m" }`.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct UpgradeConfig {
+    /// The time since the run starts.
+    #[serde(
+        serialize_with = "serialize_duration",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub at: Duration,
+    /// The new binary of the nodes of `cluster`.
+    pub binary: Option<PathBuf>,
+    /// The new image of the containers of `docker`.
+    pub image: Option<String>,
+    /// The duration to wait after a node is ready before the next one is restarted.
+    #[serde(
+        serialize_with = "serialize_duration",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub settle: Duration,
+    /// The run fails if a node is not ready within it after it is stopped.
+    #[serde(
+        serialize_with = "serialize_duration",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub max_unavailable: Duration,
+}
+
+enum Target {
+    Cluster(Arc<Cluster>, PathBuf),
+    Docker(Arc<Docker>, String),
+}
+
+/// Upgrade the nodes one by one, the next node is not restarted until the previous one accepts
+/// connections and the cluster settles. Each restart is recorded as a fault window, so the
+/// unavailability is tolerated by the readers, and the safety is verified as usual.
+pub struct RollingUpgrade {
+    cfg: UpgradeConfig,
+    target: Target,
+    nodes: Vec<String>,
+    log: Arc<FaultLog>,
+}
+
+impl RollingUpgrade {
+    pub fn new(
+        cfg: UpgradeConfig,
+        cluster: Option<Arc<Cluster>>,
+        docker: Option<Arc<Docker>>,
+        log: Arc<FaultLog>,
+    ) -> Result<Self> {
+        let (target, nodes) = match (cluster, docker, &cfg.binary, &cfg.image) {
+            (Some(cluster), _, Some(binary), _) => {
+                let nodes = cluster.node_names();
+                (Target::Cluster(cluster, binary.clone()), nodes)
+            }
+            (_, Some(docker), _, Some(image)) => {
+                let nodes = docker.node_names();
+                (Target::Docker(docker, image.clone()), nodes)
+            }
+            _ => anyhow::bail!(
+                "the upgrade requires the `cluster` config with `binary`, or the `docker` config with `image`"
+            ),
+        };
+        Ok(RollingUpgrade {
+            cfg,
+            target,
+            nodes,
+            log,
+        })
+    }
+
+    async fn upgrade_node(&self, name: &str) -> Result<()> {
+        match &self.target {
+            Target::Cluster(cluster, binary) => cluster.upgrade_node(name, binary.clone()).await,
+            Target::Docker(docker, image) => docker.upgrade_node(name, image).await,
+        }
+    }
+}
+
+#[super::async_trait]
+impl super::base::Task for RollingUpgrade {
+    async fn run(&self, mut ctx: ExecCtx) {
+        if ctx
+            .wait_until_timeout_or_shutdown(self.cfg.at)
+            .await
+            .is_none()
+        {
+            return;
+        }
+        info!("rolling upgrade of {} nodes begins", self.nodes.len());
+        for (i, name) in self.nodes.iter().enumerate() {
+            let targets = [name.clone()];
+            let id = self.log.begin("upgrade", &targets);
+            let start = Instant::now();
+            let result = self.upgrade_node(name).await;
+            self.log.end(id);
+            let unavailable = start.elapsed();
+            if let Err(e) = result {
+                FailureKind::Internal.mark_panic();
+                panic!("upgrade node {}: {}", name, e);
+            }
+            if unavailable > self.cfg.max_unavailable {
+                FailureKind::Availability.mark_panic();
+                panic!(
+                    "node {} is unavailable for {:?} during upgrade, exceeds {:?}",
+                    name, unavailable, self.cfg.max_unavailable
+                );
+            }
+            info!(
+                "node {} is upgraded, {} of {} nodes, unavailable for {:?}",
+                name,
+                i + 1,
+                self.nodes.len(),
+                unavailable
+            );
+            if ctx
+                .wait_until_timeout_or_shutdown(self.cfg.settle)
+                .await
+                .is_none()
+            {
+                info!("rolling upgrade is stopped after {} nodes", i + 1);
+                return;
+            }
+        }
+        info!("rolling upgrade is finished");
+    }
+}
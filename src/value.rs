@@ -1,6 +1,21 @@
+use anyhow::Result;
+
+use crate::history::now_us;
+
+/// The first byte of the encoded values, to tell them from the foreign data.
+const MAGIC: u8 = 0xec;
+/// The version of the format, it is bumped once the layout is changed. The fields are fixed
+/// width u64 in little endian since version 2, so the values are portable across platforms.
+const VERSION: u8 = 2;
+/// The end of the magic byte, the version and the crc32 of the rest.
+const CHECKSUM_END: usize = 6;
+const HEAD_LEN: usize = CHECKSUM_END + 3 * core::mem::size_of::<u64>();
+
 pub struct Value {
     writer: usize,
     index: usize,
+    /// The wall clock time in microseconds since unix epoch, when the value is created.
+    written_at_us: u64,
     inner: Vec<u8>,
 }
 
@@ -9,20 +24,90 @@ impl Value {
         Value {
             writer,
             index,
+            written_at_us: now_us(),
             inner,
         }
     }
 
+    /// Encode the value, the layout is the magic byte, the version, the crc32 of the rest, then
+    /// the writer, the index and the write time, and the inner value.
     pub fn encode(&self) -> Vec<u8> {
-        let cap = 2 * core::mem::size_of::<usize>() + self.inner.len();
-        let mut buf = Vec::with_capacity(cap);
-        buf.extend_from_slice(&self.writer.to_le_bytes());
-        buf.extend_from_slice(&self.index.to_le_bytes());
+        let mut buf = Vec::with_capacity(HEAD_LEN + self.inner.len());
+        buf.push(MAGIC);
+        buf.push(VERSION);
+        buf.extend_from_slice(&[0u8; 4]);
+        buf.extend_from_slice(&(self.writer as u64).to_le_bytes());
+        buf.extend_from_slice(&(self.index as u64).to_le_bytes());
+        buf.extend_from_slice(&self.written_at_us.to_le_bytes());
         buf.extend_from_slice(&self.inner);
+        let checksum = crc32fast::hash(&buf[CHECKSUM_END..]);
+        buf[2..CHECKSUM_END].copy_from_slice(&checksum.to_le_bytes());
         buf
     }
 
-    #[allow(unused)]
+    /// Decode the value, the foreign data, the values of other format versions and the
+    /// corrupted ones are rejected.
+    pub fn decode(value: &[u8]) -> Result<Self> {
+        if value.len() < HEAD_LEN {
+            anyhow::bail!(
+                "value of {} bytes is shorter than the header of {} bytes",
+                value.len(),
+                HEAD_LEN
+            );
+        }
+        if value[0] != MAGIC {
+            anyhow::bail!(
+                "value starts with {:#04x} instead of the magic {:#04x}, it is not written by the supervisor",
+                value[0],
+                MAGIC
+            );
+        }
+        if value[1] != VERSION {
+            anyhow::bail!(
+                "value is in format version {}, but only version {} is supported",
+                value[1],
+                VERSION
+            );
+        }
+        let mut crc = [0u8; 4];
+        crc.copy_from_slice(&value[2..CHECKSUM_END]);
+        let (expect, actual) = (
+            u32::from_le_bytes(crc),
+            crc32fast::hash(&value[CHECKSUM_END..]),
+        );
+        if expect != actual {
+            anyhow::bail!(
+                "value of {} bytes is corrupted, its checksum is {:08x} but the content is {:08x}",
+                value.len(),
+                expect,
+                actual
+            );
+        }
+
+        let field = |n: usize| {
+            let len = core::mem::size_of::<u64>();
+            let offset = CHECKSUM_END + n * len;
+            let mut buf = [0u8; core::mem::size_of::<u64>()];
+            buf.copy_from_slice(&value[offset..offset + len]);
+            u64::from_le_bytes(buf)
+        };
+        let usize_field = |n: usize, name: &str| {
+            usize::try_from(field(n)).map_err(|_| {
+                anyhow::anyhow!(
+                    "value has the {} {} which overflows usize of this platform",
+                    name,
+                    field(n)
+                )
+            })
+        };
+        Ok(Value {
+            writer: usize_field(0, "writer")?,
+            index: usize_field(1, "index")?,
+            written_at_us: field(2),
+            inner: value[HEAD_LEN..].to_owned(),
+        })
+    }
+
     #[inline]
     pub fn writer(&self) -> usize {
         self.writer
@@ -33,30 +118,87 @@ impl Value {
         self.index
     }
 
+    #[inline]
+    pub fn written_at_us(&self) -> u64 {
+        self.written_at_us
+    }
+
     #[inline]
     pub fn value(&self) -> Vec<u8> {
         self.inner.clone()
     }
 }
 
+/// Decode the value read from the cluster, a value which could not be decoded fails the run,
+/// see [`Value::decode`].
 impl From<&[u8]> for Value {
     fn from(value: &[u8]) -> Self {
-        let head = 2 * core::mem::size_of::<usize>();
-        if value.len() <= head {
-            panic!("value len {} is too small", value.len());
-        }
-
-        let mut buf = [0u8; core::mem::size_of::<usize>()];
-        buf.as_mut_slice()
-            .copy_from_slice(&value[..core::mem::size_of::<usize>()]);
-        let writer = usize::from_le_bytes(buf);
-        buf.as_mut_slice()
-            .copy_from_slice(&value[core::mem::size_of::<usize>()..head]);
-        let index = usize::from_le_bytes(buf);
-        Value {
-            writer,
-            index,
-            inner: value[head..].to_owned(),
+        match Value::decode(value) {
+            Ok(value) => value,
+            Err(e) => panic!("decode value: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded() -> Vec<u8> {
+        Value::new(3, 42, b"hello".to_vec()).encode()
+    }
+
+    fn decode_err(value: &[u8]) -> String {
+        match Value::decode(value) {
+            Ok(_) => panic!("decode {:?} should fail", value),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let value = Value::new(3, 42, b"hello".to_vec());
+        let decoded = Value::decode(&value.encode()).unwrap();
+        assert_eq!(decoded.writer(), 3);
+        assert_eq!(decoded.index(), 42);
+        assert_eq!(decoded.written_at_us(), value.written_at_us());
+        assert_eq!(decoded.value(), b"hello");
+
+        let empty = Value::decode(&Value::new(0, 0, vec![]).encode()).unwrap();
+        assert!(empty.value().is_empty());
+    }
+
+    #[test]
+    fn flipped_byte() {
+        for i in CHECKSUM_END..encoded().len() {
+            let mut buf = encoded();
+            buf[i] ^= 0x01;
+            assert!(decode_err(&buf).contains("corrupted"));
+        }
+        let mut buf = encoded();
+        buf[2] ^= 0x01;
+        assert!(decode_err(&buf).contains("corrupted"));
+    }
+
+    #[test]
+    fn wrong_magic() {
+        let mut buf = encoded();
+        buf[0] = b'x';
+        assert!(decode_err(&buf).contains("magic"));
+    }
+
+    #[test]
+    fn wrong_version() {
+        let mut buf = encoded();
+        buf[1] = VERSION + 1;
+        assert!(decode_err(&buf).contains("version"));
+    }
+
+    #[test]
+    fn short_buffer() {
+        let buf = encoded();
+        for len in [0, 1, CHECKSUM_END, HEAD_LEN - 1] {
+            assert!(decode_err(&buf[..len]).contains("shorter"));
         }
     }
 }
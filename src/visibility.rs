@@ -0,0 +1,120 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use tokio::{
+    sync::{broadcast, Mutex},
+    time::Instant,
+};
+use tracing::warn;
+
+use crate::{
+    base::ExecCtx,
+    histogram::{Histogram, HistogramSnapshot},
+    history::{now_us, History, OpEvent, OpKind, OpOutcome},
+};
+
+/// The versions not observed in this duration since they are acknowledged are dropped, eg they
+/// are overwritten before any reader reaches them.
+const HORIZON: Duration = Duration::from_secs(600);
+
+/// The state of a version, with the wall clock time in microseconds it enters the state.
+enum Version {
+    Acked(u64),
+    /// Observed by a reader before the put is acknowledged to the writer.
+    Observed(u64),
+    Measured(u64),
+}
+
+/// Measure the read-after-write visibility latency, the time between a put is acknowledged to
+/// the writer and the version `(writer, step)` is first observed by any reader. The events are
+/// received from the history.
+pub struct VisibilityMeter {
+    receiver: Mutex<broadcast::Receiver<Arc<OpEvent>>>,
+    latency: Histogram,
+}
+
+impl VisibilityMeter {
+    pub fn new(history: &History) -> Self {
+        VisibilityMeter {
+            receiver: Mutex::new(history.subscribe()),
+            latency: Histogram::default(),
+        }
+    }
+
+    pub fn latency(&self) -> HistogramSnapshot {
+        self.latency.snapshot()
+    }
+
+    fn handle(&self, versions: &mut HashMap<(usize, usize), Version>, event: &OpEvent) {
+        if !matches!(event.outcome, OpOutcome::Ok) {
+            return;
+        }
+        let at_us = event.completed_at_us;
+        match event.kind {
+            OpKind::Put => {
+                let key = (event.writer, event.step);
+                match versions.get(&key) {
+                    Some(Version::Observed(_)) => {
+                        // The put is visible before the writer knows it.
+                        self.latency.record(Duration::ZERO);
+                        versions.insert(key, Version::Measured(at_us));
+                    }
+                    _ => {
+                        versions.insert(key, Version::Acked(at_us));
+                    }
+                }
+            }
+            OpKind::Get => {
+                let key = match event.read_step {
+                    Some(step) => (event.writer, step),
+                    None => return,
+                };
+                match versions.get(&key) {
+                    Some(Version::Acked(acked_at_us)) => {
+                        let latency = at_us.saturating_sub(*acked_at_us);
+                        self.latency.record(Duration::from_micros(latency));
+                        versions.insert(key, Version::Measured(at_us));
+                    }
+                    Some(_) => {}
+                    None => {
+                        versions.insert(key, Version::Observed(at_us));
+                    }
+                }
+            }
+            OpKind::Delete => {}
+        }
+    }
+}
+
+#[super::async_trait]
+impl super::base::Task for VisibilityMeter {
+    async fn run(&self, mut ctx: ExecCtx) {
+        let mut versions = HashMap::new();
+        let mut receiver = self.receiver.lock().await;
+        let mut last_pruned = Instant::now();
+        loop {
+            tokio::select! {
+                event = receiver.recv() => match event {
+                    Ok(event) => self.handle(&mut versions, &event),
+                    Err(broadcast::error::RecvError::Lagged(num)) => {
+                        warn!("visibility meter lost {} events", num);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                result = ctx.wait_until_timeout_or_shutdown(Duration::from_secs(1)) => {
+                    if result.is_none() {
+                        break;
+                    }
+                }
+            }
+            if last_pruned.elapsed() >= HORIZON {
+                let horizon = now_us().saturating_sub(HORIZON.as_micros() as u64);
+                versions.retain(|_, v| match v {
+                    Version::Acked(at_us) | Version::Observed(at_us) | Version::Measured(at_us) => {
+                        *at_us >= horizon
+                    }
+                });
+                last_pruned = Instant::now();
+            }
+        }
+    }
+}
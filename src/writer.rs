@@ -8,11 +8,15 @@ use std::{
 
 use anyhow::Result;
 use engula_client::Collection;
-use tracing::debug;
+use rand::{prelude::SmallRng, Rng, SeedableRng};
+use tokio::time::Instant;
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
 use crate::{
-    base::{Config, ExecCtx},
-    gen::{Generator, NextOp},
+    base::{with_timeout, Config, ErrorStats, ExecCtx, FailureKind, VerifyProgress, WriterOptions},
+    gen::{Generator, NextOp, Snapshot},
+    histogram::{Histogram, OpLatencies},
+    history::{now_us, OpEvent, OpOutcome},
     value::Value,
 };
 
@@ -23,6 +27,15 @@ where
     index: usize,
     step: AtomicUsize,
     collection: Collection,
+    /// The collection which records an index entry for each key of `collection`, see
+    /// [`Writer::execute`] for the order of updates.
+    index_collection: Option<Collection>,
+    opts: WriterOptions,
+    error_stats: ErrorStats,
+    put_latency: Histogram,
+    delete_latency: Histogram,
+    get_latency: Histogram,
+    verify_progress: VerifyProgress,
     core: Mutex<CoreWriter>,
 }
 
@@ -31,27 +44,62 @@ where
     Self: Send,
 {
     gen: Generator,
+    /// Used to sample the puts to read back and the think time, it is independent of the
+    /// generator so the sequence of ops is not affected.
+    sampler: SmallRng,
 }
 
 impl Writer {
-    pub fn new(index: usize, seed: u64, config: Config, collection: Collection) -> Self {
+    pub fn new(
+        index: usize,
+        seed: u64,
+        config: Config,
+        collection: Collection,
+        index_collection: Option<Collection>,
+        opts: WriterOptions,
+    ) -> Self {
         Writer {
             index,
             step: AtomicUsize::new(0),
             collection,
+            index_collection,
+            opts,
+            error_stats: ErrorStats::default(),
+            put_latency: Histogram::default(),
+            delete_latency: Histogram::default(),
+            get_latency: Histogram::default(),
+            verify_progress: VerifyProgress::default(),
             core: Mutex::new(CoreWriter {
                 gen: Generator::new(seed, index as u64, config),
+                sampler: SmallRng::seed_from_u64(!seed),
             }),
         }
     }
 
-    fn next_op(&self) -> NextOp {
+    /// Restore the writer from a snapshot taken by a previous run. The op of the snapshot step is
+    /// not guaranteed to be acknowledged, so it will be executed again.
+    pub fn restore(&self, snapshot: &Snapshot) {
+        let mut core = self.core.lock().unwrap();
+        assert_eq!(core.gen.seed(), snapshot.seed);
+        let snapshot = Snapshot {
+            num_ops: snapshot.num_ops.saturating_sub(1),
+            ..snapshot.clone()
+        };
+        core.gen = Generator::restore(&snapshot, core.gen.config());
+        self.step.store(snapshot.num_ops, Ordering::Release);
+    }
+
+    /// Return the next op, and whether it should be sent again after it is acknowledged.
+    fn next_op(&self) -> (NextOp, bool) {
         let mut core = self.core.lock().unwrap();
         self.step.fetch_add(1, Ordering::AcqRel);
-        core.gen.next_op()
+        let op = core.gen.next_op();
+        (op, core.gen.is_duplicated())
     }
 
-    async fn execute(&self, op: &NextOp) -> Result<()> {
+    /// Execute the op. If the index collection is configured, the data is put before its index
+    /// entry and deleted after it, so a visible index entry always points to a visible data.
+    async fn execute(&self, op: &NextOp, duplicated: bool) -> Result<()> {
         let step = self.step.load(Ordering::Relaxed);
         match op {
             NextOp::Delete { key } => {
@@ -61,7 +109,21 @@ impl Writer {
                     step,
                     String::from_utf8_lossy(key.as_slice()),
                 );
-                self.collection.delete(key.clone()).await?;
+                if let Some(index_collection) = &self.index_collection {
+                    with_timeout(
+                        self.opts.effective_op_timeout(),
+                        index_collection.delete(key.clone()),
+                    )
+                    .await?;
+                }
+                with_timeout(
+                    self.opts.effective_op_timeout(),
+                    self.collection.delete(key.clone()),
+                )
+                .await?;
+                if duplicated {
+                    self.delete_again(key).await?;
+                }
             }
             NextOp::Put { key, value } => {
                 debug!(
@@ -71,8 +133,188 @@ impl Writer {
                     String::from_utf8_lossy(key.as_slice()),
                     String::from_utf8_lossy(value.as_slice()),
                 );
-                let v = Value::new(self.index, step, value.clone());
-                self.collection.put(key.clone(), v.encode()).await?;
+                let data = Value::new(self.index, step, value.clone()).encode();
+                with_timeout(
+                    self.opts.effective_op_timeout(),
+                    self.collection.put(key.clone(), data.clone()),
+                )
+                .await?;
+                if self.should_read_back() {
+                    self.read_back(step, key, value).await?;
+                }
+                let mut entry = None;
+                if let Some(index_collection) = &self.index_collection {
+                    let v = Value::new(self.index, step, key.clone()).encode();
+                    with_timeout(
+                        self.opts.effective_op_timeout(),
+                        index_collection.put(key.clone(), v.clone()),
+                    )
+                    .await?;
+                    entry = Some(v);
+                }
+                if duplicated {
+                    self.put_again(key, data, entry).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Send the acknowledged delete again, it should be idempotent.
+    async fn delete_again(&self, key: &[u8]) -> Result<()> {
+        let step = self.step.load(Ordering::Relaxed);
+        debug!("writer {} index {} send delete again", self.index, step);
+        if let Some(index_collection) = &self.index_collection {
+            with_timeout(
+                self.opts.effective_op_timeout(),
+                index_collection.delete(key.to_owned()),
+            )
+            .await?;
+        }
+        with_timeout(
+            self.opts.effective_op_timeout(),
+            self.collection.delete(key.to_owned()),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Send the acknowledged put again with exactly the same bytes of the data and the index
+    /// entry, it should be idempotent.
+    async fn put_again(&self, key: &[u8], data: Vec<u8>, entry: Option<Vec<u8>>) -> Result<()> {
+        let step = self.step.load(Ordering::Relaxed);
+        debug!("writer {} index {} send put again", self.index, step);
+        with_timeout(
+            self.opts.effective_op_timeout(),
+            self.collection.put(key.to_owned(), data),
+        )
+        .await?;
+        if let (Some(index_collection), Some(entry)) = (&self.index_collection, entry) {
+            with_timeout(
+                self.opts.effective_op_timeout(),
+                index_collection.put(key.to_owned(), entry),
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
+    fn publish(&self, step: usize, op: &NextOp, result: &Result<()>, invoked_at_us: u64) {
+        let outcome = OpOutcome::from_result(result);
+        let event = OpEvent::new(self.index, step, op, outcome, invoked_at_us);
+        self.opts.history.publish(event);
+    }
+
+    /// Wait until the slowest reader catches up, return false if it is shutdown.
+    async fn wait_for_readers(&self, ctx: &mut ExecCtx) -> bool {
+        let max_lag = match self.opts.max_verify_lag_steps {
+            Some(max_lag) => max_lag,
+            None => return true,
+        };
+        let mut throttled = false;
+        loop {
+            let step = self.step.load(Ordering::Acquire);
+            let lag = self.verify_progress.lag(step).unwrap_or_default();
+            if lag <= max_lag {
+                if throttled {
+                    info!("writer {} is not throttled at step {}", self.index, step);
+                }
+                return true;
+            }
+            if !throttled {
+                warn!(
+                    "writer {} is throttled at step {}, the readers lag {} steps behind",
+                    self.index, step, lag
+                );
+                throttled = true;
+            }
+            if ctx
+                .wait_until_timeout_or_shutdown(Duration::from_millis(100))
+                .await
+                .is_none()
+            {
+                return false;
+            }
+        }
+    }
+
+    /// Wait until the writers are resumed, return false if it is shutdown.
+    async fn wait_while_paused(&self, ctx: &mut ExecCtx) -> bool {
+        while self.opts.paused.load(Ordering::Acquire) {
+            if ctx
+                .wait_until_timeout_or_shutdown(Duration::from_millis(100))
+                .await
+                .is_none()
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn reaches_max_steps(&self) -> bool {
+        match self.opts.max_steps {
+            Some(max_steps) => self.step.load(Ordering::Acquire) >= max_steps,
+            None => false,
+        }
+    }
+
+    fn think_time(&self) -> Option<Duration> {
+        let think_time = self.opts.tunables.get().think_time?;
+        let mut core = self.core.lock().unwrap();
+        Some(think_time.sample(&mut core.sampler))
+    }
+
+    fn should_read_back(&self) -> bool {
+        let ratio = self.opts.tunables.get().read_your_writes_ratio;
+        if ratio <= 0.0 {
+            return false;
+        }
+        let mut core = self.core.lock().unwrap();
+        core.sampler.gen_bool(ratio.min(1.0))
+    }
+
+    /// Read the key back and make sure the acknowledged put is visible to the writer itself.
+    async fn read_back(&self, step: usize, key: &[u8], value: &[u8]) -> Result<()> {
+        let start = Instant::now();
+        let got = with_timeout(
+            self.opts.effective_op_timeout(),
+            self.collection.get(key.to_owned()),
+        )
+        .await?;
+        if Instant::now() >= self.opts.warmup_deadline {
+            self.get_latency.record(start.elapsed());
+        }
+        if matches!(self.opts.slow_op_threshold, Some(threshold) if start.elapsed() > threshold) {
+            warn!(
+                "writer {} slow read back of key {} at step {}, elapsed {:?}",
+                self.index,
+                String::from_utf8_lossy(key),
+                step,
+                start.elapsed(),
+            );
+        }
+        match got {
+            Some(got_value) => {
+                let v = Value::from(got_value.as_slice());
+                if v.writer() != self.index || v.index() != step || v.value() != value {
+                    panic!(
+                        "writer {} read back key {} put at step {}, but got value of writer {} step {}",
+                        self.index,
+                        String::from_utf8_lossy(key),
+                        step,
+                        v.writer(),
+                        v.index(),
+                    );
+                }
+            }
+            None => {
+                panic!(
+                    "writer {} read back key {} put at step {}, but it is not found",
+                    self.index,
+                    String::from_utf8_lossy(key),
+                    step,
+                );
             }
         }
         Ok(())
@@ -81,20 +323,109 @@ impl Writer {
 
 #[super::async_trait]
 impl super::base::Task for Writer {
-    async fn run(&self, _ctx: ExecCtx) {
-        'OUTER: loop {
-            let op = self.next_op();
-            for _ in 0..120 {
-                match self.execute(&op).await {
-                    Ok(()) => continue 'OUTER,
+    async fn run(&self, mut ctx: ExecCtx) {
+        let mut acknowledged = true;
+        'OUTER: while !ctx.is_shutdown() {
+            if !self.wait_while_paused(&mut ctx).await || !self.wait_for_readers(&mut ctx).await {
+                break;
+            }
+            if self.reaches_max_steps() {
+                info!(
+                    "writer {} reaches the max steps, wait for shutdown",
+                    self.index
+                );
+                while ctx
+                    .wait_until_timeout_or_shutdown(Duration::from_secs(1))
+                    .await
+                    .is_some()
+                {}
+                break;
+            }
+            let (op, duplicated) = self.next_op();
+            acknowledged = false;
+            let step = self.step.load(Ordering::Acquire);
+            let mut attempts = 0;
+            while attempts < 120 {
+                let start = Instant::now();
+                let invoked_at_us = now_us();
+                let span = info_span!(
+                    "op",
+                    writer = self.index,
+                    step,
+                    attempt = attempts,
+                    op = op.name(),
+                    key = %String::from_utf8_lossy(op.key()),
+                );
+                let result = self.execute(&op, duplicated).instrument(span).await;
+                self.publish(step, &op, &result, invoked_at_us);
+                if matches!(self.opts.slow_op_threshold, Some(threshold) if start.elapsed() > threshold)
+                {
+                    warn!(
+                        "writer {} slow {} of key {} at step {}, attempt {}, elapsed {:?}, ok: {}",
+                        self.index,
+                        op.name(),
+                        String::from_utf8_lossy(op.key()),
+                        step,
+                        attempts,
+                        start.elapsed(),
+                        result.is_ok(),
+                    );
+                }
+                match result {
+                    Ok(()) => {
+                        let histogram = match &op {
+                            NextOp::Put { .. } => &self.put_latency,
+                            NextOp::Delete { .. } => &self.delete_latency,
+                        };
+                        if Instant::now() >= self.opts.warmup_deadline {
+                            histogram.record(start.elapsed());
+                        }
+                        self.error_stats.record_success();
+                        acknowledged = true;
+                        if let Some(think_time) = self.think_time() {
+                            if ctx
+                                .wait_until_timeout_or_shutdown(think_time)
+                                .await
+                                .is_none()
+                            {
+                                break 'OUTER;
+                            }
+                        }
+                        continue 'OUTER;
+                    }
                     Err(e) => {
-                        tracing::error!("{}", e);
-                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        // The failures expected by the active faults are not counted.
+                        if !self.opts.relaxation.tolerate_write_failures() {
+                            attempts += 1;
+                        }
+                        let class = self.error_stats.record(&e);
+                        error!(
+                            error_class = class.as_str(),
+                            "writer {} execute op: {}, total {} timeouts and {} errors",
+                            self.index,
+                            e,
+                            self.error_stats.timeouts(),
+                            self.error_stats.errors(),
+                        );
+                        if ctx
+                            .wait_until_timeout_or_shutdown(Duration::from_secs(1))
+                            .await
+                            .is_none()
+                        {
+                            break 'OUTER;
+                        }
                     }
                 }
             }
-            panic!("could not execute op after 120 secs");
+            FailureKind::Availability.mark_panic();
+            panic!("could not execute op after 120 attempts");
         }
+        info!(
+            "writer {} is shutdown at step {}, the op of the last step is acknowledged: {}",
+            self.index,
+            self.step.load(Ordering::Acquire),
+            acknowledged,
+        );
     }
 }
 
@@ -104,6 +435,10 @@ impl super::base::Writer for Writer {
         self.index
     }
 
+    fn error_stats(&self) -> &ErrorStats {
+        &self.error_stats
+    }
+
     fn current_step(&self) -> usize {
         self.step.load(Ordering::Acquire)
     }
@@ -117,4 +452,25 @@ impl super::base::Writer for Writer {
         let core = self.core.lock().unwrap();
         core.gen.config()
     }
+
+    fn snapshot(&self) -> Snapshot {
+        let core = self.core.lock().unwrap();
+        core.gen.snapshot()
+    }
+
+    fn latencies(&self) -> OpLatencies {
+        OpLatencies {
+            put: self.put_latency.snapshot(),
+            delete: self.delete_latency.snapshot(),
+            get: self.get_latency.snapshot(),
+        }
+    }
+
+    fn verify_progress(&self) -> &VerifyProgress {
+        &self.verify_progress
+    }
+
+    fn collections(&self) -> (Collection, Option<Collection>) {
+        (self.collection.clone(), self.index_collection.clone())
+    }
 }